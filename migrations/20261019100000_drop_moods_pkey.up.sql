@@ -0,0 +1 @@
+ALTER TABLE moods DROP CONSTRAINT moods_pkey;
\ No newline at end of file
@@ -0,0 +1 @@
+CREATE INDEX moods_username_idx ON moods (username, created_at);
\ No newline at end of file
@@ -0,0 +1,19 @@
+ALTER TABLE api_tokens DROP CONSTRAINT api_tokens_pkey;
+
+ALTER TABLE api_tokens ADD COLUMN token_prefix TEXT;
+
+ALTER TABLE api_tokens ADD COLUMN token_hash TEXT;
+
+UPDATE api_tokens SET
+    token_prefix = 'legacy-' || encode(sha256(convert_to(token, 'UTF8')), 'hex'),
+    token_hash = encode(sha256(convert_to(token, 'UTF8')), 'hex');
+
+ALTER TABLE api_tokens ALTER COLUMN token_prefix SET NOT NULL;
+
+ALTER TABLE api_tokens ALTER COLUMN token_hash SET NOT NULL;
+
+ALTER TABLE api_tokens ADD PRIMARY KEY (token_prefix);
+
+ALTER TABLE api_tokens DROP COLUMN token;
+
+CREATE INDEX api_tokens_username_idx ON api_tokens (username);
\ No newline at end of file
@@ -0,0 +1,7 @@
+ALTER TABLE api_tokens ADD COLUMN expires_at TEXT;
+
+ALTER TABLE api_tokens ADD COLUMN last_used_at TEXT;
+
+ALTER TABLE api_tokens ADD COLUMN last_used_ip TEXT;
+
+ALTER TABLE api_tokens ADD COLUMN revoked_at TEXT;
\ No newline at end of file
@@ -0,0 +1,19 @@
+ALTER TABLE api_tokens ADD COLUMN scopes TEXT[] NOT NULL DEFAULT '{}';
+
+UPDATE api_tokens SET scopes = array_remove(
+    ARRAY[
+        CASE WHEN can_set_mood THEN 'mood:write' END,
+        CASE WHEN can_change_pwd THEN 'account:password' END,
+        CASE WHEN can_change_email THEN 'account:email' END,
+        CASE WHEN can_delete_user THEN 'account:delete' END
+    ],
+    NULL
+);
+
+ALTER TABLE api_tokens DROP COLUMN can_change_pwd;
+
+ALTER TABLE api_tokens DROP COLUMN can_set_mood;
+
+ALTER TABLE api_tokens DROP COLUMN can_delete_user;
+
+ALTER TABLE api_tokens DROP COLUMN can_change_email;
\ No newline at end of file
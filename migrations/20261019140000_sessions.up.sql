@@ -0,0 +1,10 @@
+CREATE TABLE sessions (
+    session_hash TEXT NOT NULL PRIMARY KEY,
+    username TEXT NOT NULL,
+    csrf_token TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    FOREIGN KEY (username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX sessions_username_idx ON sessions (username);
\ No newline at end of file
@@ -0,0 +1,35 @@
+CREATE TABLE oauth_clients (
+    client_id TEXT NOT NULL PRIMARY KEY,
+    client_secret_hash TEXT NOT NULL,
+    client_name TEXT NOT NULL,
+    redirect_uris TEXT[] NOT NULL,
+    scopes TEXT[] NOT NULL,
+    website TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE oauth_codes (
+    code_hash TEXT NOT NULL PRIMARY KEY,
+    client_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    redirect_uri TEXT NOT NULL,
+    scopes TEXT[] NOT NULL,
+    code_challenge TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    FOREIGN KEY (client_id) REFERENCES oauth_clients(client_id) ON DELETE CASCADE,
+    FOREIGN KEY (username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE TABLE oauth_refresh_tokens (
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    client_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    token_prefix TEXT NOT NULL,
+    scopes TEXT[] NOT NULL,
+    created_at TEXT NOT NULL,
+    is_active BOOLEAN NOT NULL,
+    FOREIGN KEY (client_id) REFERENCES oauth_clients(client_id) ON DELETE CASCADE,
+    FOREIGN KEY (username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+ALTER TABLE api_tokens ADD COLUMN client_id TEXT REFERENCES oauth_clients(client_id) ON DELETE CASCADE;
\ No newline at end of file
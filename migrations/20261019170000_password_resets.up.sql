@@ -0,0 +1,10 @@
+CREATE TABLE password_resets (
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    username TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    used_at TEXT,
+    FOREIGN KEY (username) REFERENCES users(username) ON DELETE CASCADE
+);
+
+CREATE INDEX password_resets_username_idx ON password_resets (username);
\ No newline at end of file
@@ -0,0 +1,8 @@
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM users WHERE pwd !~ '^\$2[aby]\$\d\d\$.{53}$' AND pwd NOT LIKE '$argon2%') THEN
+        CREATE EXTENSION IF NOT EXISTS pgcrypto;
+        UPDATE users SET pwd = crypt(pwd, gen_salt('bf')) WHERE pwd !~ '^\$2[aby]\$\d\d\$.{53}$' AND pwd NOT LIKE '$argon2%';
+    END IF;
+END
+$$;
\ No newline at end of file
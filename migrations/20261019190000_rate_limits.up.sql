@@ -0,0 +1,6 @@
+CREATE TABLE rate_limits (
+    key TEXT NOT NULL PRIMARY KEY,
+    count INTEGER NOT NULL,
+    window_start BIGINT NOT NULL,
+    locked_until BIGINT
+);
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN email_token_expires_at TEXT;
+ALTER TABLE users ADD COLUMN verification_resend_after TEXT;
\ No newline at end of file
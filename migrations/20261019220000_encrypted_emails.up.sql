@@ -0,0 +1,4 @@
+ALTER TABLE users ADD COLUMN email_lookup TEXT;
+CREATE UNIQUE INDEX users_email_lookup_idx ON users (email_lookup);
+ALTER TABLE email_changes ADD COLUMN old_email_lookup TEXT;
+ALTER TABLE email_changes ADD COLUMN new_email_lookup TEXT;
\ No newline at end of file
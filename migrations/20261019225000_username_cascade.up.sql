@@ -0,0 +1,9 @@
+ALTER TABLE moods DROP CONSTRAINT moods_username_fkey, ADD CONSTRAINT moods_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE api_tokens DROP CONSTRAINT api_tokens_username_fkey, ADD CONSTRAINT api_tokens_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE user_files DROP CONSTRAINT user_files_username_fkey, ADD CONSTRAINT user_files_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE sessions DROP CONSTRAINT sessions_username_fkey, ADD CONSTRAINT sessions_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE oauth_codes DROP CONSTRAINT oauth_codes_username_fkey, ADD CONSTRAINT oauth_codes_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE oauth_refresh_tokens DROP CONSTRAINT oauth_refresh_tokens_username_fkey, ADD CONSTRAINT oauth_refresh_tokens_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE recovery_codes DROP CONSTRAINT recovery_codes_username_fkey, ADD CONSTRAINT recovery_codes_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE password_resets DROP CONSTRAINT password_resets_username_fkey, ADD CONSTRAINT password_resets_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE email_changes DROP CONSTRAINT email_changes_username_fkey, ADD CONSTRAINT email_changes_username_fkey FOREIGN KEY (username) REFERENCES users(username) ON UPDATE CASCADE ON DELETE CASCADE;
\ No newline at end of file
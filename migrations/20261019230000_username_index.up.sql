@@ -0,0 +1,15 @@
+DO $$
+DECLARE
+    collisions TEXT;
+BEGIN
+    SELECT string_agg(names, '; ') INTO collisions FROM (
+        SELECT string_agg(username, ', ' ORDER BY username) AS names FROM users GROUP BY LOWER(username) HAVING COUNT(*) > 1
+    ) AS groups;
+    IF collisions IS NOT NULL THEN
+        RAISE EXCEPTION 'These usernames only differ in case: %', collisions
+            USING HINT = 'Rename all but one account of each group, for example with UPDATE users SET username = ''new-name'' WHERE username = ''Old-Name''; and run the migrations again.';
+    END IF;
+END
+$$;
+
+CREATE UNIQUE INDEX users_username_lower_idx ON users (LOWER(username));
\ No newline at end of file
@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN purge_at TEXT;
+ALTER TABLE users ADD COLUMN deletion_undo_hash TEXT UNIQUE;
\ No newline at end of file
@@ -0,0 +1,5 @@
+UPDATE api_tokens SET scopes = array_append(scopes, 'account:2fa')
+WHERE 'account:password' = ANY(scopes) AND NOT 'account:2fa' = ANY(scopes);
+
+UPDATE oauth_refresh_tokens SET scopes = array_append(scopes, 'account:2fa')
+WHERE 'account:password' = ANY(scopes) AND NOT 'account:2fa' = ANY(scopes);
\ No newline at end of file
@@ -0,0 +1,3 @@
+ALTER TABLE oauth_clients ADD COLUMN is_confidential BOOLEAN NOT NULL DEFAULT TRUE;
+
+ALTER TABLE oauth_clients ALTER COLUMN client_secret_hash DROP NOT NULL;
\ No newline at end of file
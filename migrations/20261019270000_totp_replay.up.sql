@@ -0,0 +1 @@
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
\ No newline at end of file
@@ -0,0 +1 @@
+ALTER TABLE rate_limits ADD COLUMN window_secs BIGINT NOT NULL DEFAULT 0;
\ No newline at end of file
@@ -0,0 +1 @@
+UPDATE user_files SET file_id = translate(file_id, '/', '-') WHERE file_id LIKE '%/%';
\ No newline at end of file
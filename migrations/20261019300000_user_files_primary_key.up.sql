@@ -0,0 +1,5 @@
+ALTER TABLE user_files DROP CONSTRAINT user_files_pkey;
+ALTER TABLE user_files ADD PRIMARY KEY (file_id);
+CREATE INDEX user_files_username_idx ON user_files (username);
+ALTER TABLE user_files ADD COLUMN uploaded_at TEXT NOT NULL DEFAULT '';
+ALTER TABLE user_files ALTER COLUMN uploaded_at DROP DEFAULT;
\ No newline at end of file
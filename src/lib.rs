@@ -3,6 +3,22 @@ Jade by Alyx Shang.
 Licensed under the FSL v1.
 */
 
+// Jade spells out error handling with
+// explicit matches, names the result of
+// every unit operation, and takes owned
+// string types by reference. These lints
+// flag exactly that style, so they are
+// allowed for the whole crate.
+#![allow(
+    clippy::question_mark,
+    clippy::let_unit_value,
+    clippy::ptr_arg,
+    clippy::redundant_field_names,
+    clippy::needless_return,
+    clippy::needless_else,
+    clippy::too_many_arguments
+)]
+
 /// Declaring the "modules"
 /// directory as a module.
 pub mod modules;
@@ -53,4 +69,84 @@ pub use modules::email::*;
 /// Re-exporting the module
 /// containing this app's
 /// app runner function.
-pub use modules::runner::*;
\ No newline at end of file
+pub use modules::runner::*;
+
+/// Re-exporting the module
+/// containing functions for
+/// publishing and streaming
+/// mood updates.
+pub use modules::events::*;
+
+/// Re-exporting the module
+/// containing this app's
+/// websocket API.
+pub use modules::socket::*;
+
+/// Re-exporting the module
+/// containing functions for
+/// rendering feeds and pages.
+pub use modules::render::*;
+
+/// Re-exporting the module
+/// containing this app's
+/// extractor for authenticating
+/// requests.
+pub use modules::auth::*;
+
+/// Re-exporting the module
+/// containing functions for
+/// generating and hashing
+/// API tokens.
+pub use modules::tokens::*;
+
+/// Re-exporting the module
+/// containing the scopes
+/// API tokens can be granted.
+pub use modules::scopes::*;
+
+/// Re-exporting the module
+/// containing functions for
+/// browser sessions.
+pub use modules::sessions::*;
+
+/// Re-exporting the module
+/// containing functions for
+/// this app's OAuth
+/// authorization server.
+pub use modules::oauth::*;
+
+/// Re-exporting the module
+/// containing functions for
+/// two-factor authentication.
+pub use modules::totp::*;
+
+/// Re-exporting the module
+/// containing functions for
+/// hashing and checking
+/// passwords.
+pub use modules::password::*;
+
+/// Re-exporting the module
+/// containing this app's
+/// rate limits.
+pub use modules::ratelimit::*;
+
+/// Re-exporting the module
+/// containing the functions for
+/// encrypting email addresses.
+pub use modules::crypto::*;
+
+/// Re-exporting the module
+/// containing the rules usernames
+/// and email addresses must follow.
+pub use modules::validation::*;
+
+/// Re-exporting the module
+/// containing the functions for
+/// exporting a user's data.
+pub use modules::export::*;
+
+/// Re-exporting the module
+/// containing the functions for
+/// importing a user's data.
+pub use modules::import::*;
\ No newline at end of file
@@ -11,6 +11,7 @@ use jade::cli;
 /// Rust compiler.
 #[tokio::main]
 async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     match cli().await {
         Ok(feedback) => println!("{}", feedback),
         Err(e) => println!("{}", &e.to_string())
@@ -17,11 +17,6 @@ use crate::JadeUserFile;
 /// error structure.
 use super::err::JadeErr;
 
-/// Importing the function
-/// to delete a user from
-/// the database.
-use super::rw::wipe_user;
-
 /// Importing the "Data"
 /// structure from "actix-web"
 /// to main a connection
@@ -90,6 +85,11 @@ use actix_web::HttpResponse;
 /// to retrieve a users mood.
 use super::rw::get_user_mood;
 
+/// Importing the function
+/// to retrieve a user by their
+/// username.
+use super::rw::get_user_by_handle;
+
 /// Importing the function
 /// to retrieve all moods a 
 /// user has ever posted.
@@ -128,11 +128,6 @@ use super::units::StatusResponse;
 /// verify a user's email address.
 use super::rw::verify_user_email;
 
-/// Importing the structure
-/// for routes that only require an
-/// API token.
-use super::units::TokenOnlyPayload;
-
 /// Importing the structure
 /// for routes that only offer
 /// operations for managing moods.
@@ -168,9 +163,9 @@ use super::units::CreateTokenPayload;
 use super::units::UsernameOnlyPayload;
 
 /// Importing the structure
-/// for routes that only modify
-/// a user's information.
-use super::units::ChangeEntityPayload;
+/// for changing a user's email
+/// address.
+use super::units::ChangeEmailPayload;
 
 /// Importing the structure
 /// for routes that only concern
@@ -181,6 +176,468 @@ use super::units::UserAPITokensPayload;
 /// multipart file uploads.
 use actix_multipart::form::MultipartForm;
 
+/// Importing the structure
+/// containing the API token and
+/// user a request was authenticated
+/// with.
+use super::auth::AuthedUser;
+
+/// Importing the enum
+/// containing all actions that
+/// require authorization.
+use super::scopes::JadeAction;
+
+/// Importing the function
+/// to log a user in.
+use super::rw::login_user;
+
+/// Importing the function
+/// to end a browser session.
+use super::rw::wipe_session;
+
+/// Importing the structure
+/// containing information on a
+/// browser session.
+use super::units::JadeSession;
+
+/// Importing the structure
+/// for logging in.
+use super::units::LoginPayload;
+
+/// Importing the structure
+/// returned after logging in.
+use super::units::LoginResponse;
+
+/// Importing the function
+/// to build the session cookie.
+use super::sessions::session_cookie;
+
+/// Importing the function
+/// to build a cookie removing
+/// the session cookie.
+use super::sessions::removal_cookie;
+
+/// Importing the function
+/// to check the path a user
+/// returns to after logging in.
+use super::sessions::safe_return_to;
+
+/// Importing the function
+/// to build the URL of the
+/// login page.
+use super::sessions::login_url;
+
+/// Importing the function
+/// to render the login page.
+use super::render::render_login_page;
+
+/// Importing the function
+/// to render the pages behind
+/// links from emails.
+use super::render::render_link_page;
+
+/// Importing the structure
+/// containing the query parameters
+/// of the login page.
+use super::units::LoginPageQuery;
+
+/// Importing the structure
+/// containing the fields of the
+/// login form.
+use super::units::LoginForm;
+
+/// Importing the type of an
+/// OAuth application that can
+/// keep a client secret.
+use super::oauth::CONFIDENTIAL_CLIENT;
+
+/// Importing the "Form"
+/// structure to extract
+/// form-encoded bodies.
+use actix_web::web::Form;
+
+/// Importing the "Either"
+/// enum to accept bodies that
+/// are either JSON or form-encoded.
+use actix_web::Either;
+
+/// Importing the structure
+/// containing information on a
+/// registered OAuth application.
+use super::units::OAuthClient;
+
+/// Importing the structure
+/// for registering an OAuth
+/// application.
+use super::units::RegisterClientPayload;
+
+/// Importing the structure
+/// returned when an OAuth
+/// application is registered.
+use super::units::NewClientResponse;
+
+/// Importing the structure
+/// for registering an application
+/// the way Mastodon clients do.
+use super::units::MastodonAppPayload;
+
+/// Importing the structure
+/// returned to Mastodon clients
+/// registering an application.
+use super::units::MastodonAppResponse;
+
+/// Importing the structure
+/// containing an OAuth
+/// authorization request.
+use super::units::AuthorizeQuery;
+
+/// Importing the structure
+/// containing the fields of the
+/// OAuth consent form.
+use super::units::ConsentForm;
+
+/// Importing the structure
+/// for redeeming authorization
+/// codes and refresh tokens.
+use super::units::OAuthTokenPayload;
+
+/// Importing the structure
+/// returned when tokens are
+/// issued through OAuth.
+use super::units::OAuthTokenResponse;
+
+/// Importing the function
+/// to register an OAuth
+/// application.
+use super::rw::register_client;
+
+/// Importing the function
+/// to check an OAuth authorization
+/// request.
+use super::rw::check_authorization_request;
+
+/// Importing the function
+/// to create an authorization
+/// code.
+use super::rw::create_authorization_code;
+
+/// Importing the function
+/// to redeem an authorization
+/// code.
+use super::rw::redeem_authorization_code;
+
+/// Importing the function
+/// to redeem a refresh token.
+use super::rw::refresh_oauth_token;
+
+/// Importing the function
+/// to resolve a browser session
+/// without checking its CSRF
+/// token.
+use super::auth::session_user;
+
+/// Importing the function
+/// to authenticate a request
+/// with its API token.
+use super::auth::authenticate_token;
+
+/// Importing the name of the
+/// "Authorization" header.
+use actix_web::http::header::AUTHORIZATION;
+
+/// Importing the function
+/// to translate the scopes
+/// Mastodon clients request.
+use super::oauth::translate_mastodon_scopes;
+
+/// Importing the function
+/// to build a redirect URL
+/// with query parameters.
+use super::oauth::redirect_with_params;
+
+/// Importing the redirect
+/// URI for applications that
+/// cannot receive redirects.
+use super::oauth::OOB_REDIRECT_URI;
+
+/// Importing the function
+/// to render the OAuth consent
+/// screen.
+use super::render::render_consent_page;
+
+/// Importing the function
+/// to render a page showing
+/// an authorization code.
+use super::render::render_code_page;
+
+/// Importing the structure
+/// containing a new TOTP
+/// enrollment.
+use super::units::TotpEnrollment;
+
+/// Importing the structure
+/// for submitting a TOTP code
+/// or a recovery code.
+use super::units::TotpCodePayload;
+
+/// Importing the structure
+/// containing newly-issued
+/// recovery codes.
+use super::units::RecoveryCodesResponse;
+
+/// Importing the function
+/// to start a TOTP enrollment.
+use super::rw::enroll_totp;
+
+/// Importing the function
+/// to confirm a TOTP enrollment.
+use super::rw::confirm_totp;
+
+/// Importing the function
+/// to turn off two-factor
+/// authentication.
+use super::rw::disable_totp;
+
+/// Importing the structure
+/// for requesting a password
+/// reset link.
+use super::units::ForgotPasswordPayload;
+
+/// Importing the structure
+/// for setting a new password
+/// with a reset token.
+use super::units::ResetPasswordPayload;
+
+/// Importing the structure
+/// for changing a user's
+/// password.
+use super::units::ChangePasswordPayload;
+
+/// Importing the function
+/// to email a password reset
+/// link.
+use super::rw::request_password_reset;
+
+/// Importing the function
+/// to set a new password with
+/// a reset token.
+use super::rw::reset_password;
+
+/// Importing the function
+/// to check whether an account
+/// is locked.
+use super::ratelimit::check_account_lock;
+
+/// Importing the function
+/// to record the outcome of an
+/// authentication attempt.
+use super::ratelimit::record_account_attempt;
+
+/// Importing the function
+/// to record the outcome of
+/// a check of a secret for the
+/// client's address.
+use super::ratelimit::record_ip_attempt;
+
+/// Importing the structure
+/// containing information to
+/// request a new verification
+/// email.
+use super::units::ResendVerificationPayload;
+
+/// Importing the function
+/// to send a new verification
+/// email.
+use super::rw::resend_verification_email;
+
+/// Importing the function
+/// to confirm a change of a
+/// user's email address.
+use super::rw::confirm_email_change;
+
+/// Importing the function
+/// to revert a change of a
+/// user's email address.
+use super::rw::revert_email_change;
+
+/// Importing the function
+/// to check the payload for
+/// creating a new user.
+use super::rw::check_new_user;
+
+/// Importing the structure
+/// containing a problem with
+/// a single field of a payload.
+use super::units::FieldError;
+
+/// Importing the function
+/// to build the response for
+/// payloads with invalid fields.
+use super::err::validation_response;
+
+/// Importing the enum
+/// describing the kind of
+/// an error.
+use super::err::JadeErrKind;
+
+/// Importing the "StatusCode"
+/// enum to set the status code
+/// of rendered pages.
+use actix_web::http::StatusCode;
+
+/// Importing the function
+/// to check the syntax of an
+/// email address.
+use super::validation::validate_email;
+
+/// Importing the structure
+/// containing information to
+/// delete a user's account.
+use super::units::DeleteAccountPayload;
+
+/// Importing the structure
+/// containing the time a user's
+/// account will be purged at.
+use super::units::DeletionScheduled;
+
+/// Importing the function
+/// to schedule the deletion of
+/// a user's account.
+use super::rw::schedule_user_deletion;
+
+/// Importing the function
+/// to undo the deletion of a
+/// user's account.
+use super::rw::undo_user_deletion;
+
+/// Importing the structure
+/// returned when a new API token
+/// is created.
+use super::units::NewTokenResponse;
+
+/// Importing the "Query"
+/// structure to extract
+/// query parameters.
+use actix_web::web::Query;
+
+/// Importing the structure
+/// containing the query parameters
+/// for streaming mood updates.
+use super::units::MoodStreamQuery;
+
+/// Importing the function
+/// to open a stream of mood
+/// updates.
+use super::events::mood_event_stream;
+
+/// Importing the "HttpRequest"
+/// structure to upgrade a request
+/// to a websocket connection.
+use actix_web::HttpRequest;
+
+/// Importing the "Payload"
+/// structure to upgrade a request
+/// to a websocket connection.
+use actix_web::web::Payload;
+
+/// Importing the function
+/// to run a websocket connection
+/// for mood updates.
+use super::socket::run_mood_socket;
+
+/// Importing the structure
+/// containing a feed in the
+/// JSON Feed format.
+use super::units::JsonFeed;
+
+/// Importing the function
+/// to render an Atom feed.
+use super::render::render_atom_feed;
+
+/// Importing the function
+/// to render an RSS feed.
+use super::render::render_rss_feed;
+
+/// Importing the function
+/// to render a JSON feed.
+use super::render::render_json_feed;
+
+/// Importing the function
+/// to retrieve a user's public
+/// mood history.
+use super::rw::get_public_mood_history;
+
+/// Importing the function
+/// to render a mood badge.
+use super::render::render_badge;
+
+/// Importing the function
+/// to render a mood widget.
+use super::render::render_widget;
+
+/// Importing the function
+/// to retrieve a user's public
+/// current mood.
+use super::rw::get_public_mood;
+
+/// Importing the function
+/// to render a user's profile
+/// page.
+use super::render::render_profile;
+
+/// Importing the function
+/// to retrieve a user's avatar.
+use super::rw::get_user_avatar;
+
+/// Importing the function
+/// to retrieve a file a user
+/// has uploaded.
+use super::rw::get_user_file;
+
+/// Importing the function
+/// to retrieve a user whose
+/// moods are public.
+use super::rw::get_public_user;
+
+/// Importing the function
+/// to guess the media type
+/// of an uploaded image.
+use super::render::image_content_type;
+
+/// Importing the number of
+/// past moods shown on a
+/// profile page.
+use super::render::PROFILE_HISTORY_LENGTH;
+
+/// Importing the number of
+/// moods included in a feed.
+use super::render::FEED_LENGTH;
+
+/// Importing the function
+/// to package a user's data
+/// into an archive.
+use super::export::build_export_archive;
+
+/// Importing the function
+/// to import a user's data
+/// from an archive.
+use super::import::import_export_archive;
+
+/// Importing the structure
+/// to upload a data export
+/// to import.
+use super::units::ImportUploadForm;
+
+/// Importing the structure
+/// containing information on
+/// what was imported.
+use super::units::ImportSummary;
+
+/// The value of the "Cache-Control"
+/// header for embeddable mood badges
+/// and widgets.
+pub const EMBED_CACHE_CONTROL: &str = "public, max-age=300";
+
 /// Importing the structure to return
 /// information on whether email address
 /// verification was successful or not.
@@ -188,21 +645,54 @@ use super::units::EmailVerificationStatus;
 
 #[post("files/upload")]
 pub async fn upload_user_file(
+    auth: AuthedUser,
     MultipartForm(form): MultipartForm<FileUploadForm>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr>{
+    let _auth_check: () = match auth.authorize(JadeAction::UploadFile) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
     let mut buf: Vec<u8> = Vec::new();
     let _read_op: usize = match form.file.file.as_file().read_to_end(&mut buf){
         Ok(_read_op) => _read_op,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => {
+            let e: String = format!("Could not read the uploaded file: {}", e);
+            return Err::<HttpResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
     };
-    let user_file: JadeUserFile = match store_file(&buf, &form.metadata.api_token, &form.metadata.name, &data.pool).await {
+    let user_file: JadeUserFile = match store_file(&buf, &auth.user, &form.metadata.name, &data.pool).await {
         Ok(user_file) => user_file,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(user_file))
 }
 
+/// This API route attempts to return the
+/// contents of a file the user the request is
+/// authenticated as has uploaded. If this operation
+/// fails, an error response is returend.
+pub async fn get_file(
+    auth: AuthedUser,
+    file_id: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let _auth_check: () = match auth.authorize(JadeAction::ReadFile) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let user_file: JadeUserFile = match get_user_file(&auth.user.username, &file_id, &data.pool).await {
+        Ok(user_file) => user_file,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(
+        HttpResponse::Ok()
+            .content_type(image_content_type(&user_file.file_name))
+            .insert_header(("X-Content-Type-Options", "nosniff"))
+            .body(user_file.data)
+    )
+}
+
 #[post("/email/verify/{email_token}")]
 pub async fn verify_email(
     token: Path<String>,
@@ -210,12 +700,95 @@ pub async fn verify_email(
 ) -> Result<HttpResponse, JadeErr> {
     let verified: bool = match verify_user_email(&token, &data.pool).await {
         Ok(verified) => verified,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     let res: EmailVerificationStatus = EmailVerificationStatus{status:verified};
     Ok(HttpResponse::Ok().json(res))
 }
 
+/// Builds the response carrying a page for a
+/// link from an email. The page is not cached
+/// and does not pass the token in its address
+/// on to other sites.
+pub fn link_page_response(status: StatusCode, page: String) -> HttpResponse {
+    HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .insert_header(("Referrer-Policy", "no-referrer"))
+        .body(page)
+}
+
+/// This API route returns the page the link
+/// in a verification email opens. The page asks
+/// the user to confirm, so that mail scanners
+/// opening the link do not verify the address.
+pub async fn verify_email_page(
+    token: Path<String>
+) -> HttpResponse {
+    let page: String = render_link_page(
+        "Verify your email address",
+        "Confirm that this is your email address to finish setting up your Jade account.",
+        &Some(format!("/email/verify/{}/form", token.as_str())),
+        "Verify my email address"
+    );
+    link_page_response(StatusCode::OK, page)
+}
+
+/// Builds the response carrying the page that
+/// shows the outcome of a form on a page for a
+/// link from an email. Internal errors are passed
+/// on, all other errors are shown on the page.
+pub fn link_outcome_response<T>(
+    outcome: Result<T, JadeErr>,
+    done_title: &str,
+    done_message: &str,
+    failed_title: &str
+) -> Result<HttpResponse, JadeErr> {
+    match outcome {
+        Ok(_done) => {
+            let page: String = render_link_page(done_title, done_message, &None, "");
+            Ok(link_page_response(StatusCode::OK, page))
+        },
+        Err(e) if e.kind == JadeErrKind::Internal => Err::<HttpResponse, JadeErr>(e),
+        Err(e) => {
+            let page: String = render_link_page(failed_title, &e.details, &None, "");
+            Ok(link_page_response(e.kind.status_code(), page))
+        }
+    }
+}
+
+/// This API route attempts to verify a user's
+/// email address with the form on the page the
+/// verification link opens. The outcome is shown
+/// on a page. If this operation fails, an error
+/// response is returend.
+pub async fn verify_email_form(
+    token: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    link_outcome_response(
+        verify_user_email(&token, &data.pool).await,
+        "Email address verified",
+        "Your email address has been verified. You can now log in.",
+        "Email address not verified"
+    )
+}
+
+/// This API route attempts to send a new
+/// verification email to a user who has not
+/// verified their email address yet. If this
+/// operation fails, an error response is returend.
+pub async fn resend_verification(
+    payload: Json<ResendVerificationPayload>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let status: StatusResponse = match resend_verification_email(&payload, &data.email_keys, &data.pool, &data.smtp_server, &data.instance_url()).await {
+        Ok(status) => status,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(status))
+}
+
 /// This API route attempts to create a new user
 /// with the given payload. If this operation
 /// fails, an error response is returend.
@@ -223,79 +796,351 @@ pub async fn create_user(
     payload: Json<CreateUserPayload>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let created: JadeUser = match write_user(&payload, &data.pool, &data.smtp_server).await {
+    let problems: Vec<FieldError> = match check_new_user(&payload, &data.email_keys, &data.pool).await {
+        Ok(problems) => problems,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    if problems.is_empty() {}
+    else {
+        return Ok(validation_response(problems));
+    }
+    let created: JadeUser = match write_user(&payload, &data.email_keys, &data.pool, &data.smtp_server, &data.instance_url()).await {
         Ok(created) => created,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(created))
 }
 
-/// This API route attempts to delete a user
-/// with the given payload. If this operation
+/// This API route attempts to schedule the
+/// deletion of the user the request was
+/// authenticated as. If this operation
 /// fails, an error response is returend.
 pub async fn delete_user(
-    payload: Json<TokenOnlyPayload>,
+    auth: AuthedUser,
+    payload: Json<DeleteAccountPayload>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let wiped: StatusResponse = match wipe_user(&payload, &data.pool).await {
-        Ok(created) => created,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+    let _auth_check: () = match auth.authorize(JadeAction::DeleteUser) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
-    Ok(HttpResponse::Ok().json(wiped))
+    let scheduled: DeletionScheduled = match schedule_user_deletion(
+        &payload,
+        &auth.user,
+        &data.email_keys,
+        data.deletion_grace_secs,
+        &data.pool,
+        &data.smtp_server,
+        &data.instance_url()
+    ).await {
+        Ok(scheduled) => scheduled,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(scheduled))
+}
+
+/// This API route returns the page the link
+/// to undo the deletion of an account opens.
+/// The page asks the user to confirm, so that
+/// mail scanners opening the link do not undo
+/// the deletion.
+pub async fn undo_delete_user_page(
+    token: Path<String>
+) -> HttpResponse {
+    let page: String = render_link_page(
+        "Keep your Jade account",
+        "Your Jade account is scheduled to be deleted. Confirm to keep your account and everything in it.",
+        &Some(format!("/user/delete/undo/{}/form", token.as_str())),
+        "Keep my account"
+    );
+    link_page_response(StatusCode::OK, page)
+}
+
+/// This API route attempts to undo the
+/// scheduled deletion of a user's account
+/// with the form on the page the link to undo
+/// the deletion opens. The outcome is shown
+/// on a page. If this operation fails, an error
+/// response is returend.
+pub async fn undo_delete_user_form(
+    token: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    link_outcome_response(
+        undo_user_deletion(&token, &data.pool).await,
+        "Account kept",
+        "Your Jade account will not be deleted. Sessions and API tokens revoked when the deletion was scheduled stay revoked, so please log in again.",
+        "Account deletion not undone"
+    )
+}
+
+/// This API route attempts to undo the
+/// scheduled deletion of a user's account.
+/// If this operation fails, an error response
+/// is returend.
+pub async fn undo_delete_user(
+    token: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let op_status: StatusResponse = match undo_user_deletion(&token, &data.pool).await {
+        Ok(op_status) => op_status,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(op_status))
+}
+
+/// This API route attempts to package
+/// everything Jade stores about the user
+/// the request was authenticated as into a
+/// ZIP archive for download. If this operation
+/// fails, an error response is returend.
+pub async fn export_user_data(
+    auth: AuthedUser,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let _auth_check: () = match auth.authorize(JadeAction::ExportData) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let archive: Vec<u8> = match build_export_archive(&auth.user, &data.email_keys, &data.pool).await {
+        Ok(archive) => archive,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(
+        HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header(("Content-Disposition", format!("attachment; filename=\"{}-export.zip\"", &auth.user.username)))
+            .insert_header(("Cache-Control", "no-store"))
+            .body(archive)
+    )
+}
+
+/// This API route attempts to import a
+/// Jade data export into the account of
+/// the user the request was authenticated
+/// as. If this operation fails, an error
+/// response is returend.
+pub async fn import_user_data(
+    auth: AuthedUser,
+    MultipartForm(form): MultipartForm<ImportUploadForm>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let _auth_check: () = match auth.authorize(JadeAction::ImportData) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let mut buf: Vec<u8> = Vec::new();
+    let _read_op: usize = match form.archive.file.as_file().read_to_end(&mut buf){
+        Ok(_read_op) => _read_op,
+        Err(e) => {
+            let e: String = format!("Could not read the uploaded archive: {}", e);
+            return Err::<HttpResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    let summary: ImportSummary = match import_export_archive(buf, &auth.user, &data.pool).await {
+        Ok(summary) => summary,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(summary))
 }
 
 /// This API route attempts to create a new API
 /// token with the given payload. If this operation
 /// fails, an error response is returend.
 pub async fn create_token(
+    req: HttpRequest,
     payload: Json<CreateTokenPayload>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let wiped: APIToken = match create_new_token(&payload, &data.pool).await {
+    match check_account_lock(&data, &payload.username).await {
+        Ok(Some(locked)) => return Ok(locked),
+        Ok(None) => {},
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let attempt = create_new_token(&payload, &data.email_keys, &data.pool).await;
+    let _record_op: () = match record_account_attempt(&req, &data, &payload.username, &attempt).await {
+        Ok(_record_op) => _record_op,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let created: NewTokenResponse = match attempt {
         Ok(created) => created,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
-    Ok(HttpResponse::Ok().json(wiped))
+    Ok(HttpResponse::Ok().json(created))
 }
 
 /// This API route attempts to delete an API
 /// token with the given payload. If this operation
 /// fails, an error response is returend.
 pub async fn delete_token(
+    req: HttpRequest,
     payload: Json<DeleteTokenPayload>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let wiped: StatusResponse = match wipe_token(&payload, &data.pool).await {
+    match check_account_lock(&data, &payload.username).await {
+        Ok(Some(locked)) => return Ok(locked),
+        Ok(None) => {},
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let attempt = wipe_token(&payload, &data.pool).await;
+    let _record_op: () = match record_account_attempt(&req, &data, &payload.username, &attempt).await {
+        Ok(_record_op) => _record_op,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let wiped: StatusResponse = match attempt {
         Ok(wiped) => wiped,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(wiped))
 }
 
+/// This API route attempts to log a user in
+/// with the given payload. The session ID is
+/// set as an HTTP-only cookie and the session's
+/// CSRF token is returned. If this operation
+/// fails, an error response is returend.
+pub async fn login(
+    req: HttpRequest,
+    payload: Json<LoginPayload>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    match check_account_lock(&data, &payload.username).await {
+        Ok(Some(locked)) => return Ok(locked),
+        Ok(None) => {},
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let attempt = login_user(&payload, &data.email_keys, &data.pool).await;
+    let _record_op: () = match record_account_attempt(&req, &data, &payload.username, &attempt).await {
+        Ok(_record_op) => _record_op,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let (session_id, session): (String, JadeSession) = match attempt {
+        Ok(logged_in) => logged_in,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let res: LoginResponse = LoginResponse {
+        username: session.username,
+        csrf_token: session.csrf_token,
+        expires_at: session.expires_at
+    };
+    Ok(HttpResponse::Ok().cookie(session_cookie(&session_id)).json(res))
+}
+
+/// This API route returns the login page for
+/// browsers. Once the user has logged in, they
+/// are sent back to the path in the "return_to"
+/// query parameter.
+pub async fn login_page(
+    query: Query<LoginPageQuery>
+) -> HttpResponse {
+    let page: String = render_login_page(&safe_return_to(&query.return_to), &None);
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .insert_header(("Cache-Control", "no-store"))
+        .body(page)
+}
+
+/// This API route attempts to log a user in
+/// with the form on the login page. The session
+/// ID is set as an HTTP-only cookie and the user
+/// is sent back to the path they came from. If
+/// the credentials are wrong, the login page is
+/// shown again with the reason. If this operation
+/// fails, an error response is returend.
+pub async fn login_form(
+    req: HttpRequest,
+    form: Form<LoginForm>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let return_to: String = safe_return_to(&form.return_to);
+    let payload: LoginPayload = LoginPayload {
+        username: form.username.clone(),
+        password: form.password.clone(),
+        totp_code: form.totp_code.clone().filter(|code| !code.is_empty())
+    };
+    match check_account_lock(&data, &payload.username).await {
+        Ok(Some(locked)) => return Ok(locked),
+        Ok(None) => {},
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let attempt = login_user(&payload, &data.email_keys, &data.pool).await;
+    let _record_op: () = match record_account_attempt(&req, &data, &payload.username, &attempt).await {
+        Ok(_record_op) => _record_op,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    match attempt {
+        Ok((session_id, _session)) => Ok(
+            HttpResponse::SeeOther()
+                .cookie(session_cookie(&session_id))
+                .insert_header(("Location", return_to))
+                .finish()
+        ),
+        Err(e) if e.kind == JadeErrKind::Internal => Err::<HttpResponse, JadeErr>(e),
+        Err(e) => Ok(
+            HttpResponse::build(e.kind.status_code())
+                .content_type("text/html; charset=utf-8")
+                .insert_header(("Cache-Control", "no-store"))
+                .body(render_login_page(&return_to, &Some(e.details)))
+        )
+    }
+}
+
+/// This API route attempts to end the browser
+/// session the request was made with and removes
+/// the session cookie. If this operation fails,
+/// an error response is returend.
+pub async fn logout(
+    auth: AuthedUser,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let session: JadeSession = match auth.session {
+        Some(session) => session,
+        None => {
+            let e: String = "Only browser sessions can be logged out.".to_string();
+            return Err::<HttpResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    let status: StatusResponse = match wipe_session(&session, &data.pool).await {
+        Ok(status) => status,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().cookie(removal_cookie()).json(status))
+}
+
 /// This API route attempts to create a new mood
 /// with the given payload. If this operation
 /// fails, an error response is returend.
 pub async fn set_mood(
+    auth: AuthedUser,
     payload: Json<MoodActionPayload>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let new_mood: JadeMood = match create_new_mood(&payload, &data.pool).await {
+    let _auth_check: () = match auth.authorize(JadeAction::SetMood) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let new_mood: JadeMood = match create_new_mood(&payload, &auth.user, &data.pool).await {
         Ok(new_mood) => new_mood,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(new_mood))
 }
 
-/// This API route attempts to delete a mood
-/// with the given payload. If this operation
-/// fails, an error response is returend.
+/// This API route attempts to clear the mood
+/// of the user the request was authenticated as.
+/// If this operation fails, an error response
+/// is returend.
 pub async fn delete_mood(
-    payload: Json<MoodActionPayload>,
+    auth: AuthedUser,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let status: StatusResponse = match wipe_mood(&payload, &data.pool).await {
+    let _auth_check: () = match auth.authorize(JadeAction::DeleteMood) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let status: StatusResponse = match wipe_mood(&auth.user, &data.pool).await {
         Ok(status) => status,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(status))
 }
@@ -305,12 +1150,17 @@ pub async fn delete_mood(
 /// If this operation fails, an error 
 /// response is returend.
 pub async fn change_user_pwd(
-    payload: Json<ChangeEntityPayload>,
+    auth: AuthedUser,
+    payload: Json<ChangePasswordPayload>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let op_status: StatusResponse = match update_user_password(&payload, &data.pool).await {
+    let _auth_check: () = match auth.authorize(JadeAction::ChangePassword) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let op_status: StatusResponse = match update_user_password(&payload, &auth.user, &data.pool).await {
         Ok(op_status) => op_status,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(op_status))
 }
@@ -320,12 +1170,118 @@ pub async fn change_user_pwd(
 /// If this operation fails, an error 
 /// response is returend.
 pub async fn change_user_email(
-    payload: Json<ChangeEntityPayload>,
+    auth: AuthedUser,
+    payload: Json<ChangeEmailPayload>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let _auth_check: () = match auth.authorize(JadeAction::ChangeEmail) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    if let Some(problem) = validate_email("new_email", &payload.new_email) {
+        return Ok(validation_response(vec![problem]));
+    }
+    let op_status: StatusResponse = match update_user_email(&payload, &auth.user, &data.email_keys, &data.pool, &data.smtp_server, &data.instance_url()).await {
+        Ok(op_status) => op_status,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(op_status))
+}
+
+/// This API route returns the page the link
+/// to confirm a change of a user's email address
+/// opens. The page asks the user to confirm, so
+/// that mail scanners opening the link do not
+/// confirm the change.
+pub async fn confirm_email_page(
+    token: Path<String>
+) -> HttpResponse {
+    let page: String = render_link_page(
+        "Confirm your new email address",
+        "Confirm to make this the email address of your Jade account.",
+        &Some(format!("/email/change/confirm/{}/form", token.as_str())),
+        "Confirm my new email address"
+    );
+    link_page_response(StatusCode::OK, page)
+}
+
+/// This API route attempts to confirm a
+/// pending change of a user's email address
+/// with the form on the page the confirmation
+/// link opens. The outcome is shown on a page.
+/// If this operation fails, an error response
+/// is returend.
+pub async fn confirm_email_form(
+    token: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    link_outcome_response(
+        confirm_email_change(&token, &data.pool).await,
+        "Email address changed",
+        "This is now the email address of your Jade account.",
+        "Email address not changed"
+    )
+}
+
+/// This API route attempts to confirm a
+/// pending change of a user's email address.
+/// If this operation fails, an error response
+/// is returend.
+pub async fn confirm_email(
+    token: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let op_status: StatusResponse = match confirm_email_change(&token, &data.pool).await {
+        Ok(op_status) => op_status,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(op_status))
+}
+
+/// This API route returns the page the link
+/// to revert a change of a user's email address
+/// opens. The page asks the user to confirm, so
+/// that mail scanners opening the link do not
+/// revert the change.
+pub async fn revert_email_page(
+    token: Path<String>
+) -> HttpResponse {
+    let page: String = render_link_page(
+        "Keep your email address",
+        "Confirm to keep this email address for your Jade account. The change of your email address is undone and all devices are logged out.",
+        &Some(format!("/email/change/revert/{}/form", token.as_str())),
+        "Keep this email address"
+    );
+    link_page_response(StatusCode::OK, page)
+}
+
+/// This API route attempts to revert a
+/// change of a user's email address with the
+/// form on the page the revert link opens. The
+/// outcome is shown on a page. If this operation
+/// fails, an error response is returend.
+pub async fn revert_email_form(
+    token: Path<String>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let op_status: StatusResponse = match update_user_email(&payload, &data.pool, &data.smtp_server).await {
+    link_outcome_response(
+        revert_email_change(&token, &data.pool).await,
+        "Email address kept",
+        "The change of your email address has been undone and all devices have been logged out. Please change your password if you did not ask for the change.",
+        "Email change not undone"
+    )
+}
+
+/// This API route attempts to revert a
+/// change of a user's email address. If this
+/// operation fails, an error response is returend.
+pub async fn revert_email(
+    token: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let op_status: StatusResponse = match revert_email_change(&token, &data.pool).await {
         Ok(op_status) => op_status,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(op_status))
 }
@@ -340,7 +1296,7 @@ pub async fn get_mood(
 ) -> Result<HttpResponse, JadeErr> {
     let mood: JadeMood = match get_user_mood(&payload, &data.pool).await {
         Ok(mood) => mood,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(mood))
 }
@@ -355,7 +1311,7 @@ pub async fn get_moods(
 ) -> Result<HttpResponse, JadeErr> {
     let moods: UserMoodsResponse = match get_user_moods(&payload, &data.pool).await {
         Ok(moods) => moods,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(moods))
 }
@@ -365,12 +1321,542 @@ pub async fn get_moods(
 /// If this operation fails, an error 
 /// response is returend.
 pub async fn get_tokens(
+    req: HttpRequest,
     payload: Json<UserAPITokensPayload>,
     data: Data<AppData>
 ) -> Result<HttpResponse, JadeErr> {
-    let tokens: Vec<APIToken> = match get_user_tokens(&payload, &data.pool).await {
+    match check_account_lock(&data, &payload.username).await {
+        Ok(Some(locked)) => return Ok(locked),
+        Ok(None) => {},
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let attempt = get_user_tokens(&payload, &data.pool).await;
+    let _record_op: () = match record_account_attempt(&req, &data, &payload.username, &attempt).await {
+        Ok(_record_op) => _record_op,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let tokens: Vec<APIToken> = match attempt {
         Ok(tokens) => tokens,
-        Err(e) => return Err::<HttpResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
     };
     Ok(HttpResponse::Ok().json(tokens))
+}
+
+/// This API route attempts to open a stream
+/// of Server-Sent Events that reports every
+/// change to the moods of the given users.
+/// If this operation fails, an error response
+/// is returend.
+pub async fn stream_moods(
+    query: Query<MoodStreamQuery>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let mut users: Vec<String> = Vec::new();
+    for username in query.users.split(',') {
+        let trimmed: String = username.trim().to_string();
+        if trimmed.is_empty() {}
+        else {
+            let user: JadeUser = match get_user_by_handle(&trimmed, &data.pool).await {
+                Ok(user) => user,
+                Err(e) => return Err::<HttpResponse, JadeErr>(e)
+            };
+            users.push(user.username);
+        }
+    }
+    if users.is_empty() {
+        let e: String = "No users to watch were specified.".to_string();
+        return Err::<HttpResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    let events = mood_event_stream(&users, data.mood_events.subscribe());
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .insert_header(("Cache-Control", "no-cache"))
+            .streaming(events)
+    )
+}
+
+/// This API route attempts to upgrade the
+/// request to a websocket connection over which
+/// a client can authenticate, subscribe to mood
+/// updates, and set or clear their own mood.
+/// Clients that send an "Authorization" header
+/// with the upgrade request are authenticated
+/// from the start. The session cookie is ignored,
+/// since browsers send it with upgrade requests
+/// from any site. If this operation fails, an
+/// error response is returend.
+pub async fn mood_socket(
+    req: HttpRequest,
+    body: Payload,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let auth: Option<AuthedUser> = if req.headers().contains_key(AUTHORIZATION) {
+        match authenticate_token(&req, &data).await {
+            Ok(auth) => Some(auth),
+            Err(e) => return Err::<HttpResponse, JadeErr>(e)
+        }
+    }
+    else {
+        None
+    };
+    let (response, session, messages) = match actix_ws::handle(&req, body) {
+        Ok(handled) => handled,
+        Err(e) => {
+            let e: String = format!("Could not open a websocket connection: {}", e);
+            return Err::<HttpResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    actix_web::rt::spawn(run_mood_socket(session, messages, auth, data.mood_events.subscribe(), data.pool.clone()));
+    Ok(response)
+}
+
+/// This API route attempts to return a user's
+/// mood history as an RSS feed. If this operation
+/// fails, an error response is returend.
+pub async fn user_feed_rss(
+    username: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let moods: Vec<JadeMood> = match get_public_mood_history(&username, FEED_LENGTH, &data.pool).await {
+        Ok(moods) => moods,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let feed: String = match render_rss_feed(&data.instance_url(), &username, &moods) {
+        Ok(feed) => feed,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().content_type("application/rss+xml; charset=utf-8").body(feed))
+}
+
+/// This API route attempts to return a user's
+/// mood history as an Atom feed. If this operation
+/// fails, an error response is returend.
+pub async fn user_feed_atom(
+    username: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let moods: Vec<JadeMood> = match get_public_mood_history(&username, FEED_LENGTH, &data.pool).await {
+        Ok(moods) => moods,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let feed: String = match render_atom_feed(&data.instance_url(), &username, &moods) {
+        Ok(feed) => feed,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().content_type("application/atom+xml; charset=utf-8").body(feed))
+}
+
+/// This API route attempts to return a user's
+/// mood history as a JSON feed. If this operation
+/// fails, an error response is returend.
+pub async fn user_feed_json(
+    username: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let moods: Vec<JadeMood> = match get_public_mood_history(&username, FEED_LENGTH, &data.pool).await {
+        Ok(moods) => moods,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let feed: JsonFeed = match render_json_feed(&data.instance_url(), &username, &moods) {
+        Ok(feed) => feed,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().content_type("application/feed+json").json(feed))
+}
+
+/// This API route attempts to return a user's
+/// current mood as an SVG badge. If this operation
+/// fails, an error response is returend.
+pub async fn user_badge(
+    username: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let mood: Option<JadeMood> = match get_public_mood(&username, &data.pool).await {
+        Ok(mood) => mood,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let badge: String = match render_badge(&username, &mood) {
+        Ok(badge) => badge,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(
+        HttpResponse::Ok()
+            .content_type("image/svg+xml; charset=utf-8")
+            .insert_header(("Cache-Control", EMBED_CACHE_CONTROL))
+            .body(badge)
+    )
+}
+
+/// This API route attempts to return a user's
+/// current mood as an HTML widget that can be
+/// embedded with an iframe. If this operation
+/// fails, an error response is returend.
+pub async fn user_widget(
+    username: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let mood: Option<JadeMood> = match get_public_mood(&username, &data.pool).await {
+        Ok(mood) => mood,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let widget: String = match render_widget(&data.instance_url(), &username, &mood) {
+        Ok(widget) => widget,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header(("Cache-Control", EMBED_CACHE_CONTROL))
+            .insert_header(("Content-Security-Policy", "frame-ancestors *"))
+            .body(widget)
+    )
+}
+
+/// This API route attempts to return a user's
+/// public profile page. If this operation
+/// fails, an error response is returend.
+pub async fn user_profile(
+    username: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let user: JadeUser = match get_public_user(&username, &data.pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let username: &String = &user.username;
+    let current: Option<JadeMood> = match get_public_mood(username, &data.pool).await {
+        Ok(current) => current,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let moods: Vec<JadeMood> = match get_public_mood_history(username, PROFILE_HISTORY_LENGTH as i64 + 1, &data.pool).await {
+        Ok(moods) => moods,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let avatar: Option<JadeUserFile> = match get_user_avatar(username, &data.pool).await {
+        Ok(avatar) => avatar,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let page: String = match render_profile(&data.instance_url(), username, &current, &moods, avatar.is_some()) {
+        Ok(page) => page,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header(("Cache-Control", EMBED_CACHE_CONTROL))
+            .body(page)
+    )
+}
+
+/// This API route attempts to return the
+/// avatar a user has uploaded. If this operation
+/// fails, an error response is returend.
+pub async fn user_avatar(
+    username: Path<String>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let user: JadeUser = match get_public_user(&username, &data.pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let avatar: JadeUserFile = match get_user_avatar(&user.username, &data.pool).await {
+        Ok(Some(avatar)) => avatar,
+        Ok(None) => {
+            let e: String = format!("User \"{}\" has not uploaded an avatar.", &user.username);
+            return Err::<HttpResponse, JadeErr>(JadeErr::not_found(&e.to_string()))
+        },
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(
+        HttpResponse::Ok()
+            .content_type(image_content_type(&avatar.file_name))
+            .insert_header(("Cache-Control", EMBED_CACHE_CONTROL))
+            .insert_header(("X-Content-Type-Options", "nosniff"))
+            .body(avatar.data)
+    )
+}
+
+/// This API route attempts to register a new
+/// OAuth application with the given payload.
+/// If this operation fails, an error response
+/// is returend.
+pub async fn create_oauth_client(
+    payload: Json<RegisterClientPayload>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let created: NewClientResponse = match register_client(&payload, &data.pool).await {
+        Ok(created) => created,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(created))
+}
+
+/// This API route attempts to register a new
+/// OAuth application the way Mastodon clients
+/// expect to. The body can be JSON or form-encoded.
+/// If this operation fails, an error response
+/// is returend.
+pub async fn create_mastodon_app(
+    payload: Either<Json<MastodonAppPayload>, Form<MastodonAppPayload>>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let payload: MastodonAppPayload = match payload {
+        Either::Left(json) => json.into_inner(),
+        Either::Right(form) => form.into_inner()
+    };
+    let register: RegisterClientPayload = RegisterClientPayload {
+        client_name: payload.client_name.clone(),
+        redirect_uris: payload.redirect_uris.split_whitespace().map(|uri| uri.to_string()).collect(),
+        scopes: translate_mastodon_scopes(&payload.scopes),
+        website: payload.website.clone(),
+        client_type: Some(CONFIDENTIAL_CLIENT.to_string())
+    };
+    let created: NewClientResponse = match register_client(&register, &data.pool).await {
+        Ok(created) => created,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let res: MastodonAppResponse = MastodonAppResponse {
+        id: created.details.client_id.clone(),
+        name: created.details.client_name.clone(),
+        website: created.details.website.clone(),
+        redirect_uri: created.details.redirect_uris.join("\n"),
+        client_id: created.details.client_id.clone(),
+        client_secret: created.client_secret.unwrap_or_default()
+    };
+    Ok(HttpResponse::Ok().json(res))
+}
+
+/// This API route attempts to show the consent
+/// screen for an OAuth authorization request to
+/// the user logged in with the session cookie.
+/// Users who are not logged in are sent to the
+/// login page first and come back here afterwards.
+/// If this operation fails, an error response
+/// is returend.
+pub async fn oauth_authorize(
+    req: HttpRequest,
+    query: Query<AuthorizeQuery>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let auth: AuthedUser = match session_user(&req, &data).await {
+        Ok(auth) => auth,
+        Err(_e) => {
+            let return_to: String = match req.uri().path_and_query() {
+                Some(path) => path.to_string(),
+                None => req.path().to_string()
+            };
+            return Ok(
+                HttpResponse::SeeOther()
+                    .insert_header(("Location", login_url(&return_to)))
+                    .finish()
+            )
+        }
+    };
+    let csrf_token: String = match &auth.session {
+        Some(session) => session.csrf_token.clone(),
+        None => {
+            let e: String = "Please log in to Jade before authorizing applications.".to_string();
+            return Err::<HttpResponse, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    let (client, scopes): (OAuthClient, Vec<String>) = match check_authorization_request(&query, &data.pool).await {
+        Ok(checked) => checked,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let page: String = render_consent_page(&client, &auth.user.username, &scopes, &query, &csrf_token);
+    Ok(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .insert_header(("Cache-Control", "no-store"))
+            .body(page)
+    )
+}
+
+/// This API route attempts to record the user's
+/// decision on the consent screen. If the user
+/// approved the request, the application is sent
+/// an authorization code. If this operation fails,
+/// an error response is returend.
+pub async fn oauth_confirm(
+    req: HttpRequest,
+    form: Form<ConsentForm>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let auth: AuthedUser = match session_user(&req, &data).await {
+        Ok(auth) => auth,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    match &auth.session {
+        Some(session) if session.csrf_token == form.csrf_token => {},
+        _ => {
+            let e: String = "The CSRF token of the consent form is invalid.".to_string();
+            return Err::<HttpResponse, JadeErr>(JadeErr::forbidden(&e.to_string()))
+        }
+    };
+    let (client, scopes): (OAuthClient, Vec<String>) = match check_authorization_request(&form.request, &data.pool).await {
+        Ok(checked) => checked,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let mut params: Vec<(&str, String)> = Vec::new();
+    if form.decision == "allow" {
+        let code: String = match create_authorization_code(&form.request, &scopes, &auth.user, &data.pool).await {
+            Ok(code) => code,
+            Err(e) => return Err::<HttpResponse, JadeErr>(e)
+        };
+        if form.request.redirect_uri == OOB_REDIRECT_URI {
+            return Ok(
+                HttpResponse::Ok()
+                    .content_type("text/html; charset=utf-8")
+                    .insert_header(("Cache-Control", "no-store"))
+                    .body(render_code_page(&client, &code))
+            );
+        }
+        params.push(("code", code));
+    }
+    else if form.request.redirect_uri == OOB_REDIRECT_URI {
+        let e: String = "The authorization request was denied.".to_string();
+        return Err::<HttpResponse, JadeErr>(JadeErr::forbidden(&e.to_string()))
+    }
+    else {
+        params.push(("error", "access_denied".to_string()));
+    }
+    if let Some(state) = &form.request.state {
+        params.push(("state", state.clone()));
+    }
+    let location: String = match redirect_with_params(&form.request.redirect_uri, &params) {
+        Ok(location) => location,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Found().insert_header(("Location", location)).finish())
+}
+
+/// This API route attempts to exchange an
+/// authorization code or a refresh token for
+/// an API token. The body can be JSON or
+/// form-encoded. If this operation fails, an
+/// error response is returend.
+pub async fn oauth_token(
+    req: HttpRequest,
+    payload: Either<Json<OAuthTokenPayload>, Form<OAuthTokenPayload>>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let payload: OAuthTokenPayload = match payload {
+        Either::Left(json) => json.into_inner(),
+        Either::Right(form) => form.into_inner()
+    };
+    let attempt: Result<OAuthTokenResponse, JadeErr> = match payload.grant_type.as_str() {
+        "authorization_code" => redeem_authorization_code(&payload, &data.pool).await,
+        "refresh_token" => refresh_oauth_token(&payload, &data.pool).await,
+        other => {
+            let e: String = format!("The grant type \"{}\" is not supported.", other);
+            return Err::<HttpResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    let _record_op: () = match record_ip_attempt(&req, &data, &attempt).await {
+        Ok(_record_op) => _record_op,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let issued: OAuthTokenResponse = match attempt {
+        Ok(issued) => issued,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(
+        HttpResponse::Ok()
+            .insert_header(("Cache-Control", "no-store"))
+            .json(issued)
+    )
+}
+
+/// This API route attempts to start setting up
+/// two-factor authentication for the user the
+/// request was authenticated as. If this operation
+/// fails, an error response is returend.
+pub async fn start_totp_enrollment(
+    auth: AuthedUser,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let _auth_check: () = match auth.authorize(JadeAction::ManageTwoFactor) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let enrollment: TotpEnrollment = match enroll_totp(&auth.user, &data.email_keys, &data.pool).await {
+        Ok(enrollment) => enrollment,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(enrollment))
+}
+
+/// This API route attempts to enable two-factor
+/// authentication with the given code and returns
+/// the user's recovery codes. If this operation
+/// fails, an error response is returend.
+pub async fn confirm_totp_enrollment(
+    auth: AuthedUser,
+    payload: Json<TotpCodePayload>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let _auth_check: () = match auth.authorize(JadeAction::ManageTwoFactor) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let codes: RecoveryCodesResponse = match confirm_totp(&auth.user, &payload, &data.email_keys, &data.pool).await {
+        Ok(codes) => codes,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().insert_header(("Cache-Control", "no-store")).json(codes))
+}
+
+/// This API route attempts to turn off two-factor
+/// authentication with the given code. If this
+/// operation fails, an error response is returend.
+pub async fn remove_totp(
+    auth: AuthedUser,
+    payload: Json<TotpCodePayload>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let _auth_check: () = match auth.authorize(JadeAction::ManageTwoFactor) {
+        Ok(_auth_check) => _auth_check,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let status: StatusResponse = match disable_totp(&auth.user, &payload, &data.email_keys, &data.pool).await {
+        Ok(status) => status,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// This API route attempts to email a password
+/// reset link to the user with the given username
+/// and email address. If this operation fails, an
+/// error response is returend.
+pub async fn forgot_password(
+    payload: Json<ForgotPasswordPayload>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let status: StatusResponse = match request_password_reset(&payload, &data.email_keys, &data.pool, &data.smtp_server, &data.instance_url()).await {
+        Ok(status) => status,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(status))
+}
+
+/// This API route attempts to set a new password
+/// with a password reset token. If this operation
+/// fails, an error response is returend.
+pub async fn confirm_password_reset(
+    req: HttpRequest,
+    payload: Json<ResetPasswordPayload>,
+    data: Data<AppData>
+) -> Result<HttpResponse, JadeErr> {
+    let attempt: Result<StatusResponse, JadeErr> = reset_password(&payload, &data.email_keys, &data.pool).await;
+    let _record_op: () = match record_ip_attempt(&req, &data, &attempt).await {
+        Ok(_record_op) => _record_op,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    let status: StatusResponse = match attempt {
+        Ok(status) => status,
+        Err(e) => return Err::<HttpResponse, JadeErr>(e)
+    };
+    Ok(HttpResponse::Ok().json(status))
 }
\ No newline at end of file
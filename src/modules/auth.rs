@@ -0,0 +1,521 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "Data"
+/// structure to access the
+/// app's data.
+use actix_web::web::Data;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "FromRequest"
+/// trait to implement it.
+use actix_web::FromRequest;
+
+/// Importing the "HttpRequest"
+/// structure to read request
+/// headers.
+use actix_web::HttpRequest;
+
+/// Importing the "AppData"
+/// structure to access the
+/// pool of database connections.
+use super::units::AppData;
+
+/// Importing the "APIToken"
+/// structure for explicit
+/// typing.
+use super::units::APIToken;
+
+/// Importing the "JadeUser"
+/// structure for explicit
+/// typing.
+use super::units::JadeUser;
+
+/// Importing the "Payload"
+/// structure from "actix-web"
+/// for the extractor signature.
+use actix_web::dev::Payload;
+
+/// Importing the function
+/// to retrieve an active API
+/// token.
+use super::rw::get_api_token;
+
+/// Importing the function
+/// to retrieve a user by their
+/// username.
+use super::rw::get_user_by_handle;
+
+/// Importing the function
+/// to record when an API token
+/// was last used.
+use super::rw::record_token_use;
+
+/// Importing the function
+/// to work out the address
+/// of the client that sent
+/// a request.
+use super::ratelimit::client_ip;
+
+/// Importing the enum
+/// containing all actions that
+/// require authorization.
+use super::scopes::JadeAction;
+
+/// Importing the function
+/// to check whether a token
+/// has been granted a scope.
+use super::scopes::require_scope;
+
+/// Importing the function
+/// to retrieve a browser
+/// session.
+use super::rw::get_session;
+
+/// Importing the structure
+/// containing information on a
+/// browser session.
+use super::units::JadeSession;
+
+/// Importing the function
+/// to load an API token again.
+use super::rw::refresh_api_token;
+
+/// Importing the function
+/// to load a browser session
+/// again.
+use super::rw::refresh_session;
+
+/// Importing the "Pool"
+/// structure to talk to the
+/// database.
+use sqlx::Pool;
+
+/// Importing the "Postgres"
+/// structure to talk to the
+/// database.
+use sqlx::Postgres;
+
+/// Importing the function
+/// to check the CSRF token of
+/// a request.
+use super::sessions::check_csrf;
+
+/// Importing the name of the
+/// session cookie.
+use super::sessions::SESSION_COOKIE;
+
+/// Importing the "LocalBoxFuture"
+/// type for the extractor's
+/// future.
+use futures_util::future::LocalBoxFuture;
+
+/// Importing the "AUTHORIZATION"
+/// header name.
+use actix_web::http::header::AUTHORIZATION;
+
+/// A structure containing the user a request
+/// was authenticated as and either the API token
+/// or the browser session the request was made
+/// with. Handlers receive it by listing it as an
+/// argument. Requests without a valid
+/// "Authorization: Bearer <token>" header or
+/// session cookie are rejected before the
+/// handler runs.
+#[derive(Clone)]
+pub struct AuthedUser {
+    pub user: JadeUser,
+    pub token: Option<APIToken>,
+    pub session: Option<JadeSession>
+}
+
+/// Implementing functions
+/// for the "AuthedUser" structure.
+impl AuthedUser {
+
+    /// Attempts to check whether the token a
+    /// request was authenticated with may carry
+    /// out the given action for the user it belongs
+    /// to. Browser sessions were started with the
+    /// user's password and may carry out every action.
+    /// Every route that changes a user's data calls
+    /// this before touching the database. If the
+    /// action is not permitted, an error is returned.
+    pub fn authorize(&self, action: JadeAction) -> Result<(), JadeErr> {
+        match &self.token {
+            Some(token) => require_scope(token, action.required_scope()),
+            None => match &self.session {
+                Some(session) if session.username == self.user.username => Ok(()),
+                _ => {
+                    let e: String = "The request was not authenticated.".to_string();
+                    Err::<(), JadeErr>(JadeErr::unauthorized(&e.to_string()))
+                }
+            }
+        }
+    }
+}
+
+/// Attempts to check that the credentials a
+/// long-lived connection was authenticated with
+/// are still valid and that the user may still
+/// use their account. Tokens that were revoked or
+/// have expired and sessions that have ended are
+/// rejected. If this operation succeeds, the current
+/// state of the credentials and the user is returned.
+/// If this operation fails, an error is returned.
+pub async fn refresh_auth(
+    auth: &AuthedUser,
+    pool: &Pool<Postgres>
+) -> Result<AuthedUser, JadeErr> {
+    let token: Option<APIToken> = match &auth.token {
+        Some(token) => match refresh_api_token(token, pool).await {
+            Ok(token) => Some(token),
+            Err(e) => return Err::<AuthedUser, JadeErr>(e)
+        },
+        None => None
+    };
+    let session: Option<JadeSession> = match &auth.session {
+        Some(session) => match refresh_session(session, pool).await {
+            Ok(session) => Some(session),
+            Err(e) => return Err::<AuthedUser, JadeErr>(e)
+        },
+        None => None
+    };
+    let user: JadeUser = match get_user_by_handle(&auth.user.username, pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    let _verified_check: () = match require_active_account(&user) {
+        Ok(_verified_check) => _verified_check,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    Ok(AuthedUser { user: user, token: token, session: session })
+}
+
+/// Attempts to check that a user may use their
+/// account. Users who have not verified their email
+/// address are limited to verifying it and asking for
+/// a new verification email. Users whose account is
+/// scheduled for deletion can only undo the deletion.
+/// If the user may not use their account, an error is
+/// returned.
+pub fn require_active_account(user: &JadeUser) -> Result<(), JadeErr> {
+    if user.purge_at.is_some() {
+        let e: String = format!("The account of user \"{}\" is scheduled for deletion. Use the link in the email sent to you to undo this.", &user.username);
+        Err::<(), JadeErr>(JadeErr::forbidden(&e.to_string()))
+    }
+    else if user.is_active {
+        Ok(())
+    }
+    else {
+        let e: String = format!("User \"{}\" has not verified their email address yet.", &user.username);
+        Err::<(), JadeErr>(JadeErr::forbidden(&e.to_string()))
+    }
+}
+
+/// Attempts to read the API token from the
+/// "Authorization" header of a request. If this
+/// operation fails, an error is returned.
+pub fn bearer_token(req: &HttpRequest) -> Result<String, JadeErr> {
+    let header: &str = match req.headers().get(AUTHORIZATION) {
+        Some(header) => match header.to_str() {
+            Ok(header) => header,
+            Err(_e) => {
+                let e: String = "The \"Authorization\" header must only contain visible ASCII characters.".to_string();
+                return Err::<String, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+            }
+        },
+        None => {
+            let e: String = "No \"Authorization\" header was supplied.".to_string();
+            return Err::<String, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    match header.strip_prefix("Bearer ") {
+        Some(token) => Ok(token.trim().to_string()),
+        None => {
+            let e: String = "The \"Authorization\" header must contain a bearer token.".to_string();
+            Err::<String, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    }
+}
+
+/// Attempts to resolve the API token and the
+/// user a request was made with and records the
+/// time and client address of the request on the
+/// token. If this operation succeeds, an instance
+/// of the "AuthedUser" structure is returned. If
+/// this operation fails, an error is returned.
+pub async fn authenticate_token(
+    req: &HttpRequest,
+    data: &Data<AppData>
+) -> Result<AuthedUser, JadeErr> {
+    let api_token: String = match bearer_token(req) {
+        Ok(api_token) => api_token,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    let token: APIToken = match get_api_token(&api_token, &data.pool).await {
+        Ok(token) => token,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    let user: JadeUser = match get_user_by_handle(&token.username, &data.pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    let _verified_check: () = match require_active_account(&user) {
+        Ok(_verified_check) => _verified_check,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    let client_ip: Option<String> = client_ip(req, &data.auth_limits.trusted_proxies);
+    let _record_op: () = match record_token_use(&token, &client_ip, &data.pool).await {
+        Ok(_record_op) => _record_op,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    Ok(AuthedUser { user: user, token: Some(token), session: None })
+}
+
+/// Attempts to resolve the browser session and
+/// the user a request was made with from the session
+/// cookie without checking the session's CSRF token.
+/// Callers must check the CSRF token themselves. If
+/// this operation succeeds, an instance of the
+/// "AuthedUser" structure is returned. If this
+/// operation fails, an error is returned.
+pub async fn session_user(
+    req: &HttpRequest,
+    data: &Data<AppData>
+) -> Result<AuthedUser, JadeErr> {
+    let session_id: String = match req.cookie(SESSION_COOKIE) {
+        Some(cookie) => cookie.value().to_string(),
+        None => {
+            let e: String = "No \"Authorization\" header or session cookie was supplied.".to_string();
+            return Err::<AuthedUser, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    let session: JadeSession = match get_session(&session_id, &data.pool).await {
+        Ok(session) => session,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    let user: JadeUser = match get_user_by_handle(&session.username, &data.pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    let _verified_check: () = match require_active_account(&user) {
+        Ok(_verified_check) => _verified_check,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    Ok(AuthedUser { user: user, token: None, session: Some(session) })
+}
+
+/// Attempts to resolve the browser session and
+/// the user a request was made with from the session
+/// cookie. State-changing requests must carry the
+/// session's CSRF token. If this operation succeeds,
+/// an instance of the "AuthedUser" structure is
+/// returned. If this operation fails, an error is
+/// returned.
+pub async fn authenticate_session(
+    req: &HttpRequest,
+    data: &Data<AppData>
+) -> Result<AuthedUser, JadeErr> {
+    let auth: AuthedUser = match session_user(req, data).await {
+        Ok(auth) => auth,
+        Err(e) => return Err::<AuthedUser, JadeErr>(e)
+    };
+    if let Some(session) = &auth.session {
+        let _csrf_check: () = match check_csrf(req, session) {
+            Ok(_csrf_check) => _csrf_check,
+            Err(e) => return Err::<AuthedUser, JadeErr>(e)
+        };
+    }
+    Ok(auth)
+}
+
+/// Attempts to authenticate a request. Requests
+/// with an "Authorization" header are authenticated
+/// with the API token in it, all other requests with
+/// their session cookie. If this operation succeeds,
+/// an instance of the "AuthedUser" structure is
+/// returned. If this operation fails, an error is
+/// returned.
+pub async fn authenticate_request(req: &HttpRequest) -> Result<AuthedUser, JadeErr> {
+    let data: &Data<AppData> = match req.app_data::<Data<AppData>>() {
+        Some(data) => data,
+        None => {
+            let e: String = "App data is not available.".to_string();
+            return Err::<AuthedUser, JadeErr>(JadeErr::new(&e.to_string()))
+        }
+    };
+    if req.headers().contains_key(AUTHORIZATION) {
+        authenticate_token(req, data).await
+    }
+    else {
+        authenticate_session(req, data).await
+    }
+}
+
+/// Implementing the "FromRequest"
+/// trait so that handlers can receive
+/// the authenticated user as an argument.
+impl FromRequest for AuthedUser {
+    type Error = JadeErr;
+    type Future = LocalBoxFuture<'static, Result<AuthedUser, JadeErr>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req: HttpRequest = req.clone();
+        Box::pin(async move { authenticate_request(&req).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::AuthedUser;
+    use super::super::oauth::translate_mastodon_scopes;
+    use super::super::scopes::*;
+    use super::super::units::APIToken;
+    use super::super::units::JadeSession;
+    use super::super::units::JadeUser;
+
+    /// Every action with the one scope
+    /// that must allow it.
+    const ACTIONS: [(JadeAction, &str); 10] = [
+        (JadeAction::SetMood, MOOD_WRITE),
+        (JadeAction::DeleteMood, MOOD_WRITE),
+        (JadeAction::ReadFile, FILES_READ),
+        (JadeAction::UploadFile, FILES_WRITE),
+        (JadeAction::ChangePassword, ACCOUNT_PASSWORD),
+        (JadeAction::ChangeEmail, ACCOUNT_EMAIL),
+        (JadeAction::DeleteUser, ACCOUNT_DELETE),
+        (JadeAction::ManageTwoFactor, ACCOUNT_TWO_FACTOR),
+        (JadeAction::ExportData, ACCOUNT_EXPORT),
+        (JadeAction::ImportData, ACCOUNT_IMPORT)
+    ];
+
+    fn user() -> JadeUser {
+        JadeUser {
+            username: "alice".to_string(),
+            email: String::new(),
+            pwd: String::new(),
+            email_token: String::new(),
+            is_active: true,
+            totp_secret: None,
+            totp_enabled: false,
+            email_token_expires_at: None,
+            verification_resend_after: None,
+            email_lookup: None,
+            purge_at: None,
+            deletion_undo_hash: None,
+            totp_last_step: None
+        }
+    }
+
+    fn with_token(scopes: Vec<String>, is_active: bool) -> AuthedUser {
+        AuthedUser {
+            user: user(),
+            token: Some(
+                APIToken {
+                    username: "alice".to_string(),
+                    token_prefix: "prefix".to_string(),
+                    token_hash: String::new(),
+                    created_at: String::new(),
+                    is_active,
+                    scopes,
+                    expires_at: None,
+                    last_used_at: None,
+                    last_used_ip: None,
+                    revoked_at: None,
+                    client_id: None
+                }
+            ),
+            session: None
+        }
+    }
+
+    #[test]
+    fn each_scope_allows_only_its_actions() {
+        for scope in ALL_SCOPES {
+            let auth: AuthedUser = with_token(vec![scope.to_string()], true);
+            for (action, required) in ACTIONS {
+                assert_eq!(
+                    auth.authorize(action).is_ok(),
+                    scope == required,
+                    "{:?} with scope {}",
+                    action,
+                    scope
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tokens_without_scopes_are_denied() {
+        let auth: AuthedUser = with_token(Vec::new(), true);
+        for (action, _required) in ACTIONS {
+            assert!(auth.authorize(action).is_err(), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn revoked_tokens_are_denied() {
+        let scopes: Vec<String> = ALL_SCOPES.iter().map(|scope| scope.to_string()).collect();
+        let auth: AuthedUser = with_token(scopes, false);
+        for (action, _required) in ACTIONS {
+            assert!(auth.authorize(action).is_err(), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn sessions_allow_every_action() {
+        let auth: AuthedUser = AuthedUser {
+            user: user(),
+            token: None,
+            session: Some(
+                JadeSession {
+                    session_hash: String::new(),
+                    username: "alice".to_string(),
+                    csrf_token: String::new(),
+                    created_at: String::new(),
+                    expires_at: String::new()
+                }
+            )
+        };
+        for (action, _required) in ACTIONS {
+            assert!(auth.authorize(action).is_ok(), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn unauthenticated_requests_are_denied() {
+        let auth: AuthedUser = AuthedUser { user: user(), token: None, session: None };
+        for (action, _required) in ACTIONS {
+            assert!(auth.authorize(action).is_err(), "{:?}", action);
+        }
+    }
+
+    #[test]
+    fn oauth_scopes_allow_only_their_actions() {
+        let table: [(&str, Vec<JadeAction>); 3] = [
+            ("read", vec![JadeAction::ReadFile]),
+            ("write", vec![JadeAction::SetMood, JadeAction::DeleteMood, JadeAction::UploadFile]),
+            ("read write follow push", vec![JadeAction::SetMood, JadeAction::DeleteMood, JadeAction::ReadFile, JadeAction::UploadFile])
+        ];
+        for (requested, allowed) in table {
+            let scopes: Vec<String> = translate_mastodon_scopes(&Some(requested.to_string()));
+            let auth: AuthedUser = with_token(scopes, true);
+            for (action, _required) in ACTIONS {
+                assert_eq!(
+                    auth.authorize(action).is_ok(),
+                    allowed.contains(&action),
+                    "{:?} with OAuth scopes {}",
+                    action,
+                    requested
+                );
+            }
+        }
+    }
+}
\ No newline at end of file
@@ -26,6 +26,149 @@ use super::runner::run_app;
 /// structure for explicit typing.
 use super::units::ConfigData;
 
+/// Importing the "FromStr"
+/// trait to parse optional
+/// settings.
+use std::str::FromStr;
+
+/// Importing the structure
+/// containing the limits placed
+/// on authentication endpoints.
+use super::units::AuthLimitConfig;
+
+/// Importing the "IpAddr"
+/// enum to parse the addresses
+/// of trusted proxies.
+use std::net::IpAddr;
+
+/// Importing the structure
+/// containing the request budgets
+/// of all routes.
+use super::units::ApiLimitConfig;
+
+/// Importing the function
+/// to export a user's data
+/// into a file.
+use super::export::export_user_to_file;
+
+/// Attempts to read an optional setting
+/// from the environment variable with the
+/// given name. If the variable is not set,
+/// the given default is returned. If the
+/// variable cannot be parsed, an error is
+/// returned.
+pub fn var_or_default<T: FromStr>(name: &str, default: T) -> Result<T, JadeErr> {
+    match var(name){
+        Ok(value) => match value.parse::<T>(){
+            Ok(parsed) => Ok(parsed),
+            Err(_e) => {
+                let e: String = format!("Could not parse the environment variable \"{}\".", name);
+                Err::<T, JadeErr>(JadeErr::new(&e.to_string()))
+            }
+        },
+        Err(_e) => Ok(default)
+    }
+}
+
+/// Attempts to read the addresses of the
+/// reverse proxies whose "X-Forwarded-For"
+/// headers are trusted from the comma-separated
+/// list in the given environment variable. If
+/// the variable is not set, no proxy is trusted.
+/// If an address cannot be parsed, an error is
+/// returned.
+pub fn ip_list_from_env(name: &str) -> Result<Vec<IpAddr>, JadeErr> {
+    let list: String = match var(name){
+        Ok(list) => list,
+        Err(_e) => return Ok(Vec::new())
+    };
+    let mut addrs: Vec<IpAddr> = Vec::new();
+    for entry in list.split(',').map(|entry| entry.trim()).filter(|entry| !entry.is_empty()) {
+        match entry.parse::<IpAddr>(){
+            Ok(addr) => addrs.push(addr),
+            Err(_e) => {
+                let e: String = format!("Could not parse the environment variable \"{}\".", name);
+                return Err::<Vec<IpAddr>, JadeErr>(JadeErr::new(&e.to_string()))
+            }
+        }
+    }
+    Ok(addrs)
+}
+
+/// Attempts to read the limits placed on
+/// authentication endpoints from the environment.
+/// Settings that are not set fall back to their
+/// defaults. If this operation fails, an error
+/// is returned.
+pub fn auth_limits_from_env() -> Result<AuthLimitConfig, JadeErr> {
+    let ip_limit: i32 = match var_or_default("AUTH_RATE_LIMIT", 20){
+        Ok(ip_limit) => ip_limit,
+        Err(e) => return Err::<AuthLimitConfig, JadeErr>(e)
+    };
+    let window_secs: i64 = match var_or_default("AUTH_RATE_WINDOW_SECS", 60){
+        Ok(window_secs) => window_secs,
+        Err(e) => return Err::<AuthLimitConfig, JadeErr>(e)
+    };
+    let max_failures: i32 = match var_or_default("AUTH_MAX_FAILURES", 5){
+        Ok(max_failures) => max_failures,
+        Err(e) => return Err::<AuthLimitConfig, JadeErr>(e)
+    };
+    let lockout_secs: i64 = match var_or_default("AUTH_LOCKOUT_SECS", 900){
+        Ok(lockout_secs) => lockout_secs,
+        Err(e) => return Err::<AuthLimitConfig, JadeErr>(e)
+    };
+    let store: String = match var_or_default("RATE_LIMIT_STORE", "memory".to_string()){
+        Ok(store) => store,
+        Err(e) => return Err::<AuthLimitConfig, JadeErr>(e)
+    };
+    let trusted_proxies: Vec<IpAddr> = match ip_list_from_env("TRUSTED_PROXIES"){
+        Ok(trusted_proxies) => trusted_proxies,
+        Err(e) => return Err::<AuthLimitConfig, JadeErr>(e)
+    };
+    Ok(
+        AuthLimitConfig {
+            ip_limit: ip_limit,
+            window_secs: window_secs,
+            max_failures: max_failures,
+            lockout_secs: lockout_secs,
+            store: store,
+            trusted_proxies: trusted_proxies
+        }
+    )
+}
+
+/// Attempts to read the request budgets of
+/// the groups of routes from the environment.
+/// Settings that are not set fall back to their
+/// defaults. If this operation fails, an error
+/// is returned.
+pub fn api_limits_from_env() -> Result<ApiLimitConfig, JadeErr> {
+    let default_limit: i32 = match var_or_default("API_RATE_LIMIT", 300){
+        Ok(default_limit) => default_limit,
+        Err(e) => return Err::<ApiLimitConfig, JadeErr>(e)
+    };
+    let moods_limit: i32 = match var_or_default("MOODS_RATE_LIMIT", 60){
+        Ok(moods_limit) => moods_limit,
+        Err(e) => return Err::<ApiLimitConfig, JadeErr>(e)
+    };
+    let files_limit: i32 = match var_or_default("FILES_RATE_LIMIT", 30){
+        Ok(files_limit) => files_limit,
+        Err(e) => return Err::<ApiLimitConfig, JadeErr>(e)
+    };
+    let window_secs: i64 = match var_or_default("API_RATE_WINDOW_SECS", 60){
+        Ok(window_secs) => window_secs,
+        Err(e) => return Err::<ApiLimitConfig, JadeErr>(e)
+    };
+    Ok(
+        ApiLimitConfig {
+            default_limit: default_limit,
+            moods_limit: moods_limit,
+            files_limit: files_limit,
+            window_secs: window_secs
+        }
+    )
+}
+
 /// The function containing this app's
 /// tiny CLI.
 pub async fn cli() -> Result<String, JadeErr>{
@@ -36,6 +179,7 @@ pub async fn cli() -> Result<String, JadeErr>{
         "Alyx Shang"
     );
     mocha.add_arg("runa", "run the application", &false);
+    mocha.add_arg("export", "export a user's data into a ZIP archive", &true);
     if mocha.version_is(){
         result = mocha.version_info();
     }
@@ -59,18 +203,69 @@ pub async fn cli() -> Result<String, JadeErr>{
             Ok(port) => port,
             Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
         };
+        let api_domain: String = match var("API_DOMAIN"){
+            Ok(api_domain) if !api_domain.is_empty() => api_domain,
+            _ => {
+                let fallback: String = format!("{}:{}", &host, &port);
+                log::warn!("\"API_DOMAIN\" is not set, links will point to \"{}\".", &fallback);
+                fallback
+            }
+        };
+        let email_key: String = match var("EMAIL_ENCRYPTION_KEY"){
+            Ok(email_key) => email_key,
+            Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+        };
+        let deletion_grace_secs: i64 = match var_or_default("ACCOUNT_DELETION_GRACE_SECS", 604800){
+            Ok(deletion_grace_secs) => deletion_grace_secs,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
+        let auth_limits: AuthLimitConfig = match auth_limits_from_env(){
+            Ok(auth_limits) => auth_limits,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
+        let api_limits: ApiLimitConfig = match api_limits_from_env(){
+            Ok(api_limits) => api_limits,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
         let config: ConfigData = ConfigData::new(
             &db_url,
             &host,
             &port,
-            &smtp_server
+            &smtp_server,
+            &api_domain,
+            &auth_limits,
+            &api_limits,
+            &email_key,
+            &deletion_grace_secs
         );
         let _runner: () = match run_app(&config).await{
             Ok(_runner) => _runner,
-            Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+            Err(e) => return Err::<String, JadeErr>(e)
         };
         result = format!("App running on \"{}:{}\".", &host, &port);
     }
+    else if mocha.arg_was_used("export"){
+        let username: String = match mocha.get_arg_data("export"){
+            Some(username) => username,
+            None => {
+                let e: String = "No username was supplied to export.".to_string();
+                return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+            }
+        };
+        let db_url: String = match var("DATABASE_URL"){
+            Ok(db_url) => db_url,
+            Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+        };
+        let email_key: String = match var("EMAIL_ENCRYPTION_KEY"){
+            Ok(email_key) => email_key,
+            Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+        };
+        let path: String = match export_user_to_file(&db_url, &email_key, &username).await{
+            Ok(path) => path,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
+        result = format!("Data of \"{}\" exported to \"{}\".", &username, &path);
+    }
     else {
         result = mocha.help_info();
     }
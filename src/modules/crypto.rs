@@ -0,0 +1,350 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "Rng"
+/// trait to generate nonces.
+use rand::Rng;
+
+/// Importing the "Mac"
+/// trait to compute keyed
+/// hashes.
+use hmac::Mac;
+
+/// Importing the "Hmac"
+/// structure to compute keyed
+/// hashes.
+use hmac::Hmac;
+
+/// Importing the "Sha256"
+/// structure to use with HMAC.
+use sha2::Sha256;
+
+/// Importing the "Engine"
+/// trait to encode ciphertexts.
+use base64::Engine;
+
+/// Importing the "Nonce"
+/// structure to pass nonces
+/// to the cipher.
+use aes_gcm::Nonce;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "Aes256Gcm"
+/// structure to encrypt email
+/// addresses.
+use aes_gcm::Aes256Gcm;
+
+/// Importing the "Aead"
+/// trait to encrypt and
+/// decrypt with the cipher.
+use aes_gcm::aead::Aead;
+
+/// Importing the "KeyInit"
+/// trait to create the cipher
+/// from a key.
+use aes_gcm::aead::KeyInit;
+
+/// Importing the standard
+/// base64 engine.
+use base64::engine::general_purpose::STANDARD;
+
+/// The prefix of email addresses
+/// stored encrypted. Addresses without
+/// it were stored as bcrypt hashes by
+/// earlier versions of Jade.
+pub const ENCRYPTED_EMAIL_PREFIX: &str = "v1:";
+
+/// The number of bytes in
+/// the server key email addresses
+/// are protected with.
+pub const EMAIL_KEY_LENGTH: usize = 32;
+
+/// The number of bytes in
+/// an AES-GCM nonce.
+pub const NONCE_LENGTH: usize = 12;
+
+/// A structure containing the keys
+/// email addresses are encrypted and
+/// looked up with and the key TOTP secrets
+/// are encrypted with. All keys are derived
+/// from the server key so that a single
+/// secret has to be configured.
+#[derive(Clone)]
+pub struct EmailKeys {
+    pub cipher_key: Vec<u8>,
+    pub lookup_key: Vec<u8>,
+    pub totp_key: Vec<u8>
+}
+
+/// Implementing functions
+/// for the "EmailKeys" structure.
+impl EmailKeys {
+
+    /// Attempts to derive the keys from
+    /// the base64-encoded server key. The
+    /// server key must be 32 bytes long. If
+    /// this operation fails, an error is
+    /// returned.
+    pub fn new(server_key: &String) -> Result<EmailKeys, JadeErr> {
+        let decoded: Vec<u8> = match STANDARD.decode(server_key.trim()) {
+            Ok(decoded) => decoded,
+            Err(e) => return Err::<EmailKeys, JadeErr>(JadeErr::new(&e.to_string()))
+        };
+        if decoded.len() != EMAIL_KEY_LENGTH {
+            let e: String = format!("The email encryption key must be {} bytes long.", EMAIL_KEY_LENGTH);
+            return Err::<EmailKeys, JadeErr>(JadeErr::new(&e.to_string()))
+        }
+        let cipher_key: Vec<u8> = match keyed_hash(&decoded, b"jade-email-encryption") {
+            Ok(cipher_key) => cipher_key,
+            Err(e) => return Err::<EmailKeys, JadeErr>(e)
+        };
+        let lookup_key: Vec<u8> = match keyed_hash(&decoded, b"jade-email-lookup") {
+            Ok(lookup_key) => lookup_key,
+            Err(e) => return Err::<EmailKeys, JadeErr>(e)
+        };
+        let totp_key: Vec<u8> = match keyed_hash(&decoded, b"jade-totp-encryption") {
+            Ok(totp_key) => totp_key,
+            Err(e) => return Err::<EmailKeys, JadeErr>(e)
+        };
+        Ok(EmailKeys { cipher_key: cipher_key, lookup_key: lookup_key, totp_key: totp_key })
+    }
+}
+
+/// Attempts to compute the HMAC-SHA256
+/// of the given data with the given key.
+/// If this operation fails, an error is
+/// returned.
+pub fn keyed_hash(key: &[u8], data: &[u8]) -> Result<Vec<u8>, JadeErr> {
+    let mut mac: Hmac<Sha256> = match <Hmac<Sha256> as Mac>::new_from_slice(key) {
+        Ok(mac) => mac,
+        Err(e) => return Err::<Vec<u8>, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    mac.update(data);
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// Brings an email address into the
+/// form addresses are compared in.
+pub fn normalize_email(email: &String) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Checks whether a stored email
+/// address is encrypted.
+pub fn is_encrypted_email(stored: &String) -> bool {
+    stored.starts_with(ENCRYPTED_EMAIL_PREFIX)
+}
+
+/// Attempts to compute the deterministic
+/// hash an email address is looked up and
+/// checked for uniqueness with. If this
+/// operation fails, an error is returned.
+pub fn email_lookup(keys: &EmailKeys, email: &String) -> Result<String, JadeErr> {
+    match keyed_hash(&keys.lookup_key, normalize_email(email).as_bytes()) {
+        Ok(hashed) => Ok(hashed.iter().map(|byte| format!("{:02x}", byte)).collect()),
+        Err(e) => Err::<String, JadeErr>(e)
+    }
+}
+
+/// Attempts to encrypt a value with
+/// AES-256-GCM under the given key and a
+/// fresh nonce. If this operation fails,
+/// an error is returned.
+pub fn encrypt_value(key: &[u8], value: &str) -> Result<String, JadeErr> {
+    let cipher: Aes256Gcm = match Aes256Gcm::new_from_slice(key) {
+        Ok(cipher) => cipher,
+        Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    let nonce_bytes: [u8; NONCE_LENGTH] = rand::thread_rng().gen();
+    let encrypted: Vec<u8> = match cipher.encrypt(Nonce::from_slice(&nonce_bytes), value.as_bytes()) {
+        Ok(encrypted) => encrypted,
+        Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    let mut sealed: Vec<u8> = nonce_bytes.to_vec();
+    sealed.extend(encrypted);
+    Ok(format!("{}{}", ENCRYPTED_EMAIL_PREFIX, STANDARD.encode(sealed)))
+}
+
+/// Attempts to decrypt a value encrypted
+/// with the given key. If the value is not
+/// encrypted or cannot be decrypted with
+/// the key, an error is returned.
+pub fn decrypt_value(key: &[u8], stored: &String) -> Result<String, JadeErr> {
+    let encoded: &str = match stored.strip_prefix(ENCRYPTED_EMAIL_PREFIX) {
+        Some(encoded) => encoded,
+        None => {
+            let e: String = "The stored value is not encrypted.".to_string();
+            return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+        }
+    };
+    let sealed: Vec<u8> = match STANDARD.decode(encoded) {
+        Ok(sealed) => sealed,
+        Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    if sealed.len() <= NONCE_LENGTH {
+        let e: String = "The stored value is malformed.".to_string();
+        return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    }
+    let (nonce_bytes, encrypted) = sealed.split_at(NONCE_LENGTH);
+    let cipher: Aes256Gcm = match Aes256Gcm::new_from_slice(key) {
+        Ok(cipher) => cipher,
+        Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    let decrypted: Vec<u8> = match cipher.decrypt(Nonce::from_slice(nonce_bytes), encrypted) {
+        Ok(decrypted) => decrypted,
+        Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    match String::from_utf8(decrypted) {
+        Ok(value) => Ok(value),
+        Err(e) => Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
+
+/// Attempts to encrypt an email address with
+/// AES-256-GCM under a fresh nonce. If this
+/// operation fails, an error is returned.
+pub fn encrypt_email(keys: &EmailKeys, email: &String) -> Result<String, JadeErr> {
+    encrypt_value(&keys.cipher_key, email.trim())
+}
+
+/// Attempts to decrypt a stored email address.
+/// If the address is not encrypted or cannot be
+/// decrypted with the configured key, an error
+/// is returned.
+pub fn decrypt_email(keys: &EmailKeys, stored: &String) -> Result<String, JadeErr> {
+    decrypt_value(&keys.cipher_key, stored)
+}
+
+/// Attempts to encrypt a TOTP secret with
+/// its own key, so that a copy of the database
+/// alone does not reveal the secrets. If this
+/// operation fails, an error is returned.
+pub fn encrypt_totp_secret(keys: &EmailKeys, secret: &String) -> Result<String, JadeErr> {
+    encrypt_value(&keys.totp_key, secret)
+}
+
+/// Attempts to decrypt a stored TOTP secret.
+/// If this operation fails, an error is returned.
+pub fn decrypt_totp_secret(keys: &EmailKeys, stored: &String) -> Result<String, JadeErr> {
+    decrypt_value(&keys.totp_key, stored)
+}
+
+/// Attempts to check whether a supplied email
+/// address matches a stored one. Encrypted
+/// addresses are decrypted and compared, and
+/// addresses stored as bcrypt hashes by earlier
+/// versions of Jade are checked against their
+/// hash. These addresses were hashed as they were
+/// typed, so the supplied address is checked both
+/// as it is and in the form addresses are compared
+/// in. If this operation fails, an error is returned.
+pub fn email_matches(
+    keys: &EmailKeys,
+    supplied: &String,
+    stored: &String
+) -> Result<bool, JadeErr> {
+    if is_encrypted_email(stored) {
+        match decrypt_email(keys, stored) {
+            Ok(email) => Ok(normalize_email(&email) == normalize_email(supplied)),
+            Err(e) => Err::<bool, JadeErr>(e)
+        }
+    }
+    else {
+        let normalized: String = normalize_email(supplied);
+        let candidates: Vec<&str> = if normalized == supplied.as_str() {
+            vec![supplied.as_str()]
+        }
+        else {
+            vec![supplied.as_str(), normalized.as_str()]
+        };
+        for candidate in candidates {
+            match bcrypt::verify(candidate, stored) {
+                Ok(true) => return Ok(true),
+                Ok(false) => {},
+                Err(e) => return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
+            }
+        }
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn test_keys(fill: u8) -> EmailKeys {
+        EmailKeys::new(&STANDARD.encode([fill; EMAIL_KEY_LENGTH])).unwrap()
+    }
+
+    #[test]
+    fn server_keys_must_be_32_bytes() {
+        assert!(EmailKeys::new(&STANDARD.encode([7u8; 16])).is_err());
+        assert!(EmailKeys::new(&"not base64!".to_string()).is_err());
+        let keys: EmailKeys = test_keys(7);
+        assert_ne!(keys.cipher_key, keys.lookup_key);
+        assert_ne!(keys.cipher_key, keys.totp_key);
+        assert_ne!(keys.lookup_key, keys.totp_key);
+    }
+
+    #[test]
+    fn addresses_survive_encryption() {
+        let keys: EmailKeys = test_keys(7);
+        let email: String = "alice@example.com".to_string();
+        let first: String = encrypt_email(&keys, &email).unwrap();
+        let second: String = encrypt_email(&keys, &email).unwrap();
+        assert!(is_encrypted_email(&first));
+        assert_ne!(first, second);
+        assert_eq!(decrypt_email(&keys, &first).unwrap(), email);
+        assert_eq!(decrypt_email(&keys, &second).unwrap(), email);
+    }
+
+    #[test]
+    fn tampered_or_foreign_values_are_rejected() {
+        let keys: EmailKeys = test_keys(7);
+        let stored: String = encrypt_email(&keys, &"alice@example.com".to_string()).unwrap();
+        assert!(decrypt_email(&test_keys(8), &stored).is_err());
+        let mut sealed: Vec<u8> = STANDARD.decode(stored.strip_prefix(ENCRYPTED_EMAIL_PREFIX).unwrap()).unwrap();
+        let last: usize = sealed.len() - 1;
+        sealed[last] ^= 1;
+        let tampered: String = format!("{}{}", ENCRYPTED_EMAIL_PREFIX, STANDARD.encode(sealed));
+        assert!(decrypt_email(&keys, &tampered).is_err());
+        assert!(decrypt_email(&keys, &"alice@example.com".to_string()).is_err());
+        assert!(decrypt_email(&keys, &format!("{}AAAA", ENCRYPTED_EMAIL_PREFIX)).is_err());
+    }
+
+    #[test]
+    fn totp_secrets_use_their_own_key() {
+        let keys: EmailKeys = test_keys(7);
+        let secret: String = "JBSWY3DPEHPK3PXP".to_string();
+        let stored: String = encrypt_totp_secret(&keys, &secret).unwrap();
+        assert_eq!(decrypt_totp_secret(&keys, &stored).unwrap(), secret);
+        assert!(decrypt_email(&keys, &stored).is_err());
+    }
+
+    #[test]
+    fn lookups_ignore_case_and_surrounding_space() {
+        let keys: EmailKeys = test_keys(7);
+        let lookup: String = email_lookup(&keys, &"alice@example.com".to_string()).unwrap();
+        assert_eq!(email_lookup(&keys, &" Alice@Example.COM ".to_string()).unwrap(), lookup);
+        assert_ne!(email_lookup(&keys, &"bob@example.com".to_string()).unwrap(), lookup);
+        assert_ne!(email_lookup(&test_keys(8), &"alice@example.com".to_string()).unwrap(), lookup);
+    }
+
+    #[test]
+    fn encrypted_and_legacy_addresses_are_matched() {
+        let keys: EmailKeys = test_keys(7);
+        let encrypted: String = encrypt_email(&keys, &"alice@example.com".to_string()).unwrap();
+        assert!(email_matches(&keys, &"ALICE@example.com".to_string(), &encrypted).unwrap());
+        assert!(!email_matches(&keys, &"bob@example.com".to_string(), &encrypted).unwrap());
+        let legacy: String = bcrypt::hash("alice@example.com", 4).unwrap();
+        assert!(email_matches(&keys, &"alice@example.com".to_string(), &legacy).unwrap());
+        assert!(email_matches(&keys, &"Alice@Example.com".to_string(), &legacy).unwrap());
+        assert!(!email_matches(&keys, &"bob@example.com".to_string(), &legacy).unwrap());
+    }
+}
\ No newline at end of file
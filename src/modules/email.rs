@@ -32,7 +32,6 @@ pub async fn send_email(
     msg: &String,
     server: &String
 ) -> Result<bool, JadeErr>{
-    let mut res: bool = false;
     let parsed_from = match from.parse(){
         Ok(parsed_from) => parsed_from,
         Err(_e) => {
@@ -60,9 +59,8 @@ pub async fn send_email(
         Ok(sender) => sender,
         Err(e) => return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
     };
-    let _result = match sender.build().send(&email){
-        Ok(_sender) => res = true,
-        Err(e) => return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    Ok(res)
+    match sender.build().send(&email){
+        Ok(_sender) => Ok(true),
+        Err(e) => Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
+    }
 }
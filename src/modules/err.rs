@@ -21,11 +21,171 @@ use std::fmt::Formatter;
 
 use actix_web::error;
 
+/// Importing the "Serialize"
+/// trait to derive it.
+use serde::Serialize;
+
+/// Importing the "HttpResponse"
+/// structure to build error
+/// responses.
+use actix_web::HttpResponse;
+
+/// Importing the structure
+/// containing a problem with a
+/// single field of a payload.
+use super::units::FieldError;
+
+/// Importing the "HttpRequest"
+/// structure to handle payload
+/// errors.
+use actix_web::HttpRequest;
+
+/// Importing the "JsonPayloadError"
+/// enum to handle JSON payloads
+/// that cannot be read.
+use actix_web::error::JsonPayloadError;
+
+/// Importing the "MultipartError"
+/// enum to handle multipart forms
+/// that cannot be read.
+use actix_multipart::MultipartError;
+
+/// Importing the error
+/// structure of "sqlx" to
+/// map database errors.
+use sqlx::Error as SqlxError;
+
+/// Importing the "StatusCode"
+/// enum to map errors to
+/// status codes.
+use actix_web::http::StatusCode;
+
+/// The message sent to clients
+/// instead of the details of
+/// internal errors.
+pub const INTERNAL_ERROR_MESSAGE: &str = "An internal error occurred.";
+
+/// The SQLSTATE code Postgres
+/// reports when a row would break
+/// a unique constraint.
+pub const UNIQUE_VIOLATION_CODE: &str = "23505";
+
+/// The message clients receive
+/// when a write clashes with a
+/// row that is already stored.
+pub const UNIQUE_VIOLATION_MESSAGE: &str = "This clashes with something that is already stored.";
+
+/// An enum describing the
+/// kinds of errors that can
+/// occur. Each kind maps to
+/// an HTTP status code.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum JadeErrKind {
+    NotFound,
+    Unauthorized,
+    Forbidden,
+    Validation,
+    Conflict,
+    Internal
+}
+
+/// Implements functions
+/// for the "JadeErrKind"
+/// enum.
+impl JadeErrKind {
+
+    /// Returns the HTTP status
+    /// code errors of this kind
+    /// are sent with.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            JadeErrKind::NotFound => StatusCode::NOT_FOUND,
+            JadeErrKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            JadeErrKind::Forbidden => StatusCode::FORBIDDEN,
+            JadeErrKind::Validation => StatusCode::UNPROCESSABLE_ENTITY,
+            JadeErrKind::Conflict => StatusCode::CONFLICT,
+            JadeErrKind::Internal => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    /// Returns the machine-readable
+    /// code clients receive for errors
+    /// of this kind.
+    pub fn code(&self) -> &'static str {
+        match self {
+            JadeErrKind::NotFound => "not_found",
+            JadeErrKind::Unauthorized => "unauthorized",
+            JadeErrKind::Forbidden => "forbidden",
+            JadeErrKind::Validation => "validation",
+            JadeErrKind::Conflict => "conflict",
+            JadeErrKind::Internal => "internal"
+        }
+    }
+}
+
+/// A structure containing
+/// the code and the message of
+/// an error sent to a client.
+#[derive(Serialize)]
+pub struct ErrorDetails {
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>
+}
+
+/// A structure containing
+/// the body of an error
+/// response.
+#[derive(Serialize)]
+pub struct ErrorBody {
+    pub error: ErrorDetails
+}
+
+/// Builds the JSON body of an
+/// error response with the given
+/// code and message.
+pub fn error_body(code: &str, message: &str) -> ErrorBody {
+    ErrorBody {
+        error: ErrorDetails {
+            code: code.to_string(),
+            message: message.to_string(),
+            fields: Vec::new()
+        }
+    }
+}
+
+/// Builds the response sent to
+/// clients whose payload has problems
+/// with the given fields.
+pub fn validation_response(fields: Vec<FieldError>) -> HttpResponse {
+    let mut body: ErrorBody = error_body(JadeErrKind::Validation.code(), "The payload contains invalid fields.");
+    body.error.fields = fields;
+    HttpResponse::build(JadeErrKind::Validation.status_code()).json(body)
+}
+
+/// Turns a JSON payload that cannot
+/// be read into a validation error so
+/// that it is sent to clients like
+/// every other error.
+pub fn json_payload_error(err: JsonPayloadError, _req: &HttpRequest) -> error::Error {
+    JadeErr::validation(&err.to_string()).into()
+}
+
+/// Turns a multipart form that cannot
+/// be read into a validation error so
+/// that it is sent to clients like
+/// every other error.
+pub fn multipart_payload_error(err: MultipartError, _req: &HttpRequest) -> error::Error {
+    JadeErr::validation(&err.to_string()).into()
+}
+
 /// A data structure for
 /// storing and handling errors.
 #[derive(Clone,Eq,PartialEq, Debug)]
 pub struct JadeErr {
-    pub details: String
+    pub details: String,
+    pub kind: JadeErrKind
 }
 
 /// Implements generic methods.
@@ -33,17 +193,73 @@ impl JadeErr {
 
     /// Implements a generic method to create
     /// a new instance of this data structure.
+    /// Errors created with this method are
+    /// internal errors.
     pub fn new(details: &str) -> JadeErr {
+        JadeErr::with_kind(JadeErrKind::Internal, details)
+    }
+
+    /// Implements a generic method to create
+    /// a new instance of this data structure
+    /// of the given kind.
+    pub fn with_kind(kind: JadeErrKind, details: &str) -> JadeErr {
         JadeErr {
-            details: details.to_owned()
+            details: details.to_owned(),
+            kind
         }
     }
 
-    /// Implements a generic method to return
-    /// a string representation of this 
-    /// data structure.
-    pub fn to_string(self) -> String {
-        return self.details.to_string();
+    /// Creates an error for
+    /// something that does not
+    /// exist.
+    pub fn not_found(details: &str) -> JadeErr {
+        JadeErr::with_kind(JadeErrKind::NotFound, details)
+    }
+
+    /// Creates an error for
+    /// missing or wrong
+    /// credentials.
+    pub fn unauthorized(details: &str) -> JadeErr {
+        JadeErr::with_kind(JadeErrKind::Unauthorized, details)
+    }
+
+    /// Creates an error for
+    /// something the client
+    /// may not do.
+    pub fn forbidden(details: &str) -> JadeErr {
+        JadeErr::with_kind(JadeErrKind::Forbidden, details)
+    }
+
+    /// Creates an error for
+    /// input that is not valid.
+    pub fn validation(details: &str) -> JadeErr {
+        JadeErr::with_kind(JadeErrKind::Validation, details)
+    }
+
+    /// Creates an error for
+    /// something that clashes with
+    /// what is already stored.
+    pub fn conflict(details: &str) -> JadeErr {
+        JadeErr::with_kind(JadeErrKind::Conflict, details)
+    }
+
+    /// Creates an error for a failed
+    /// database operation. Writes that
+    /// break a unique constraint are
+    /// conflicts, everything else is an
+    /// internal error.
+    pub fn database(err: &SqlxError) -> JadeErr {
+        let is_unique_violation: bool = match err.as_database_error() {
+            Some(db_err) => db_err.code().as_deref() == Some(UNIQUE_VIOLATION_CODE),
+            None => false
+        };
+        if is_unique_violation {
+            log::info!("Rejected a write breaking a unique constraint: {}", err);
+            JadeErr::conflict(UNIQUE_VIOLATION_MESSAGE)
+        }
+        else {
+            JadeErr::new(&err.to_string())
+        }
     }
 }
 
@@ -61,4 +277,87 @@ impl Display for JadeErr {
     }
 }
 
-impl error::ResponseError for JadeErr {}
\ No newline at end of file
+/// Implements the "ResponseError" trait
+/// so that errors are sent to clients with
+/// the status code of their kind and a JSON
+/// body. The details of internal errors are
+/// only logged and never sent to clients.
+impl error::ResponseError for JadeErr {
+    fn status_code(&self) -> StatusCode {
+        self.kind.status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let message: String = match self.kind {
+            JadeErrKind::Internal => {
+                log::error!("Internal error: {}", &self.details);
+                INTERNAL_ERROR_MESSAGE.to_string()
+            },
+            _ => self.details.clone()
+        };
+        HttpResponse::build(self.status_code()).json(error_body(self.kind.code(), &message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::borrow::Cow;
+    use sqlx::error::DatabaseError;
+    use sqlx::error::ErrorKind;
+
+    #[derive(Debug)]
+    struct FakeDbError {
+        code: &'static str
+    }
+
+    impl Display for FakeDbError {
+        fn fmt(&self, f: &mut Formatter) -> Result {
+            write!(f, "database error {}", self.code)
+        }
+    }
+
+    impl Error for FakeDbError {}
+
+    impl DatabaseError for FakeDbError {
+        fn message(&self) -> &str {
+            "database error"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.code))
+        }
+
+        fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::Other
+        }
+    }
+
+    #[test]
+    fn unique_violations_are_conflicts() {
+        let err: SqlxError = SqlxError::Database(Box::new(FakeDbError { code: "23505" }));
+        let mapped: JadeErr = JadeErr::database(&err);
+        assert_eq!(mapped.kind, JadeErrKind::Conflict);
+        assert_eq!(mapped.details, UNIQUE_VIOLATION_MESSAGE);
+    }
+
+    #[test]
+    fn other_database_errors_are_internal() {
+        let err: SqlxError = SqlxError::Database(Box::new(FakeDbError { code: "23503" }));
+        assert_eq!(JadeErr::database(&err).kind, JadeErrKind::Internal);
+        assert_eq!(JadeErr::database(&SqlxError::RowNotFound).kind, JadeErrKind::Internal);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,206 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the
+/// "Pool" structure
+/// from the "sqlx" crate
+/// to make a pool for
+/// database connections.
+use sqlx::Pool;
+
+/// Importing the "Bytes"
+/// structure to send chunks
+/// of a streamed response.
+use actix_web::web::Bytes;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "Stream"
+/// trait for explicit typing.
+use futures_util::Stream;
+
+/// Importing the "Duration"
+/// structure to space out
+/// keep-alive messages.
+use std::time::Duration;
+
+/// Importing the "HashSet"
+/// structure to store the
+/// watched usernames.
+use std::collections::HashSet;
+
+/// Importing the "StreamExt"
+/// trait to combine and filter
+/// streams.
+use futures_util::StreamExt;
+
+/// Importing the "Postgres"
+/// structure from the "sqlx"
+/// crate.
+use sqlx::postgres::Postgres;
+
+/// Importing the structure
+/// describing a change to a
+/// user's mood.
+use super::units::MoodEvent;
+
+/// Importing the "PgListener"
+/// structure to receive
+/// notifications from PostgreSQL.
+use sqlx::postgres::PgListener;
+
+/// Importing the "PgConnection"
+/// structure to publish events
+/// inside a transaction.
+use sqlx::postgres::PgConnection;
+
+/// Importing the "Sender"
+/// structure to hand mood updates
+/// to every open stream.
+use tokio::sync::broadcast::Sender;
+
+/// Importing the "Receiver"
+/// structure to receive mood
+/// updates in an open stream.
+use tokio::sync::broadcast::Receiver;
+
+/// Importing the "RecvError"
+/// enum to tell lagging streams
+/// from closed channels.
+use tokio::sync::broadcast::error::RecvError;
+
+/// The name of the PostgreSQL channel
+/// mood updates are published on.
+pub const MOOD_CHANNEL: &str = "jade_mood_updates";
+
+/// The number of seconds between
+/// keep-alive comments on an open
+/// event stream.
+pub const KEEP_ALIVE_SECS: u64 = 15;
+
+/// The number of mood updates
+/// kept for streams that fall
+/// behind.
+pub const MOOD_EVENT_CAPACITY: usize = 256;
+
+/// The number of seconds to wait
+/// before listening again after the
+/// listener lost its connection.
+pub const LISTENER_RETRY_SECS: u64 = 5;
+
+/// Attempts to publish a mood event on the
+/// PostgreSQL channel for mood updates so that
+/// every running Jade process can forward it to
+/// its listeners. The event should be published
+/// on the transaction that changes the mood, so
+/// that it is only delivered once the change is
+/// committed. If the operation fails, an error
+/// is returned.
+pub async fn publish_mood_event(
+    event: &MoodEvent,
+    conn: &mut PgConnection
+) -> Result<(), JadeErr> {
+    let payload: String = match serde_json::to_string(event){
+        Ok(payload) => payload,
+        Err(e) => return Err::<(), JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    let _notify_op = match sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(MOOD_CHANNEL)
+        .bind(payload)
+        .execute(conn)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+    };
+    Ok(())
+}
+
+/// Listens on the PostgreSQL channel for mood
+/// updates with a single connection for as long
+/// as the app runs and hands every update to all
+/// open event streams and websockets through the
+/// given sender. If the listener cannot be opened,
+/// this is logged and tried again.
+pub async fn run_mood_listener(pool: Pool<Postgres>, sender: Sender<String>) {
+    loop {
+        let mut listener: PgListener = match PgListener::connect_with(&pool).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                log::error!("Could not open the mood listener: {}", &e.to_string());
+                tokio::time::sleep(Duration::from_secs(LISTENER_RETRY_SECS)).await;
+                continue;
+            }
+        };
+        match listener.listen(MOOD_CHANNEL).await {
+            Ok(_listen_op) => {},
+            Err(e) => {
+                log::error!("Could not listen for mood updates: {}", &e.to_string());
+                tokio::time::sleep(Duration::from_secs(LISTENER_RETRY_SECS)).await;
+                continue;
+            }
+        };
+        loop {
+            match listener.recv().await {
+                Ok(notification) => {
+                    let _sent = sender.send(notification.payload().to_string());
+                },
+                Err(e) => {
+                    log::error!("The mood listener failed: {}", &e.to_string());
+                    break;
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(LISTENER_RETRY_SECS)).await;
+    }
+}
+
+/// Waits for the next mood update on the
+/// given receiver. Updates missed because the
+/// receiver fell behind are skipped. If the
+/// channel was closed, nothing is returned.
+pub async fn next_mood_update(receiver: &mut Receiver<String>) -> Option<String> {
+    loop {
+        match receiver.recv().await {
+            Ok(payload) => return Some(payload),
+            Err(RecvError::Lagged(_skipped)) => continue,
+            Err(RecvError::Closed) => return None
+        }
+    }
+}
+
+/// Builds a stream of Server-Sent Events
+/// for the watched users from the updates
+/// handed out by the shared mood listener.
+pub fn mood_event_stream(
+    users: &Vec<String>,
+    receiver: Receiver<String>
+) -> impl Stream<Item = Result<Bytes, JadeErr>> {
+    let watched: HashSet<String> = users.iter().cloned().collect();
+    let updates = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        next_mood_update(&mut receiver).await.map(|payload| (payload, receiver))
+    });
+    let events = updates.filter_map(move |payload| {
+        let frame: Option<Result<Bytes, JadeErr>> = match serde_json::from_str::<MoodEvent>(&payload) {
+            Ok(event) => {
+                if watched.contains(&event.username) {
+                    Some(Ok(Bytes::from(format!("event: mood\ndata: {}\n\n", payload))))
+                }
+                else {
+                    None
+                }
+            },
+            Err(_e) => None
+        };
+        async move { frame }
+    });
+    let keep_alive = futures_util::stream::unfold((), |_| async {
+        tokio::time::sleep(Duration::from_secs(KEEP_ALIVE_SECS)).await;
+        Some((Ok(Bytes::from_static(b": keep-alive\n\n")), ()))
+    });
+    futures_util::stream::select(events, keep_alive)
+}
\ No newline at end of file
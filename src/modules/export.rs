@@ -0,0 +1,306 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "Write"
+/// trait to write entries
+/// into an archive.
+use std::io::Write;
+
+/// Importing the "Cursor"
+/// structure to build an
+/// archive in memory.
+use std::io::Cursor;
+
+/// Importing the "Serialize"
+/// trait to write entries as
+/// JSON.
+use serde::Serialize;
+
+/// Importing the "Pool"
+/// structure to talk to the
+/// database.
+use sqlx::Pool;
+
+/// Importing the "Postgres"
+/// structure to talk to the
+/// database.
+use sqlx::Postgres;
+
+/// Importing the "ZipWriter"
+/// structure to write archives.
+use zip::ZipWriter;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the function
+/// to get the current time.
+use super::time::get_time;
+
+/// Importing the structure
+/// containing the keys for
+/// email addresses.
+use super::crypto::EmailKeys;
+
+/// Importing the structure
+/// containing information on
+/// a user.
+use super::units::JadeUser;
+
+/// Importing the structure
+/// containing information on
+/// a saved mood.
+use super::units::JadeMood;
+
+/// Importing the structure
+/// containing information on
+/// an API token.
+use super::units::APIToken;
+
+/// Importing the structure
+/// containing information on
+/// an uploaded file.
+use super::units::JadeUserFile;
+
+/// Importing the structure
+/// containing information on
+/// an uploaded file in an
+/// export.
+use super::units::ExportedFile;
+
+/// Importing the structure
+/// containing the profile of a
+/// user in an export.
+use super::units::ExportProfile;
+
+/// Importing the function
+/// to read stored email
+/// addresses.
+use super::crypto::decrypt_email;
+
+/// Importing the function
+/// to fetch the files a user
+/// has uploaded.
+use super::rw::get_user_files;
+
+/// Importing the function
+/// to fetch every mood a user
+/// has set.
+use super::rw::get_mood_history;
+
+/// Importing the function
+/// to fetch every token a user
+/// has created.
+use super::rw::get_token_history;
+
+/// Importing the function
+/// to fetch a user by their
+/// handle.
+use super::rw::get_user_by_handle;
+
+/// Importing the function
+/// to connect to the database.
+use super::utils::create_connection;
+
+/// Importing the "CompressionMethod"
+/// enum to compress entries.
+use zip::CompressionMethod;
+
+/// Importing the "SimpleFileOptions"
+/// structure to configure entries.
+use zip::write::SimpleFileOptions;
+
+/// The text placed at the root
+/// of every export to describe
+/// its contents.
+pub const EXPORT_README: &str = "This archive contains all data Jade stores about your account.\n\n\
+profile.json: your profile.\n\
+moods.json: every mood you have set.\n\
+tokens.json: every API token you have created. Token secrets are never stored and are not included.\n\
+files.json: the files you have uploaded and where their contents are in this archive.\n\
+files/: the contents of the files you have uploaded.\n\n\
+Jade has no follow relationships yet, so this archive holds no follow list.\n";
+
+/// Replaces every character of an
+/// uploaded file's name that could
+/// escape its directory in an archive.
+pub fn sanitize_file_name(file_name: &String) -> String {
+    let sanitized: String = file_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '.' || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    if sanitized.is_empty() || sanitized.chars().all(|c| c == '.') {
+        "file".to_string()
+    }
+    else {
+        sanitized
+    }
+}
+
+/// Attempts to write the given bytes
+/// into an archive under the given path.
+/// If this operation fails, an error is
+/// returned.
+pub fn write_entry(
+    archive: &mut ZipWriter<Cursor<Vec<u8>>>,
+    path: &String,
+    data: &[u8]
+) -> Result<(), JadeErr> {
+    let options: SimpleFileOptions = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated);
+    match archive.start_file(path.as_str(), options) {
+        Ok(_f) => {},
+        Err(e) => return Err::<(), JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    match archive.write_all(data) {
+        Ok(_f) => Ok(()),
+        Err(e) => Err::<(), JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
+
+/// Attempts to write the given value
+/// into an archive as pretty-printed
+/// JSON under the given path. If this
+/// operation fails, an error is returned.
+pub fn write_json_entry<T: Serialize>(
+    archive: &mut ZipWriter<Cursor<Vec<u8>>>,
+    path: &String,
+    value: &T
+) -> Result<(), JadeErr> {
+    let json: Vec<u8> = match serde_json::to_vec_pretty(value) {
+        Ok(json) => json,
+        Err(e) => return Err::<(), JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    write_entry(archive, path, &json)
+}
+
+/// Attempts to package the given profile, mood
+/// history, API token metadata, and uploaded files
+/// into a ZIP archive. If this operation succeeds,
+/// the bytes of the archive are returned. If this
+/// operation fails, an error is returned.
+pub fn write_export_archive(
+    profile: &ExportProfile,
+    moods: &Vec<JadeMood>,
+    tokens: &Vec<APIToken>,
+    files: &Vec<JadeUserFile>
+) -> Result<Vec<u8>, JadeErr> {
+    let mut archive: ZipWriter<Cursor<Vec<u8>>> = ZipWriter::new(Cursor::new(Vec::new()));
+    match write_entry(&mut archive, &"README.txt".to_string(), EXPORT_README.as_bytes()) {
+        Ok(_f) => {},
+        Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+    };
+    match write_json_entry(&mut archive, &"profile.json".to_string(), profile) {
+        Ok(_f) => {},
+        Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+    };
+    match write_json_entry(&mut archive, &"moods.json".to_string(), moods) {
+        Ok(_f) => {},
+        Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+    };
+    match write_json_entry(&mut archive, &"tokens.json".to_string(), tokens) {
+        Ok(_f) => {},
+        Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+    };
+    let mut exported: Vec<ExportedFile> = Vec::new();
+    for file in files {
+        let path: Option<String> = if file.data.is_empty() {
+            None
+        }
+        else {
+            let path: String = format!("files/{}/{}", sanitize_file_name(&file.file_id), sanitize_file_name(&file.file_name));
+            match write_entry(&mut archive, &path, &file.data) {
+                Ok(_f) => {},
+                Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+            };
+            Some(path)
+        };
+        exported.push(
+            ExportedFile {
+                file_id: file.file_id.clone(),
+                file_name: file.file_name.clone(),
+                path
+            }
+        );
+    }
+    match write_json_entry(&mut archive, &"files.json".to_string(), &exported) {
+        Ok(_f) => {},
+        Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+    };
+    match archive.finish() {
+        Ok(cursor) => Ok(cursor.into_inner()),
+        Err(e) => Err::<Vec<u8>, JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
+
+/// Attempts to package everything Jade
+/// stores about a user into a ZIP archive:
+/// their profile, their mood history, the
+/// metadata of their API tokens, and the
+/// files they have uploaded. If this operation
+/// succeeds, the bytes of the archive are
+/// returned. If this operation fails, an
+/// error is returned.
+pub async fn build_export_archive(
+    user: &JadeUser,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<Vec<u8>, JadeErr> {
+    let moods: Vec<JadeMood> = match get_mood_history(&user.username, pool).await {
+        Ok(moods) => moods,
+        Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+    };
+    let tokens: Vec<APIToken> = match get_token_history(&user.username, pool).await {
+        Ok(tokens) => tokens,
+        Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+    };
+    let files: Vec<JadeUserFile> = match get_user_files(&user.username, pool).await {
+        Ok(files) => files,
+        Err(e) => return Err::<Vec<u8>, JadeErr>(e)
+    };
+    let profile: ExportProfile = ExportProfile {
+        username: user.username.clone(),
+        email: decrypt_email(keys, &user.email).ok(),
+        is_active: user.is_active,
+        totp_enabled: user.totp_enabled,
+        exported_at: get_time()
+    };
+    write_export_archive(&profile, &moods, &tokens, &files)
+}
+
+/// Attempts to export everything Jade stores
+/// about the user with the given handle into a
+/// ZIP archive in the current directory. This
+/// function is used by the CLI. If this operation
+/// succeeds, the path of the archive is returned.
+/// If this operation fails, an error is returned.
+pub async fn export_user_to_file(
+    db_url: &String,
+    email_key: &String,
+    username: &String
+) -> Result<String, JadeErr> {
+    let pool: Pool<Postgres> = match create_connection(db_url).await {
+        Ok(pool) => pool,
+        Err(e) => return Err::<String, JadeErr>(e)
+    };
+    let keys: EmailKeys = match EmailKeys::new(email_key) {
+        Ok(keys) => keys,
+        Err(e) => return Err::<String, JadeErr>(e)
+    };
+    let user: JadeUser = match get_user_by_handle(username, &pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<String, JadeErr>(e)
+    };
+    let archive: Vec<u8> = match build_export_archive(&user, &keys, &pool).await {
+        Ok(archive) => archive,
+        Err(e) => return Err::<String, JadeErr>(e)
+    };
+    let path: String = format!("{}-export.zip", sanitize_file_name(&user.username));
+    match std::fs::write(&path, archive) {
+        Ok(_f) => Ok(path),
+        Err(e) => Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,424 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "Read"
+/// trait to read entries
+/// from an archive.
+use std::io::Read;
+
+/// Importing the "Cursor"
+/// structure to read an
+/// archive in memory.
+use std::io::Cursor;
+
+/// Importing the "Pool"
+/// structure to talk to the
+/// database.
+use sqlx::Pool;
+
+/// Importing the "Postgres"
+/// structure to talk to the
+/// database.
+use sqlx::Postgres;
+
+/// Importing the "ZipArchive"
+/// structure to read archives.
+use zip::ZipArchive;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the function
+/// to parse timestamps.
+use super::time::parse_time;
+
+/// Importing the structure
+/// containing information on
+/// a user.
+use super::units::JadeUser;
+
+/// Importing the structure
+/// containing information on
+/// a saved mood.
+use super::units::JadeMood;
+
+/// Importing the structure
+/// containing information on
+/// an uploaded file.
+use super::units::JadeUserFile;
+
+/// Importing the structure
+/// containing information on
+/// an uploaded file in an
+/// export.
+use super::units::ExportedFile;
+
+/// Importing the structure
+/// containing the profile of a
+/// user in an export.
+use super::units::ExportProfile;
+
+/// Importing the structure
+/// containing information on
+/// what was imported.
+use super::units::ImportSummary;
+
+/// Importing the structure
+/// containing the data read
+/// from a data export.
+use super::units::ImportedData;
+
+/// Importing the "DeserializeOwned"
+/// trait to read entries as JSON.
+use serde::de::DeserializeOwned;
+
+/// Importing the function
+/// to write imported data to
+/// the database.
+use super::rw::store_imported_data;
+
+/// Importing the function
+/// to generate random strings.
+use super::tokens::random_string;
+
+/// Importing the number of
+/// characters in the ID of an
+/// uploaded file.
+use super::tokens::FILE_ID_LENGTH;
+
+/// The maximum number of
+/// bytes a single entry of an
+/// imported archive may unpack to.
+pub const MAX_IMPORT_ENTRY_BYTES: u64 = 100 * 1024 * 1024;
+
+/// The maximum number of
+/// bytes all entries read from
+/// an imported archive may unpack
+/// to together.
+pub const MAX_IMPORT_TOTAL_BYTES: u64 = 150 * 1024 * 1024;
+
+/// The maximum number of
+/// moods an imported archive
+/// may contain.
+pub const MAX_IMPORT_MOODS: usize = 100_000;
+
+/// The maximum number of
+/// files an imported archive
+/// may contain.
+pub const MAX_IMPORT_FILES: usize = 1_000;
+
+/// Attempts to open the given bytes as
+/// a ZIP archive. If this operation fails,
+/// an error is returned.
+pub fn open_archive(bytes: Vec<u8>) -> Result<ZipArchive<Cursor<Vec<u8>>>, JadeErr> {
+    match ZipArchive::new(Cursor::new(bytes)) {
+        Ok(archive) => Ok(archive),
+        Err(e) => {
+            let e: String = format!("The uploaded file is not a valid ZIP archive: {}", e);
+            Err::<ZipArchive<Cursor<Vec<u8>>>, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    }
+}
+
+/// Attempts to read the entry at the given
+/// path from an archive. Entries that unpack
+/// to more than "MAX_IMPORT_ENTRY_BYTES" are
+/// rejected, as are entries that would take the
+/// bytes unpacked so far, counted in "unpacked",
+/// past "MAX_IMPORT_TOTAL_BYTES". If this
+/// operation fails, an error is returned.
+pub fn read_entry(
+    archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+    path: &String,
+    unpacked: &mut u64
+) -> Result<Vec<u8>, JadeErr> {
+    let entry = match archive.by_name(path) {
+        Ok(entry) => entry,
+        Err(e) => {
+            let e: String = format!("Could not read \"{}\" from the archive: {}", path, e);
+            return Err::<Vec<u8>, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    let remaining: u64 = MAX_IMPORT_TOTAL_BYTES.saturating_sub(*unpacked);
+    let mut buf: Vec<u8> = Vec::new();
+    match entry.take(MAX_IMPORT_ENTRY_BYTES.min(remaining) + 1).read_to_end(&mut buf) {
+        Ok(_read_op) => {},
+        Err(e) => {
+            let e: String = format!("Could not read \"{}\" from the archive: {}", path, e);
+            return Err::<Vec<u8>, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    if buf.len() as u64 > MAX_IMPORT_ENTRY_BYTES {
+        let e: String = format!("The entry \"{}\" of the archive is too large.", path);
+        return Err::<Vec<u8>, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    if buf.len() as u64 > remaining {
+        let e: String = "The archive unpacks to too much data.".to_string();
+        return Err::<Vec<u8>, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    *unpacked += buf.len() as u64;
+    Ok(buf)
+}
+
+/// Attempts to read the entry at the given
+/// path from an archive as JSON. If this
+/// operation fails, an error is returned.
+pub fn read_json_entry<T: DeserializeOwned>(
+    archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+    path: &String,
+    unpacked: &mut u64
+) -> Result<T, JadeErr> {
+    let data: Vec<u8> = match read_entry(archive, path, unpacked) {
+        Ok(data) => data,
+        Err(e) => return Err::<T, JadeErr>(e)
+    };
+    match serde_json::from_slice(&data) {
+        Ok(value) => Ok(value),
+        Err(e) => {
+            let e: String = format!("Could not read \"{}\" from the archive: {}", path, e);
+            Err::<T, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    }
+}
+
+/// Attempts to read a Jade data export for the
+/// user with the given handle. Moods keep their
+/// original timestamps and only the most recent
+/// active mood stays active. Uploaded files are
+/// given new IDs, since the IDs in the archive may
+/// still be taken by the account it was exported
+/// from. Archives that unpack to too much data or
+/// hold too many moods or files are rejected. If
+/// this operation succeeds, the data read from the
+/// archive is returned. If this operation fails,
+/// an error is returned.
+pub fn read_export_archive(
+    bytes: Vec<u8>,
+    username: &String
+) -> Result<ImportedData, JadeErr> {
+    let mut archive: ZipArchive<Cursor<Vec<u8>>> = match open_archive(bytes) {
+        Ok(archive) => archive,
+        Err(e) => return Err::<ImportedData, JadeErr>(e)
+    };
+    let mut unpacked: u64 = 0;
+    let profile: ExportProfile = match read_json_entry(&mut archive, &"profile.json".to_string(), &mut unpacked) {
+        Ok(profile) => profile,
+        Err(e) => return Err::<ImportedData, JadeErr>(e)
+    };
+    let exported_moods: Vec<JadeMood> = match read_json_entry(&mut archive, &"moods.json".to_string(), &mut unpacked) {
+        Ok(exported_moods) => exported_moods,
+        Err(e) => return Err::<ImportedData, JadeErr>(e)
+    };
+    let exported_files: Vec<ExportedFile> = match read_json_entry(&mut archive, &"files.json".to_string(), &mut unpacked) {
+        Ok(exported_files) => exported_files,
+        Err(e) => return Err::<ImportedData, JadeErr>(e)
+    };
+    if exported_moods.len() > MAX_IMPORT_MOODS {
+        let e: String = format!("An archive can hold at most {} moods.", MAX_IMPORT_MOODS);
+        return Err::<ImportedData, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    if exported_files.len() > MAX_IMPORT_FILES {
+        let e: String = format!("An archive can hold at most {} files.", MAX_IMPORT_FILES);
+        return Err::<ImportedData, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    let mut moods: Vec<JadeMood> = Vec::new();
+    for mood in exported_moods {
+        match parse_time(&mood.created_at) {
+            Ok(_parsed) => {},
+            Err(_e) => {
+                let e: String = format!("The mood timestamp \"{}\" is invalid.", &mood.created_at);
+                return Err::<ImportedData, JadeErr>(JadeErr::validation(&e.to_string()))
+            }
+        };
+        moods.push(
+            JadeMood {
+                username: username.clone(),
+                is_active: mood.is_active,
+                mood: mood.mood,
+                created_at: mood.created_at
+            }
+        );
+    }
+    moods.sort_by(|a, b| {
+        let a_time = parse_time(&a.created_at).ok();
+        let b_time = parse_time(&b.created_at).ok();
+        a_time.cmp(&b_time)
+    });
+    let last_active: Option<usize> = moods.iter().rposition(|mood| mood.is_active);
+    for (index, mood) in moods.iter_mut().enumerate() {
+        mood.is_active = Some(index) == last_active;
+    }
+    let mut files: Vec<JadeUserFile> = Vec::new();
+    for file in exported_files {
+        let path: String = match file.path {
+            Some(path) => path,
+            None => continue
+        };
+        let data: Vec<u8> = match read_entry(&mut archive, &path, &mut unpacked) {
+            Ok(data) => data,
+            Err(e) => return Err::<ImportedData, JadeErr>(e)
+        };
+        files.push(
+            JadeUserFile {
+                file_id: random_string(FILE_ID_LENGTH),
+                username: username.clone(),
+                file_name: file.file_name,
+                data
+            }
+        );
+    }
+    Ok(
+        ImportedData {
+            profile,
+            moods,
+            files
+        }
+    )
+}
+
+/// Attempts to import a Jade data export into
+/// the given user's account. The username, email
+/// address, and credentials of the account are
+/// kept, since they cannot be carried over from
+/// another account. If this operation succeeds, a
+/// summary of what was imported is returned. If
+/// this operation fails, an error is returned and
+/// nothing is imported.
+pub async fn import_export_archive(
+    bytes: Vec<u8>,
+    user: &JadeUser,
+    pool: &Pool<Postgres>
+) -> Result<ImportSummary, JadeErr> {
+    let imported: ImportedData = match read_export_archive(bytes, &user.username) {
+        Ok(imported) => imported,
+        Err(e) => return Err::<ImportSummary, JadeErr>(e)
+    };
+    match store_imported_data(user, &imported.moods, &imported.files, pool).await {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<ImportSummary, JadeErr>(e)
+    };
+    Ok(
+        ImportSummary {
+            imported_from: imported.profile.username,
+            moods_imported: imported.moods.len(),
+            files_imported: imported.files.len()
+        }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::io::Write;
+    use zip::ZipWriter;
+    use zip::write::SimpleFileOptions;
+    use super::super::err::JadeErrKind;
+    use super::super::export::write_export_archive;
+
+    fn archive_with(entries: &[(&str, &[u8])]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer: ZipWriter<Cursor<Vec<u8>>> = ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, data) in entries {
+            writer.start_file(*path, SimpleFileOptions::default()).unwrap();
+            writer.write_all(data).unwrap();
+        }
+        let bytes: Vec<u8> = writer.finish().unwrap().into_inner();
+        open_archive(bytes).unwrap()
+    }
+
+    #[test]
+    fn malformed_archives_are_validation_errors() {
+        let e: JadeErr = open_archive(b"not a zip archive".to_vec()).err().unwrap();
+        assert_eq!(e.kind, JadeErrKind::Validation);
+    }
+
+    #[test]
+    fn missing_entries_are_validation_errors() {
+        let mut archive = archive_with(&[("profile.json", b"{}")]);
+        let mut unpacked: u64 = 0;
+        let e: JadeErr = read_entry(&mut archive, &"moods.json".to_string(), &mut unpacked).err().unwrap();
+        assert_eq!(e.kind, JadeErrKind::Validation);
+    }
+
+    #[test]
+    fn entries_count_towards_the_total() {
+        let mut archive = archive_with(&[("a", b"12345"), ("b", b"123")]);
+        let mut unpacked: u64 = 0;
+        read_entry(&mut archive, &"a".to_string(), &mut unpacked).unwrap();
+        read_entry(&mut archive, &"b".to_string(), &mut unpacked).unwrap();
+        assert_eq!(unpacked, 8);
+    }
+
+    #[test]
+    fn entries_past_the_total_are_rejected() {
+        let mut archive = archive_with(&[("a", b"12345")]);
+        let mut unpacked: u64 = MAX_IMPORT_TOTAL_BYTES - 4;
+        let e: JadeErr = read_entry(&mut archive, &"a".to_string(), &mut unpacked).err().unwrap();
+        assert_eq!(e.kind, JadeErrKind::Validation);
+        assert_eq!(unpacked, MAX_IMPORT_TOTAL_BYTES - 4);
+    }
+
+    #[test]
+    fn exports_round_trip_through_imports() {
+        let profile: ExportProfile = ExportProfile {
+            username: "alice".to_string(),
+            email: Some("alice@example.com".to_string()),
+            is_active: true,
+            totp_enabled: false,
+            exported_at: "2026-10-19/12:00:00".to_string()
+        };
+        let moods: Vec<JadeMood> = vec![
+            JadeMood {
+                username: "alice".to_string(),
+                is_active: true,
+                mood: "happy".to_string(),
+                created_at: "2026-10-19/11:00:00".to_string()
+            },
+            JadeMood {
+                username: "alice".to_string(),
+                is_active: false,
+                mood: "tired".to_string(),
+                created_at: "2026-10-18/09:30:00".to_string()
+            }
+        ];
+        let files: Vec<JadeUserFile> = vec![
+            JadeUserFile {
+                file_id: "first".to_string(),
+                username: "alice".to_string(),
+                file_name: "avatar.png".to_string(),
+                data: b"first file".to_vec()
+            },
+            JadeUserFile {
+                file_id: "second".to_string(),
+                username: "alice".to_string(),
+                file_name: "notes.txt".to_string(),
+                data: b"second file".to_vec()
+            }
+        ];
+        let bytes: Vec<u8> = write_export_archive(&profile, &moods, &Vec::new(), &files).unwrap();
+        let imported: ImportedData = read_export_archive(bytes, &"bob".to_string()).unwrap();
+        assert_eq!(imported.profile.username, "alice");
+        let read_moods: Vec<(String, bool, String)> = imported.moods
+            .iter()
+            .map(|mood| (mood.mood.clone(), mood.is_active, mood.created_at.clone()))
+            .collect();
+        assert_eq!(
+            read_moods,
+            vec![
+                ("tired".to_string(), false, "2026-10-18/09:30:00".to_string()),
+                ("happy".to_string(), true, "2026-10-19/11:00:00".to_string())
+            ]
+        );
+        assert!(imported.moods.iter().all(|mood| mood.username == "bob"));
+        assert_eq!(imported.files.len(), 2);
+        for (read, original) in imported.files.iter().zip(files.iter()) {
+            assert_eq!(read.username, "bob");
+            assert_eq!(read.file_name, original.file_name);
+            assert_eq!(read.data, original.data);
+            assert_ne!(read.file_id, original.file_id);
+        }
+    }
+}
\ No newline at end of file
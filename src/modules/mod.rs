@@ -49,4 +49,84 @@ pub mod email;
 /// Exporting the module
 /// containing this app's
 /// app runner function.
-pub mod runner;
\ No newline at end of file
+pub mod runner;
+
+/// Exporting the module
+/// containing functions for
+/// publishing and streaming
+/// mood updates.
+pub mod events;
+
+/// Exporting the module
+/// containing this app's
+/// websocket API.
+pub mod socket;
+
+/// Exporting the module
+/// containing functions for
+/// rendering feeds and pages.
+pub mod render;
+
+/// Exporting the module
+/// containing this app's
+/// extractor for authenticating
+/// requests.
+pub mod auth;
+
+/// Exporting the module
+/// containing functions for
+/// generating and hashing
+/// API tokens.
+pub mod tokens;
+
+/// Exporting the module
+/// containing the scopes
+/// API tokens can be granted.
+pub mod scopes;
+
+/// Exporting the module
+/// containing functions for
+/// browser sessions.
+pub mod sessions;
+
+/// Exporting the module
+/// containing functions for
+/// this app's OAuth
+/// authorization server.
+pub mod oauth;
+
+/// Exporting the module
+/// containing functions for
+/// two-factor authentication.
+pub mod totp;
+
+/// Exporting the module
+/// containing functions for
+/// hashing and checking
+/// passwords.
+pub mod password;
+
+/// Exporting the module
+/// containing this app's
+/// rate limits.
+pub mod ratelimit;
+
+/// Exporting the module
+/// containing the functions for
+/// encrypting email addresses.
+pub mod crypto;
+
+/// Exporting the module
+/// containing the rules usernames
+/// and email addresses must follow.
+pub mod validation;
+
+/// Exporting the module
+/// containing the functions for
+/// exporting a user's data.
+pub mod export;
+
+/// Exporting the module
+/// containing the functions for
+/// importing a user's data.
+pub mod import;
\ No newline at end of file
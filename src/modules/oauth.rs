@@ -0,0 +1,439 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "Url"
+/// structure to parse and
+/// build redirect URIs.
+use url::Url;
+
+/// Importing the "Digest"
+/// trait to hash PKCE code
+/// verifiers.
+use sha2::Digest;
+
+/// Importing the "Sha256"
+/// structure to hash PKCE code
+/// verifiers.
+use sha2::Sha256;
+
+/// Importing the "Engine"
+/// trait to encode PKCE code
+/// challenges.
+use base64::Engine;
+
+/// Importing the "Host" enum
+/// to check the host of a
+/// redirect URI.
+use url::Host;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the structure
+/// containing information on a
+/// registered OAuth application.
+use super::units::OAuthClient;
+
+/// Importing the function
+/// to hash client secrets.
+use super::tokens::hash_token_secret;
+
+/// Importing the scope for
+/// setting and deleting moods.
+use super::scopes::MOOD_WRITE;
+
+/// Importing the scope for
+/// reading uploaded files.
+use super::scopes::FILES_READ;
+
+/// Importing the scope for
+/// uploading files.
+use super::scopes::FILES_WRITE;
+
+/// Importing the URL-safe
+/// base64 engine without padding
+/// PKCE uses.
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+/// The number of characters
+/// in the ID of an OAuth
+/// application.
+pub const CLIENT_ID_LENGTH: usize = 32;
+
+/// The number of characters
+/// in the secret of an OAuth
+/// application.
+pub const CLIENT_SECRET_LENGTH: usize = 48;
+
+/// The number of characters
+/// in an authorization code.
+pub const AUTH_CODE_LENGTH: usize = 40;
+
+/// The number of characters
+/// in a refresh token.
+pub const REFRESH_TOKEN_LENGTH: usize = 48;
+
+/// The number of seconds an
+/// authorization code can be
+/// redeemed for.
+pub const AUTH_CODE_LIFETIME_SECS: i64 = 600;
+
+/// The number of seconds an
+/// API token issued through
+/// OAuth stays valid for.
+pub const ACCESS_TOKEN_LIFETIME_SECS: i64 = 7200;
+
+/// The redirect URI applications
+/// without a web server use to have
+/// the authorization code shown to
+/// the user instead.
+pub const OOB_REDIRECT_URI: &str = "urn:ietf:wg:oauth:2.0:oob";
+
+/// The only PKCE method
+/// Jade accepts.
+pub const PKCE_METHOD: &str = "S256";
+
+/// The shortest PKCE code
+/// verifier RFC 7636 allows.
+pub const MIN_CODE_VERIFIER_LENGTH: usize = 43;
+
+/// The longest PKCE code
+/// verifier RFC 7636 allows.
+pub const MAX_CODE_VERIFIER_LENGTH: usize = 128;
+
+/// The type of applications
+/// that can keep a client
+/// secret, like web servers.
+pub const CONFIDENTIAL_CLIENT: &str = "confidential";
+
+/// The type of applications
+/// that cannot keep a client
+/// secret, like mobile apps.
+pub const PUBLIC_CLIENT: &str = "public";
+
+/// Attempts to work out whether an
+/// application registers as a confidential
+/// application. Applications that do not
+/// name a type are confidential. If the type
+/// is unknown, an error is returned.
+pub fn is_confidential_client(client_type: &Option<String>) -> Result<bool, JadeErr> {
+    match client_type.as_deref() {
+        None => Ok(true),
+        Some(CONFIDENTIAL_CLIENT) => Ok(true),
+        Some(PUBLIC_CLIENT) => Ok(false),
+        Some(other) => {
+            let e: String = format!("The client type \"{}\" does not exist.", other);
+            Err::<bool, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    }
+}
+
+/// Attempts to check the client secret a
+/// token request was made with. Confidential
+/// applications must send their secret. Public
+/// applications have no secret and rely on PKCE.
+/// If the check fails, an error is returned.
+pub fn check_client_secret(
+    client: &OAuthClient,
+    client_secret: &Option<String>
+) -> Result<(), JadeErr> {
+    match (&client.client_secret_hash, client_secret) {
+        (Some(stored), Some(supplied)) if client.is_confidential && hash_token_secret(supplied) == *stored => Ok(()),
+        (_, None) if client.is_confidential => {
+            let e: String = "Confidential applications must send their client secret.".to_string();
+            Err::<(), JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        },
+        (_, None) => Ok(()),
+        _ => {
+            let e: String = "The client secret is invalid.".to_string();
+            Err::<(), JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    }
+}
+
+/// Checks whether the given host is
+/// one of the loopback addresses native
+/// applications listen on.
+pub fn is_loopback_host(host: Option<Host<&str>>) -> bool {
+    match host {
+        Some(Host::Domain(domain)) => domain == "localhost",
+        Some(Host::Ipv4(addr)) => addr.is_loopback(),
+        Some(Host::Ipv6(addr)) => addr.is_loopback(),
+        None => false
+    }
+}
+
+/// Attempts to check that a redirect URI
+/// is either the out-of-band URI or an
+/// absolute URL without a fragment. Only
+/// "https" URLs are accepted, and "http"
+/// URLs for loopback hosts, so that codes
+/// cannot be sent to scripts or files. If
+/// this operation fails, an error is returned.
+pub fn validate_redirect_uri(redirect_uri: &String) -> Result<(), JadeErr> {
+    if redirect_uri == OOB_REDIRECT_URI {
+        return Ok(());
+    }
+    let parsed: Url = match Url::parse(redirect_uri) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let e: String = format!("The redirect URI \"{}\" is not a valid URL: {}", redirect_uri, e);
+            return Err::<(), JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    let allowed_scheme: bool = match parsed.scheme() {
+        "https" => true,
+        "http" => is_loopback_host(parsed.host()),
+        _ => false
+    };
+    if allowed_scheme {}
+    else {
+        let e: String = format!("The redirect URI \"{}\" must use \"https\", or \"http\" for a loopback host.", redirect_uri);
+        return Err::<(), JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    if parsed.fragment().is_some() {
+        let e: String = format!("The redirect URI \"{}\" must not contain a fragment.", redirect_uri);
+        return Err::<(), JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    Ok(())
+}
+
+/// Attempts to check that a PKCE code
+/// verifier has the length and the characters
+/// RFC 7636 requires. If this operation fails,
+/// an error is returned.
+pub fn validate_code_verifier(code_verifier: &String) -> Result<(), JadeErr> {
+    let valid_length: bool = code_verifier.len() >= MIN_CODE_VERIFIER_LENGTH && code_verifier.len() <= MAX_CODE_VERIFIER_LENGTH;
+    let valid_chars: bool = code_verifier.chars().all(|c| c.is_ascii_alphanumeric() || "-._~".contains(c));
+    if valid_length && valid_chars {
+        Ok(())
+    }
+    else {
+        let e: String = format!(
+            "The PKCE code verifier must be {} to {} characters long and only contain letters, digits, \"-\", \".\", \"_\", and \"~\".",
+            MIN_CODE_VERIFIER_LENGTH,
+            MAX_CODE_VERIFIER_LENGTH
+        );
+        Err::<(), JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+}
+
+/// Splits a space-separated list
+/// of scopes as used by OAuth into
+/// single scopes.
+pub fn parse_scope_list(scope: &Option<String>) -> Vec<String> {
+    match scope {
+        Some(scope) => scope.split_whitespace().map(|scope| scope.to_string()).collect(),
+        None => Vec::new()
+    }
+}
+
+/// Translates the scopes Mastodon clients
+/// request into Jade's scopes. "read" and
+/// "write" are mapped to the matching Jade
+/// scopes, "follow" and "push" have no
+/// counterpart and are dropped, and all other
+/// scopes are kept as they are. Mastodon
+/// clients that do not request scopes get
+/// "read".
+pub fn translate_mastodon_scopes(scopes: &Option<String>) -> Vec<String> {
+    let requested: Vec<String> = match scopes {
+        Some(_scopes) => parse_scope_list(scopes),
+        None => vec!["read".to_string()]
+    };
+    let mut translated: Vec<String> = Vec::new();
+    for scope in requested {
+        let mapped: Vec<&str> = match scope.as_str() {
+            "read" => vec![FILES_READ],
+            "write" => vec![MOOD_WRITE, FILES_WRITE],
+            "follow" | "push" => Vec::new(),
+            other => vec![other]
+        };
+        for scope in mapped {
+            if translated.iter().any(|known| known == scope) {}
+            else {
+                translated.push(scope.to_string());
+            }
+        }
+    }
+    translated
+}
+
+/// Attempts to work out the scopes an
+/// authorization request is for. Requests
+/// without scopes get all scopes the
+/// application was registered with. If a
+/// requested scope was not registered, an
+/// error is returned.
+pub fn resolve_requested_scopes(
+    scope: &Option<String>,
+    client: &OAuthClient
+) -> Result<Vec<String>, JadeErr> {
+    let requested: Vec<String> = parse_scope_list(scope);
+    if requested.is_empty() {
+        return Ok(client.scopes.clone());
+    }
+    for scope in &requested {
+        if client.scopes.contains(scope) {}
+        else {
+            let e: String = format!("The application \"{}\" may not request the scope \"{}\".", &client.client_name, scope);
+            return Err::<Vec<String>, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    }
+    Ok(requested)
+}
+
+/// Derives the PKCE code challenge
+/// from a code verifier with the
+/// "S256" method.
+pub fn pkce_challenge(code_verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+/// Attempts to build the URL an application
+/// is redirected to with the given query
+/// parameters appended. If this operation
+/// fails, an error is returned.
+pub fn redirect_with_params(
+    redirect_uri: &String,
+    params: &Vec<(&str, String)>
+) -> Result<String, JadeErr> {
+    let mut url: Url = match Url::parse(redirect_uri) {
+        Ok(url) => url,
+        Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    {
+        let mut pairs = url.query_pairs_mut();
+        for (key, value) in params {
+            pairs.append_pair(key, value);
+        }
+    }
+    Ok(url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use super::super::tokens::hash_token_secret;
+    use super::super::err::JadeErrKind;
+
+    fn client(is_confidential: bool) -> OAuthClient {
+        OAuthClient {
+            client_id: "client".to_string(),
+            client_secret_hash: if is_confidential { Some(hash_token_secret("secret")) } else { None },
+            client_name: "app".to_string(),
+            redirect_uris: vec![OOB_REDIRECT_URI.to_string()],
+            scopes: vec![MOOD_WRITE.to_string(), FILES_READ.to_string()],
+            website: None,
+            created_at: String::new(),
+            is_confidential
+        }
+    }
+
+    #[test]
+    fn pkce_challenge_matches_rfc_7636() {
+        assert_eq!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn pkce_challenge_rejects_other_verifiers() {
+        assert_ne!(
+            pkce_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXj"),
+            "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+        );
+    }
+
+    #[test]
+    fn redirect_uris_must_use_https_or_loopback_http() {
+        let accepted: [&str; 6] = [
+            OOB_REDIRECT_URI,
+            "https://app.example/callback",
+            "https://app.example/callback?from=jade",
+            "http://localhost:8080/callback",
+            "http://127.0.0.1/callback",
+            "http://[::1]:9000/callback"
+        ];
+        for redirect_uri in accepted {
+            assert!(validate_redirect_uri(&redirect_uri.to_string()).is_ok(), "{}", redirect_uri);
+        }
+        let rejected: [&str; 8] = [
+            "javascript:alert(document.cookie)",
+            "data:text/html,<script>alert(1)</script>",
+            "file:///etc/passwd",
+            "http://app.example/callback",
+            "ftp://app.example/callback",
+            "com.example.app:/callback",
+            "https://app.example/callback#token",
+            "not a url"
+        ];
+        for redirect_uri in rejected {
+            let e: JadeErr = validate_redirect_uri(&redirect_uri.to_string()).err().unwrap();
+            assert_eq!(e.kind, JadeErrKind::Validation, "{}", redirect_uri);
+        }
+    }
+
+    #[test]
+    fn code_verifiers_must_have_the_length_rfc_7636_requires() {
+        assert!(validate_code_verifier(&"a".repeat(43)).is_ok());
+        assert!(validate_code_verifier(&"a".repeat(128)).is_ok());
+        assert!(validate_code_verifier(&"dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string()).is_ok());
+        assert!(validate_code_verifier(&"a".repeat(42)).is_err());
+        assert!(validate_code_verifier(&"a".repeat(129)).is_err());
+        assert!(validate_code_verifier(&format!("{}!", "a".repeat(43))).is_err());
+    }
+
+    #[test]
+    fn client_types_are_parsed() {
+        assert!(is_confidential_client(&None).unwrap());
+        assert!(is_confidential_client(&Some(CONFIDENTIAL_CLIENT.to_string())).unwrap());
+        assert!(!is_confidential_client(&Some(PUBLIC_CLIENT.to_string())).unwrap());
+        assert!(is_confidential_client(&Some("native".to_string())).is_err());
+    }
+
+    #[test]
+    fn client_secrets_are_checked_by_client_type() {
+        let table: [(bool, Option<&str>, bool); 6] = [
+            (true, Some("secret"), true),
+            (true, Some("wrong"), false),
+            (true, None, false),
+            (false, None, true),
+            (false, Some("secret"), false),
+            (false, Some(""), false)
+        ];
+        for (is_confidential, supplied, allowed) in table {
+            let supplied: Option<String> = supplied.map(|secret| secret.to_string());
+            assert_eq!(
+                check_client_secret(&client(is_confidential), &supplied).is_ok(),
+                allowed,
+                "confidential: {}, secret: {:?}",
+                is_confidential,
+                supplied
+            );
+        }
+    }
+
+    #[test]
+    fn redirect_uris_are_validated() {
+        assert!(validate_redirect_uri(&OOB_REDIRECT_URI.to_string()).is_ok());
+        assert!(validate_redirect_uri(&"https://app.example/callback".to_string()).is_ok());
+        assert!(validate_redirect_uri(&"https://app.example/callback#token".to_string()).is_err());
+        assert!(validate_redirect_uri(&"/callback".to_string()).is_err());
+    }
+
+    #[test]
+    fn requested_scopes_must_be_registered() {
+        assert_eq!(resolve_requested_scopes(&None, &client(true)).unwrap(), client(true).scopes);
+        assert_eq!(
+            resolve_requested_scopes(&Some(MOOD_WRITE.to_string()), &client(true)).unwrap(),
+            vec![MOOD_WRITE.to_string()]
+        );
+        assert!(resolve_requested_scopes(&Some(FILES_WRITE.to_string()), &client(true)).is_err());
+    }
+}
\ No newline at end of file
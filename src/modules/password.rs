@@ -0,0 +1,139 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "Rng"
+/// trait to generate salts.
+use rand::Rng;
+
+/// Importing the "Argon2"
+/// structure to hash passwords.
+use argon2::Argon2;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "SaltString"
+/// structure to encode salts.
+use argon2::password_hash::SaltString;
+
+/// Importing the "PasswordHash"
+/// structure to parse stored
+/// hashes.
+use argon2::password_hash::PasswordHash;
+
+/// Importing the "PasswordHasher"
+/// trait to hash passwords.
+use argon2::password_hash::PasswordHasher;
+
+/// Importing the "PasswordVerifier"
+/// trait to check passwords.
+use argon2::password_hash::PasswordVerifier;
+
+/// The prefix of password
+/// hashes created with Argon2id.
+pub const ARGON2ID_PREFIX: &str = "$argon2id$";
+
+/// The prefix all password
+/// hashes created with bcrypt
+/// share.
+pub const BCRYPT_PREFIX: &str = "$2";
+
+/// The number of random bytes
+/// in a password salt.
+pub const SALT_LENGTH: usize = 16;
+
+/// Attempts to hash a password with
+/// Argon2id. If this operation succeeds,
+/// the hash is returned in the PHC string
+/// format. If this operation fails, an
+/// error is returned.
+pub fn hash_password(password: &String) -> Result<String, JadeErr> {
+    let salt_bytes: [u8; SALT_LENGTH] = rand::thread_rng().gen();
+    let salt: SaltString = match SaltString::encode_b64(&salt_bytes) {
+        Ok(salt) => salt,
+        Err(e) => return Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    match Argon2::default().hash_password(password.as_bytes(), &salt) {
+        Ok(hashed) => Ok(hashed.to_string()),
+        Err(e) => Err::<String, JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
+
+/// Attempts to check a password against
+/// a stored hash. Hashes created with
+/// Argon2id and older hashes created with
+/// bcrypt are both accepted. If this
+/// operation fails, an error is returned.
+pub fn verify_password(password: &String, stored: &String) -> Result<bool, JadeErr> {
+    if stored.starts_with(ARGON2ID_PREFIX) {
+        let parsed: PasswordHash = match PasswordHash::new(stored) {
+            Ok(parsed) => parsed,
+            Err(e) => return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
+        };
+        Ok(Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+    }
+    else if stored.starts_with(BCRYPT_PREFIX) {
+        match bcrypt::verify(password, stored) {
+            Ok(is_valid) => Ok(is_valid),
+            Err(e) => Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
+        }
+    }
+    else {
+        let e: String = "The stored password hash has an unknown format.".to_string();
+        Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
+
+/// Checks whether a stored password
+/// hash should be replaced with an
+/// Argon2id hash the next time the
+/// password is supplied.
+pub fn needs_rehash(stored: &String) -> bool {
+    !stored.starts_with(ARGON2ID_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn argon2id_hashes_round_trip() {
+        let password: String = "correct horse battery staple".to_string();
+        let stored: String = hash_password(&password).unwrap();
+        assert!(stored.starts_with(ARGON2ID_PREFIX));
+        assert!(verify_password(&password, &stored).unwrap());
+        assert_ne!(hash_password(&password).unwrap(), stored);
+    }
+
+    #[test]
+    fn legacy_bcrypt_hashes_are_verified() {
+        let password: String = "hunter2".to_string();
+        let stored: String = bcrypt::hash(&password, 4).unwrap();
+        assert!(verify_password(&password, &stored).unwrap());
+        assert!(!verify_password(&"hunter3".to_string(), &stored).unwrap());
+    }
+
+    #[test]
+    fn wrong_passwords_are_rejected() {
+        let stored: String = hash_password(&"correct horse battery staple".to_string()).unwrap();
+        assert!(!verify_password(&"Correct horse battery staple".to_string(), &stored).unwrap());
+        assert!(!verify_password(&String::new(), &stored).unwrap());
+    }
+
+    #[test]
+    fn only_argon2id_hashes_are_kept() {
+        let argon2id: String = hash_password(&"pw".to_string()).unwrap();
+        let bcrypt_hash: String = bcrypt::hash("pw", 4).unwrap();
+        assert!(!needs_rehash(&argon2id));
+        assert!(needs_rehash(&bcrypt_hash));
+    }
+
+    #[test]
+    fn unknown_hash_formats_are_errors() {
+        assert!(verify_password(&"pw".to_string(), &"pw".to_string()).is_err());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,890 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the
+/// "Pool" structure
+/// from the "sqlx" crate
+/// to make a pool for
+/// database connections.
+use sqlx::Pool;
+
+/// Importing the "Arc"
+/// structure to share the
+/// in-memory store between
+/// workers.
+use std::sync::Arc;
+
+/// Importing the "Mutex"
+/// structure to guard the
+/// in-memory store.
+use std::sync::Mutex;
+
+/// Importing the "Utc"
+/// structure to get the
+/// current UNIX timestamp.
+use chrono::Utc;
+
+/// Importing the "Data"
+/// structure to access the
+/// app's data.
+use actix_web::web::Data;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the function
+/// to build the body of error
+/// responses.
+use super::err::error_body;
+
+/// Importing the enum
+/// describing the kinds of
+/// errors.
+use super::err::JadeErrKind;
+
+/// Importing the "Method"
+/// structure to tell which
+/// requests submit credentials.
+use actix_web::http::Method;
+
+/// Importing the "HashMap"
+/// structure to keep counters
+/// in memory.
+use std::collections::HashMap;
+
+/// Importing the "HttpResponse"
+/// structure to reject requests.
+use actix_web::HttpResponse;
+
+/// Importing the "AppData"
+/// structure to access the
+/// limits and the store.
+use super::units::AppData;
+
+/// Importing the "Postgres"
+/// structure from the "sqlx"
+/// crate.
+use sqlx::postgres::Postgres;
+
+/// Importing the structure
+/// containing the limits
+/// placed on authentication
+/// endpoints.
+use super::units::AuthLimitConfig;
+
+/// Importing the structure
+/// containing a single
+/// rate-limiting counter.
+use super::units::LimitEntry;
+
+/// Importing the "Next"
+/// structure to call the
+/// rest of the middleware
+/// chain.
+use actix_web::middleware::Next;
+
+/// Importing the structure
+/// containing the outcome of
+/// counting a request.
+use super::units::LimitDecision;
+
+/// Importing the "MessageBody"
+/// trait for the middleware's
+/// body types.
+use actix_web::body::MessageBody;
+
+/// Importing the "EitherBody"
+/// enum to return either the
+/// handler's response or a
+/// rejection.
+use actix_web::body::EitherBody;
+
+/// Importing the "ServiceRequest"
+/// structure the middleware
+/// receives.
+use actix_web::dev::ServiceRequest;
+
+/// Importing the structure
+/// containing the request budgets
+/// of all routes.
+use super::units::ApiLimitConfig;
+
+/// Importing the function
+/// to read the API token from
+/// a request.
+use super::auth::bearer_token;
+
+/// Importing the function
+/// to look up an API token
+/// and check its secret.
+use super::rw::get_api_token;
+
+/// Importing the "HeaderMap"
+/// structure to add quota headers
+/// to responses.
+use actix_web::http::header::HeaderMap;
+
+/// Importing the "HeaderName"
+/// structure to name the quota
+/// headers.
+use actix_web::http::header::HeaderName;
+
+/// Importing the "HeaderValue"
+/// structure to fill in the quota
+/// headers.
+use actix_web::http::header::HeaderValue;
+
+/// Importing the "ServiceResponse"
+/// structure the middleware
+/// returns.
+use actix_web::dev::ServiceResponse;
+
+/// Importing the "IpAddr"
+/// enum to compare client
+/// addresses.
+use std::net::IpAddr;
+
+/// Importing the "HttpRequest"
+/// structure to read the address
+/// a request came from.
+use actix_web::HttpRequest;
+
+/// The routes that accept a
+/// password or a secret with a
+/// "POST" request and are limited
+/// more strictly.
+pub const AUTH_ROUTES: [&str; 9] = [
+    "/token/create",
+    "/token/delete",
+    "/tokens/get",
+    "/auth/login",
+    "/auth/login/form",
+    "/oauth/token",
+    "/user/password/forgot",
+    "/user/password/reset",
+    "/email/resend"
+];
+
+/// The route prefixes that belong
+/// to the "moods" group of routes.
+pub const MOOD_ROUTE_PREFIXES: [&str; 2] = [
+    "/mood/",
+    "/moods/"
+];
+
+/// The route prefixes that belong
+/// to the "files" group of routes.
+pub const FILE_ROUTE_PREFIXES: [&str; 1] = [
+    "/files/"
+];
+
+/// The number of counters
+/// the in-memory store holds
+/// before expired counters are
+/// dropped.
+pub const MEMORY_PRUNE_THRESHOLD: usize = 10000;
+
+/// An enum containing the
+/// places rate-limiting counters
+/// can be kept in. Counters kept
+/// in memory are local to a single
+/// instance. Counters kept in
+/// PostgreSQL are shared by all
+/// instances using the same
+/// database.
+#[derive(Clone)]
+pub enum LimitStore {
+    Memory(Arc<Mutex<HashMap<String, LimitEntry>>>),
+    Postgres(Pool<Postgres>)
+}
+
+/// Implementing functions
+/// for the "LimitStore" enum.
+impl LimitStore {
+
+    /// Attempts to create the store with
+    /// the given name. If the name is neither
+    /// "memory" nor "postgres", an error is
+    /// returned.
+    pub fn new(store: &String, pool: &Pool<Postgres>) -> Result<LimitStore, JadeErr> {
+        match store.as_str() {
+            "memory" => Ok(LimitStore::Memory(Arc::new(Mutex::new(HashMap::new())))),
+            "postgres" => Ok(LimitStore::Postgres(pool.to_owned())),
+            other => {
+                let e: String = format!("The rate limit store \"{}\" does not exist.", other);
+                Err::<LimitStore, JadeErr>(JadeErr::new(&e.to_string()))
+            }
+        }
+    }
+}
+
+/// Gets the current
+/// UNIX timestamp in
+/// seconds.
+pub fn unix_now() -> i64 {
+    Utc::now().timestamp()
+}
+
+/// Counts one more event on a counter.
+/// Counters whose window has passed start
+/// over from one. The counter remembers the
+/// length of the window it was counted in.
+pub fn bump_entry(entry: Option<LimitEntry>, key: &String, now: i64, window_secs: i64) -> LimitEntry {
+    match entry {
+        Some(entry) if entry.window_start + window_secs > now => LimitEntry {
+            count: entry.count + 1,
+            window_secs: window_secs,
+            ..entry
+        },
+        Some(entry) => LimitEntry {
+            count: 1,
+            window_start: now,
+            window_secs: window_secs,
+            ..entry
+        },
+        None => LimitEntry {
+            key: key.to_owned(),
+            count: 1,
+            window_start: now,
+            window_secs: window_secs,
+            locked_until: None
+        }
+    }
+}
+
+/// Attempts to count one more event on the
+/// counter with the given key. If this operation
+/// succeeds, the updated counter is returned.
+/// If this operation fails, an error is returned.
+pub async fn bump_counter(
+    store: &LimitStore,
+    key: &String,
+    window_secs: i64
+) -> Result<LimitEntry, JadeErr> {
+    let now: i64 = unix_now();
+    match store {
+        LimitStore::Memory(entries) => {
+            let mut entries = match entries.lock() {
+                Ok(entries) => entries,
+                Err(e) => return Err::<LimitEntry, JadeErr>(JadeErr::new(&e.to_string()))
+            };
+            if entries.len() > MEMORY_PRUNE_THRESHOLD {
+                entries.retain(|_key, entry| {
+                    entry.window_start + entry.window_secs > now ||
+                    entry.locked_until.map(|until| until > now).unwrap_or(false)
+                });
+            }
+            let entry: LimitEntry = bump_entry(entries.get(key).cloned(), key, now, window_secs);
+            entries.insert(key.to_owned(), entry.clone());
+            Ok(entry)
+        },
+        LimitStore::Postgres(pool) => {
+            match sqlx::query_as!(
+                LimitEntry,
+                "INSERT INTO rate_limits (key, count, window_start, window_secs) VALUES ($1, 1, $2, $3) ON CONFLICT (key) DO UPDATE SET count = CASE WHEN rate_limits.window_start + $3 <= $2 THEN 1 ELSE rate_limits.count + 1 END, window_start = CASE WHEN rate_limits.window_start + $3 <= $2 THEN $2 ELSE rate_limits.window_start END, window_secs = $3 RETURNING *",
+                key,
+                now,
+                window_secs
+            )
+                .fetch_one(pool)
+                .await
+            {
+                Ok(entry) => Ok(entry),
+                Err(e) => Err::<LimitEntry, JadeErr>(JadeErr::database(&e))
+            }
+        }
+    }
+}
+
+/// Attempts to count a request against a
+/// limit of the given number of requests per
+/// window. If this operation fails, an error is
+/// returned.
+pub async fn hit_limit(
+    store: &LimitStore,
+    key: &String,
+    limit: i32,
+    window_secs: i64
+) -> Result<LimitDecision, JadeErr> {
+    let entry: LimitEntry = match bump_counter(store, key, window_secs).await {
+        Ok(entry) => entry,
+        Err(e) => return Err::<LimitDecision, JadeErr>(e)
+    };
+    Ok(
+        LimitDecision {
+            allowed: entry.count <= limit,
+            limit: limit,
+            remaining: (limit - entry.count).max(0),
+            reset_secs: (entry.window_start + window_secs - unix_now()).max(0)
+        }
+    )
+}
+
+/// Attempts to find out for how many more
+/// seconds the counter with the given key is
+/// locked. If the counter is not locked, nothing
+/// is returned. If this operation fails, an error
+/// is returned.
+pub async fn locked_for(
+    store: &LimitStore,
+    key: &String
+) -> Result<Option<i64>, JadeErr> {
+    let locked_until: Option<i64> = match store {
+        LimitStore::Memory(entries) => {
+            let entries = match entries.lock() {
+                Ok(entries) => entries,
+                Err(e) => return Err::<Option<i64>, JadeErr>(JadeErr::new(&e.to_string()))
+            };
+            entries.get(key).and_then(|entry| entry.locked_until)
+        },
+        LimitStore::Postgres(pool) => {
+            match sqlx::query_scalar!("SELECT locked_until FROM rate_limits WHERE key = $1", key)
+                .fetch_optional(pool)
+                .await
+            {
+                Ok(locked_until) => locked_until.flatten(),
+                Err(e) => return Err::<Option<i64>, JadeErr>(JadeErr::database(&e))
+            }
+        }
+    };
+    let now: i64 = unix_now();
+    Ok(locked_until.filter(|until| *until > now).map(|until| until - now))
+}
+
+/// Attempts to record a failed attempt on the
+/// counter with the given key. Once the given
+/// number of failures has been reached within
+/// the lockout period, the counter is locked
+/// for that period. If this operation fails,
+/// an error is returned.
+pub async fn record_failure(
+    store: &LimitStore,
+    key: &String,
+    max_failures: i32,
+    lockout_secs: i64
+) -> Result<(), JadeErr> {
+    let entry: LimitEntry = match bump_counter(store, key, lockout_secs).await {
+        Ok(entry) => entry,
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    if entry.count < max_failures {
+        return Ok(());
+    }
+    let now: i64 = unix_now();
+    let locked_until: i64 = now + lockout_secs;
+    match store {
+        LimitStore::Memory(entries) => {
+            let mut entries = match entries.lock() {
+                Ok(entries) => entries,
+                Err(e) => return Err::<(), JadeErr>(JadeErr::new(&e.to_string()))
+            };
+            entries.insert(
+                key.to_owned(),
+                LimitEntry {
+                    key: key.to_owned(),
+                    count: 0,
+                    window_start: now,
+                    window_secs: lockout_secs,
+                    locked_until: Some(locked_until)
+                }
+            );
+            Ok(())
+        },
+        LimitStore::Postgres(pool) => {
+            match sqlx::query!(
+                "UPDATE rate_limits SET count = $1, window_start = $2, window_secs = $3, locked_until = $4 WHERE key = $5",
+                0,
+                now,
+                lockout_secs,
+                locked_until,
+                key
+            )
+                .execute(pool)
+                .await
+            {
+                Ok(_feedback) => Ok(()),
+                Err(e) => Err::<(), JadeErr>(JadeErr::database(&e))
+            }
+        }
+    }
+}
+
+/// Attempts to forget all failed attempts
+/// recorded on the counter with the given key.
+/// If this operation fails, an error is returned.
+pub async fn clear_failures(
+    store: &LimitStore,
+    key: &String
+) -> Result<(), JadeErr> {
+    match store {
+        LimitStore::Memory(entries) => {
+            let mut entries = match entries.lock() {
+                Ok(entries) => entries,
+                Err(e) => return Err::<(), JadeErr>(JadeErr::new(&e.to_string()))
+            };
+            entries.remove(key);
+            Ok(())
+        },
+        LimitStore::Postgres(pool) => {
+            match sqlx::query!("DELETE FROM rate_limits WHERE key = $1", key)
+                .execute(pool)
+                .await
+            {
+                Ok(_feedback) => Ok(()),
+                Err(e) => Err::<(), JadeErr>(JadeErr::database(&e))
+            }
+        }
+    }
+}
+
+/// Works out the address of the client that
+/// sent a request from the address of the peer
+/// connected to Jade and the "X-Forwarded-For"
+/// header. The header is only honoured if the
+/// peer is one of the trusted proxies, and it is
+/// read from the right, skipping trusted proxies,
+/// so that clients cannot pick their own address.
+/// If the peer is unknown, nothing is returned.
+pub fn resolve_client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr]
+) -> Option<String> {
+    match peer {
+        Some(peer) => {
+            let mut client: IpAddr = peer;
+            if trusted_proxies.contains(&client) {
+                for hop in forwarded_for.unwrap_or("").rsplit(',') {
+                    match hop.trim().parse::<IpAddr>() {
+                        Ok(addr) => {
+                            client = addr;
+                            if trusted_proxies.contains(&addr) {}
+                            else {
+                                break;
+                            }
+                        },
+                        Err(_e) => break
+                    }
+                }
+            }
+            Some(client.to_string())
+        },
+        None => None
+    }
+}
+
+/// Works out the address of the client
+/// that sent the given request. If the
+/// address is unknown, nothing is returned.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<String> {
+    resolve_client_ip(
+        req.peer_addr().map(|addr| addr.ip()),
+        req.headers().get("X-Forwarded-For").and_then(|value| value.to_str().ok()),
+        trusted_proxies
+    )
+}
+
+/// Builds the key of the counter that
+/// tracks failed attempts to authenticate
+/// as the given account. Usernames are
+/// compared without regard to case, so the
+/// username is lowercased.
+pub fn account_fail_key(username: &String) -> String {
+    format!("auth-fail-user:{}", username.to_lowercase())
+}
+
+/// Builds the key of the counter that
+/// tracks failed attempts to authenticate
+/// from the given client address.
+pub fn ip_fail_key(client_ip: &String) -> String {
+    format!("auth-fail-ip:{}", client_ip)
+}
+
+/// Works out whether the given attempt passed
+/// the check of a password or a secret. Wrong
+/// credentials and unknown accounts or tokens
+/// failed it. Other errors, like invalid payloads
+/// or internal errors, say nothing about the
+/// credentials, so nothing is returned for them.
+pub fn credential_outcome<T>(attempt: &Result<T, JadeErr>) -> Option<bool> {
+    match attempt {
+        Ok(_attempt) => Some(true),
+        Err(e) if e.kind == JadeErrKind::Unauthorized || e.kind == JadeErrKind::NotFound => Some(false),
+        Err(_e) => None
+    }
+}
+
+/// Builds the response sent to clients
+/// that have to wait for the given number
+/// of seconds before trying again.
+pub fn too_many_requests(retry_after: i64, message: &str) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", retry_after.max(1).to_string()))
+        .json(error_body("too_many_requests", message))
+}
+
+/// Attempts to check whether an account is
+/// locked after too many failed attempts to
+/// authenticate as it, from any address. If the
+/// account is locked, the response telling the
+/// client how long to wait is returned. If this
+/// operation fails, an error is returned.
+pub async fn check_account_lock(
+    data: &Data<AppData>,
+    username: &String
+) -> Result<Option<HttpResponse>, JadeErr> {
+    match locked_for(&data.limiter, &account_fail_key(username)).await {
+        Ok(None) => Ok(None),
+        Ok(Some(seconds)) => Ok(Some(too_many_requests(seconds, "Too many failed attempts for this account."))),
+        Err(e) => Err::<Option<HttpResponse>, JadeErr>(e)
+    }
+}
+
+/// Attempts to record the outcome of the check
+/// of a password or a secret under the counter
+/// with the given key. Failed checks count towards
+/// a lockout and a passed check clears them.
+/// Attempts that never reached the check are not
+/// recorded. If this operation fails, an error
+/// is returned.
+pub async fn record_attempt<T>(
+    data: &Data<AppData>,
+    key: &String,
+    attempt: &Result<T, JadeErr>
+) -> Result<(), JadeErr> {
+    match credential_outcome(attempt) {
+        Some(true) => clear_failures(&data.limiter, key).await,
+        Some(false) => record_failure(
+            &data.limiter,
+            key,
+            data.auth_limits.max_failures,
+            data.auth_limits.lockout_secs
+        ).await,
+        None => Ok(())
+    }
+}
+
+/// Attempts to record the outcome of the check
+/// of a secret sent by the client that sent the
+/// given request. If this operation fails, an
+/// error is returned.
+pub async fn record_ip_attempt<T>(
+    req: &HttpRequest,
+    data: &Data<AppData>,
+    attempt: &Result<T, JadeErr>
+) -> Result<(), JadeErr> {
+    let client: String = client_ip(req, &data.auth_limits.trusted_proxies).unwrap_or("unknown".to_string());
+    record_attempt(data, &ip_fail_key(&client), attempt).await
+}
+
+/// Attempts to record the outcome of an attempt
+/// to authenticate as the given account from the
+/// client that sent the given request. The attempt
+/// counts towards the lockout of the account and
+/// towards the lockout of the client's address. If
+/// this operation fails, an error is returned.
+pub async fn record_account_attempt<T>(
+    req: &HttpRequest,
+    data: &Data<AppData>,
+    username: &String,
+    attempt: &Result<T, JadeErr>
+) -> Result<(), JadeErr> {
+    let _account_op: () = match record_attempt(data, &account_fail_key(username), attempt).await {
+        Ok(_account_op) => _account_op,
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    record_ip_attempt(req, data, attempt).await
+}
+
+/// A middleware that limits how often a single
+/// client address may submit a password or a
+/// secret to the routes that accept one. Addresses
+/// that exceed the limit or are locked after too
+/// many failed attempts are rejected with the
+/// status code 429 until they may try again. The
+/// routes record failed attempts themselves, once
+/// they have checked the credentials.
+pub async fn auth_rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if req.method() == Method::POST && AUTH_ROUTES.contains(&req.path()) {}
+    else {
+        return next.call(req).await.map(|res| res.map_into_left_body());
+    }
+    let data: Data<AppData> = match req.app_data::<Data<AppData>>() {
+        Some(data) => data.clone(),
+        None => return next.call(req).await.map(|res| res.map_into_left_body())
+    };
+    let client_ip: String = client_ip(req.request(), &data.auth_limits.trusted_proxies)
+        .unwrap_or("unknown".to_string());
+    let locked: Option<i64> = match locked_for(&data.limiter, &ip_fail_key(&client_ip)).await {
+        Ok(locked) => locked,
+        Err(e) => return Err(actix_web::Error::from(e))
+    };
+    if let Some(seconds) = locked {
+        let res: HttpResponse = too_many_requests(seconds, "Too many failed attempts from this address.");
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    let limits: &AuthLimitConfig = &data.auth_limits;
+    let decision: LimitDecision = match hit_limit(
+        &data.limiter,
+        &format!("auth-ip:{}", client_ip),
+        limits.ip_limit,
+        limits.window_secs
+    ).await {
+        Ok(decision) => decision,
+        Err(e) => return Err(actix_web::Error::from(e))
+    };
+    if decision.allowed {}
+    else {
+        let res: HttpResponse = too_many_requests(decision.reset_secs, "Too many requests from this address.");
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    next.call(req).await.map(|res| res.map_into_left_body())
+}
+
+/// Works out which group of
+/// routes the given path belongs
+/// to.
+pub fn route_group(path: &str) -> &'static str {
+    if MOOD_ROUTE_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+        "moods"
+    }
+    else if FILE_ROUTE_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+        "files"
+    }
+    else {
+        "default"
+    }
+}
+
+/// Gets the number of requests a
+/// client may make to the given group
+/// of routes per window.
+pub fn group_limit(limits: &ApiLimitConfig, group: &str) -> i32 {
+    match group {
+        "moods" => limits.moods_limit,
+        "files" => limits.files_limit,
+        _ => limits.default_limit
+    }
+}
+
+/// Adds the headers telling a client
+/// how much of its request budget is
+/// left to a response.
+pub fn insert_quota_headers(headers: &mut HeaderMap, decision: &LimitDecision) {
+    headers.insert(HeaderName::from_static("x-ratelimit-limit"), HeaderValue::from(decision.limit));
+    headers.insert(HeaderName::from_static("x-ratelimit-remaining"), HeaderValue::from(decision.remaining));
+    headers.insert(HeaderName::from_static("x-ratelimit-reset"), HeaderValue::from(decision.reset_secs));
+}
+
+/// A middleware that gives every client address
+/// and every API token a budget of requests per
+/// group of routes. Every response carries the
+/// remaining budget in the "X-RateLimit-*" headers.
+/// Requests over budget are rejected with the
+/// status code 429 until the window has passed.
+/// Requests with a valid API token count
+/// against both the token and the address.
+/// The token's secret is checked before its
+/// budget is counted, so that clients cannot
+/// use up the budget of a token they only
+/// know the public prefix of.
+pub async fn api_rate_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let data: Data<AppData> = match req.app_data::<Data<AppData>>() {
+        Some(data) => data.clone(),
+        None => return next.call(req).await.map(|res| res.map_into_left_body())
+    };
+    let group: &str = route_group(req.path());
+    let limit: i32 = group_limit(&data.api_limits, group);
+    let client_ip: String = client_ip(req.request(), &data.auth_limits.trusted_proxies)
+        .unwrap_or("unknown".to_string());
+    let mut keys: Vec<String> = vec![format!("api-ip:{}:{}", group, client_ip)];
+    if let Ok(token) = bearer_token(req.request()) {
+        if let Ok(verified) = get_api_token(&token, &data.pool).await {
+            keys.push(format!("api-token:{}:{}", group, verified.token_prefix));
+        }
+    }
+    let mut tightest: Option<LimitDecision> = None;
+    for key in &keys {
+        let decision: LimitDecision = match hit_limit(
+            &data.limiter,
+            key,
+            limit,
+            data.api_limits.window_secs
+        ).await {
+            Ok(decision) => decision,
+            Err(e) => return Err(actix_web::Error::from(e))
+        };
+        tightest = match tightest {
+            Some(known) if !known.allowed || (decision.allowed && known.remaining <= decision.remaining) => Some(known),
+            _ => Some(decision)
+        };
+    }
+    let decision: LimitDecision = match tightest {
+        Some(decision) => decision,
+        None => return next.call(req).await.map(|res| res.map_into_left_body())
+    };
+    if decision.allowed {}
+    else {
+        let mut res: HttpResponse = too_many_requests(decision.reset_secs, "The request budget for these routes has been used up.");
+        insert_quota_headers(res.headers_mut(), &decision);
+        return Ok(req.into_response(res).map_into_right_body());
+    }
+    let mut res = match next.call(req).await {
+        Ok(res) => res,
+        Err(e) => return Err(e)
+    };
+    insert_quota_headers(res.headers_mut(), &decision);
+    Ok(res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn memory_store() -> LimitStore {
+        LimitStore::Memory(Arc::new(Mutex::new(HashMap::new())))
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse::<IpAddr>().unwrap()
+    }
+
+    #[test]
+    fn counters_start_over_once_their_window_has_passed() {
+        let key: String = "test".to_string();
+        let first: LimitEntry = bump_entry(None, &key, 100, 60);
+        assert_eq!((first.count, first.window_start), (1, 100));
+        let second: LimitEntry = bump_entry(Some(first), &key, 159, 60);
+        assert_eq!((second.count, second.window_start), (2, 100));
+        let third: LimitEntry = bump_entry(Some(second), &key, 160, 60);
+        assert_eq!((third.count, third.window_start), (1, 160));
+    }
+
+    #[tokio::test]
+    async fn pruning_keeps_counters_with_longer_windows() {
+        let store: LimitStore = memory_store();
+        let now: i64 = unix_now();
+        if let LimitStore::Memory(entries) = &store {
+            let mut entries = entries.lock().unwrap();
+            for index in 0..MEMORY_PRUNE_THRESHOLD {
+                let key: String = format!("api-ip:default:{}", index);
+                entries.insert(key.clone(), bump_entry(None, &key, now - 100, 60));
+            }
+            let key: String = "auth-fail-ip:192.0.2.1".to_string();
+            entries.insert(key.clone(), bump_entry(None, &key, now - 100, 900));
+        }
+        bump_counter(&store, &"api-ip:default:new".to_string(), 60).await.unwrap();
+        if let LimitStore::Memory(entries) = &store {
+            let entries = entries.lock().unwrap();
+            assert!(entries.contains_key("auth-fail-ip:192.0.2.1"));
+            assert!(!entries.contains_key("api-ip:default:0"));
+        }
+    }
+
+    #[test]
+    fn forwarded_addresses_are_only_honoured_from_trusted_proxies() {
+        let proxies: [IpAddr; 2] = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let table: [(Option<&str>, Option<&str>, Option<&str>); 7] = [
+            (Some("203.0.113.9"), Some("198.51.100.1"), Some("203.0.113.9")),
+            (Some("10.0.0.1"), Some("198.51.100.1"), Some("198.51.100.1")),
+            (Some("10.0.0.1"), Some("192.0.2.7, 198.51.100.1"), Some("198.51.100.1")),
+            (Some("10.0.0.1"), Some("198.51.100.1, 10.0.0.2"), Some("198.51.100.1")),
+            (Some("10.0.0.1"), Some("not an address"), Some("10.0.0.1")),
+            (Some("10.0.0.1"), None, Some("10.0.0.1")),
+            (None, Some("198.51.100.1"), None)
+        ];
+        for (peer, forwarded_for, expected) in table {
+            assert_eq!(
+                resolve_client_ip(peer.map(ip), forwarded_for, &proxies),
+                expected.map(|addr| addr.to_string())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn failures_from_several_addresses_lock_the_account() {
+        let store: LimitStore = memory_store();
+        let clients: [String; 2] = ["192.0.2.1".to_string(), "198.51.100.1".to_string()];
+        for (attempt, client) in clients.iter().cycle().take(3).enumerate() {
+            assert_eq!(locked_for(&store, &account_fail_key(&"alice".to_string())).await.unwrap(), None);
+            let username: String = if attempt % 2 == 0 { "alice".to_string() } else { "ALICE".to_string() };
+            record_failure(&store, &account_fail_key(&username), 3, 900).await.unwrap();
+            record_failure(&store, &ip_fail_key(client), 3, 900).await.unwrap();
+        }
+        assert!(locked_for(&store, &account_fail_key(&"alice".to_string())).await.unwrap().is_some());
+        assert_eq!(locked_for(&store, &ip_fail_key(&clients[1])).await.unwrap(), None);
+    }
+
+    #[test]
+    fn only_credential_checks_count_as_attempts() {
+        let table: [(Result<(), JadeErr>, Option<bool>); 5] = [
+            (Ok(()), Some(true)),
+            (Err(JadeErr::unauthorized("wrong password")), Some(false)),
+            (Err(JadeErr::not_found("no such user")), Some(false)),
+            (Err(JadeErr::validation("bad payload")), None),
+            (Err(JadeErr::new("database down")), None)
+        ];
+        for (attempt, expected) in table {
+            assert_eq!(credential_outcome(&attempt), expected);
+        }
+    }
+
+    #[test]
+    fn routes_are_budgeted_in_groups() {
+        let limits: ApiLimitConfig = ApiLimitConfig {
+            default_limit: 300,
+            moods_limit: 60,
+            files_limit: 30,
+            window_secs: 60
+        };
+        let table: [(&str, &str, i32); 4] = [
+            ("/mood/alice", "moods", 60),
+            ("/moods/get", "moods", 60),
+            ("/files/upload", "files", 30),
+            ("/token/create", "default", 300)
+        ];
+        for (path, group, limit) in table {
+            assert_eq!(route_group(path), group);
+            assert_eq!(group_limit(&limits, group), limit);
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_limit_are_rejected() {
+        let store: LimitStore = memory_store();
+        let key: String = "api-ip:default:192.0.2.1".to_string();
+        for remaining in [1, 0] {
+            let decision: LimitDecision = hit_limit(&store, &key, 2, 60).await.unwrap();
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+        let decision: LimitDecision = hit_limit(&store, &key, 2, 60).await.unwrap();
+        assert!(!decision.allowed);
+        assert!(decision.reset_secs > 0);
+    }
+
+    #[tokio::test]
+    async fn repeated_failures_lock_a_counter_until_cleared() {
+        let store: LimitStore = memory_store();
+        let key: String = "auth-fail-ip:192.0.2.1".to_string();
+        for _attempt in 0..2 {
+            record_failure(&store, &key, 3, 900).await.unwrap();
+            assert_eq!(locked_for(&store, &key).await.unwrap(), None);
+        }
+        record_failure(&store, &key, 3, 900).await.unwrap();
+        let locked: Option<i64> = locked_for(&store, &key).await.unwrap();
+        assert!(locked.map(|seconds| seconds > 0 && seconds <= 900).unwrap_or(false));
+        clear_failures(&store, &key).await.unwrap();
+        assert_eq!(locked_for(&store, &key).await.unwrap(), None);
+    }
+}
\ No newline at end of file
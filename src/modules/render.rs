@@ -0,0 +1,459 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the function
+/// to convert a timestamp for
+/// RSS feeds.
+use super::time::to_rfc2822;
+
+/// Importing the function
+/// to convert a timestamp for
+/// Atom feeds and JSON feeds.
+use super::time::to_rfc3339;
+
+/// Importing the function
+/// to describe how long ago
+/// a mood was set.
+use super::time::time_ago;
+
+/// Importing the stucture that
+/// contains information on
+/// the mood of a Jade user.
+use super::units::JadeMood;
+
+/// Importing the structure
+/// containing a feed in the
+/// JSON Feed format.
+use super::units::JsonFeed;
+
+/// Importing the structure
+/// containing a single entry
+/// of a JSON feed.
+use super::units::JsonFeedItem;
+
+/// Importing the structure
+/// containing information on a
+/// registered OAuth application.
+use super::units::OAuthClient;
+
+/// Importing the structure
+/// containing an OAuth
+/// authorization request.
+use super::units::AuthorizeQuery;
+
+/// Escapes the characters of a string that
+/// carry meaning in XML and HTML documents.
+pub fn escape_markup(text: &str) -> String {
+    let mut escaped: String = String::new();
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(character)
+        }
+    }
+    escaped
+}
+
+/// Returns the URL of a user's
+/// profile on the given instance.
+pub fn profile_url(instance_url: &String, username: &String) -> String {
+    format!("{}/users/{}", instance_url, username)
+}
+
+/// Returns the URL uniquely identifying
+/// a single mood of a user.
+pub fn mood_url(instance_url: &String, mood: &JadeMood) -> String {
+    format!("{}#{}", profile_url(instance_url, &mood.username), mood.created_at)
+}
+
+/// Attempts to render a user's mood history as
+/// an RSS 2.0 feed. If this operation fails, an
+/// error is returned.
+pub fn render_rss_feed(
+    instance_url: &String,
+    username: &String,
+    moods: &Vec<JadeMood>
+) -> Result<String, JadeErr> {
+    let mut items: String = String::new();
+    for mood in moods {
+        let published: String = match to_rfc2822(&mood.created_at){
+            Ok(published) => published,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
+        items.push_str(
+            &format!(
+                "<item><title>{}</title><link>{}</link><guid isPermaLink=\"false\">{}</guid><pubDate>{}</pubDate></item>",
+                escape_markup(&mood.mood),
+                escape_markup(&profile_url(instance_url, username)),
+                escape_markup(&mood_url(instance_url, mood)),
+                published
+            )
+        );
+    }
+    Ok(
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><rss version=\"2.0\"><channel><title>{}'s moods</title><link>{}</link><description>The moods of {} on Jade.</description>{}</channel></rss>",
+            escape_markup(username),
+            escape_markup(&profile_url(instance_url, username)),
+            escape_markup(username),
+            items
+        )
+    )
+}
+
+/// Attempts to render a user's mood history as
+/// an Atom feed. If this operation fails, an
+/// error is returned.
+pub fn render_atom_feed(
+    instance_url: &String,
+    username: &String,
+    moods: &Vec<JadeMood>
+) -> Result<String, JadeErr> {
+    let mut entries: String = String::new();
+    let mut updated: String = "1970-01-01T00:00:00+00:00".to_string();
+    for mood in moods {
+        let published: String = match to_rfc3339(&mood.created_at){
+            Ok(published) => published,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
+        if entries.is_empty() {
+            updated = published.clone();
+        }
+        entries.push_str(
+            &format!(
+                "<entry><title>{}</title><id>{}</id><link href=\"{}\"/><updated>{}</updated></entry>",
+                escape_markup(&mood.mood),
+                escape_markup(&mood_url(instance_url, mood)),
+                escape_markup(&profile_url(instance_url, username)),
+                published
+            )
+        );
+    }
+    Ok(
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?><feed xmlns=\"http://www.w3.org/2005/Atom\"><title>{}'s moods</title><id>{}</id><link href=\"{}\"/><link rel=\"self\" href=\"{}/feed.atom\"/><updated>{}</updated><author><name>{}</name></author>{}</feed>",
+            escape_markup(username),
+            escape_markup(&profile_url(instance_url, username)),
+            escape_markup(&profile_url(instance_url, username)),
+            escape_markup(&profile_url(instance_url, username)),
+            updated,
+            escape_markup(username),
+            entries
+        )
+    )
+}
+
+/// Attempts to render a user's mood history as
+/// a feed in the JSON Feed format. If this operation
+/// fails, an error is returned.
+pub fn render_json_feed(
+    instance_url: &String,
+    username: &String,
+    moods: &Vec<JadeMood>
+) -> Result<JsonFeed, JadeErr> {
+    let mut items: Vec<JsonFeedItem> = Vec::new();
+    for mood in moods {
+        let published: String = match to_rfc3339(&mood.created_at){
+            Ok(published) => published,
+            Err(e) => return Err::<JsonFeed, JadeErr>(e)
+        };
+        items.push(
+            JsonFeedItem {
+                id: mood_url(instance_url, mood),
+                url: profile_url(instance_url, username),
+                title: mood.mood.clone(),
+                content_text: mood.mood.clone(),
+                date_published: published
+            }
+        );
+    }
+    Ok(
+        JsonFeed {
+            version: "https://jsonfeed.org/version/1.1".to_string(),
+            title: format!("{}'s moods", username),
+            home_page_url: profile_url(instance_url, username),
+            feed_url: format!("{}/feed.json", profile_url(instance_url, username)),
+            items: items
+        }
+    )
+}
+
+/// Returns the approximate width in pixels
+/// a text takes up in a badge.
+pub fn badge_text_width(text: &str) -> usize {
+    text.chars().count() * 7 + 10
+}
+
+/// Attempts to describe the current mood of a
+/// user for a badge or a widget, for example
+/// "happy, updated 5 minutes ago". If the user
+/// has no current mood, "no mood" is returned.
+/// If this operation fails, an error is returned.
+pub fn describe_mood(mood: &Option<JadeMood>) -> Result<String, JadeErr> {
+    match mood {
+        Some(mood) => {
+            let updated: String = match time_ago(&mood.created_at){
+                Ok(updated) => updated,
+                Err(e) => return Err::<String, JadeErr>(e)
+            };
+            Ok(format!("{}, updated {}", mood.mood, updated))
+        },
+        None => Ok("no mood".to_string())
+    }
+}
+
+/// Attempts to render a user's current mood
+/// as a shields-style SVG badge. If this operation
+/// fails, an error is returned.
+pub fn render_badge(
+    username: &String,
+    mood: &Option<JadeMood>
+) -> Result<String, JadeErr> {
+    let message: String = match describe_mood(mood){
+        Ok(message) => message,
+        Err(e) => return Err::<String, JadeErr>(e)
+    };
+    let label_width: usize = badge_text_width(username);
+    let message_width: usize = badge_text_width(&message);
+    let width: usize = label_width + message_width;
+    Ok(
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"20\" role=\"img\" aria-label=\"{label}: {message}\"><title>{label}: {message}</title><linearGradient id=\"s\" x2=\"0\" y2=\"100%\"><stop offset=\"0\" stop-color=\"#bbb\" stop-opacity=\".1\"/><stop offset=\"1\" stop-opacity=\".1\"/></linearGradient><clipPath id=\"r\"><rect width=\"{width}\" height=\"20\" rx=\"3\" fill=\"#fff\"/></clipPath><g clip-path=\"url(#r)\"><rect width=\"{label_width}\" height=\"20\" fill=\"#555\"/><rect x=\"{label_width}\" width=\"{message_width}\" height=\"20\" fill=\"#00a86b\"/><rect width=\"{width}\" height=\"20\" fill=\"url(#s)\"/></g><g fill=\"#fff\" text-anchor=\"middle\" font-family=\"Verdana,Geneva,DejaVu Sans,sans-serif\" font-size=\"11\"><text x=\"{label_x}\" y=\"14\">{label}</text><text x=\"{message_x}\" y=\"14\">{message}</text></g></svg>",
+            width = width,
+            label_width = label_width,
+            message_width = message_width,
+            label_x = label_width / 2,
+            message_x = label_width + message_width / 2,
+            label = escape_markup(username),
+            message = escape_markup(&message)
+        )
+    )
+}
+
+/// Attempts to render a user's current mood
+/// as a small HTML document that can be embedded
+/// with an iframe. If this operation fails, an error
+/// is returned.
+pub fn render_widget(
+    instance_url: &String,
+    username: &String,
+    mood: &Option<JadeMood>
+) -> Result<String, JadeErr> {
+    let message: String = match describe_mood(mood){
+        Ok(message) => message,
+        Err(e) => return Err::<String, JadeErr>(e)
+    };
+    Ok(
+        format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{username}'s mood</title><style>body{{margin:0;font-family:sans-serif;font-size:14px}}a{{color:inherit;text-decoration:none}}.jade-widget{{padding:8px 12px;border:1px solid #00a86b;border-radius:6px}}.jade-user{{font-weight:bold}}</style></head><body><a class=\"jade-widget\" href=\"{url}\" target=\"_blank\" rel=\"noopener\"><span class=\"jade-user\">{username}</span> is feeling {message}</a></body></html>",
+            username = escape_markup(username),
+            url = escape_markup(&profile_url(instance_url, username)),
+            message = escape_markup(&message)
+        )
+    )
+}
+
+/// The number of past moods shown
+/// on a user's profile page.
+pub const PROFILE_HISTORY_LENGTH: usize = 10;
+
+/// The number of newest moods
+/// included in a user's feeds.
+pub const FEED_LENGTH: i64 = 50;
+
+/// Returns the media type of an uploaded
+/// image based on the extension of its file
+/// name. Files that are not known image types
+/// are served as binary data.
+pub fn image_content_type(file_name: &String) -> String {
+    let lowered: String = file_name.to_lowercase();
+    let content_type: &str = if lowered.ends_with(".png") {
+        "image/png"
+    }
+    else if lowered.ends_with(".jpg") || lowered.ends_with(".jpeg") {
+        "image/jpeg"
+    }
+    else if lowered.ends_with(".gif") {
+        "image/gif"
+    }
+    else if lowered.ends_with(".webp") {
+        "image/webp"
+    }
+    else {
+        "application/octet-stream"
+    };
+    content_type.to_string()
+}
+
+/// Attempts to render a user's public profile
+/// page with their current mood, their recent
+/// moods, their avatar, and meta tags for link
+/// previews. If this operation fails, an error is
+/// returned.
+pub fn render_profile(
+    instance_url: &String,
+    username: &String,
+    current: &Option<JadeMood>,
+    moods: &Vec<JadeMood>,
+    has_avatar: bool
+) -> Result<String, JadeErr> {
+    let message: String = match describe_mood(current){
+        Ok(message) => message,
+        Err(e) => return Err::<String, JadeErr>(e)
+    };
+    let url: String = profile_url(instance_url, username);
+    let mut history: String = String::new();
+    for mood in moods.iter().filter(|mood| !mood.is_active).take(PROFILE_HISTORY_LENGTH) {
+        let published: String = match to_rfc3339(&mood.created_at){
+            Ok(published) => published,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
+        history.push_str(
+            &format!(
+                "<li><span class=\"jade-mood\">{}</span> <time datetime=\"{}\">{}</time></li>",
+                escape_markup(&mood.mood),
+                published,
+                escape_markup(&mood.created_at)
+            )
+        );
+    }
+    if history.is_empty() {
+        history.push_str("<li>No earlier moods.</li>");
+    }
+    let mut image_meta: String = String::new();
+    let mut avatar: String = String::new();
+    if has_avatar {
+        let avatar_url: String = format!("{}/avatar", url);
+        image_meta = format!(
+            "<meta property=\"og:image\" content=\"{0}\"><meta name=\"twitter:image\" content=\"{0}\">",
+            escape_markup(&avatar_url)
+        );
+        avatar = format!(
+            "<img class=\"jade-avatar\" src=\"{}\" alt=\"{}'s avatar\" width=\"96\" height=\"96\">",
+            escape_markup(&avatar_url),
+            escape_markup(username)
+        );
+    }
+    Ok(
+        format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"><title>{username} on Jade</title><link rel=\"canonical\" href=\"{url}\"><link rel=\"alternate\" type=\"application/atom+xml\" href=\"{url}/feed.atom\"><link rel=\"alternate\" type=\"application/rss+xml\" href=\"{url}/feed.rss\"><link rel=\"alternate\" type=\"application/feed+json\" href=\"{url}/feed.json\"><meta name=\"description\" content=\"{username} is feeling {message}\"><meta property=\"og:type\" content=\"profile\"><meta property=\"og:site_name\" content=\"Jade\"><meta property=\"og:title\" content=\"{username} on Jade\"><meta property=\"og:description\" content=\"{username} is feeling {message}\"><meta property=\"og:url\" content=\"{url}\"><meta property=\"profile:username\" content=\"{username}\"><meta name=\"twitter:card\" content=\"summary\"><meta name=\"twitter:title\" content=\"{username} on Jade\"><meta name=\"twitter:description\" content=\"{username} is feeling {message}\">{image_meta}<style>body{{max-width:36rem;margin:2rem auto;padding:0 1rem;font-family:sans-serif}}.jade-avatar{{border-radius:50%}}.jade-current{{font-size:1.5rem}}</style></head><body><header>{avatar}<h1>{username}</h1></header><main><p class=\"jade-current\">{username} is feeling {message}</p><h2>Recent moods</h2><ul>{history}</ul></main></body></html>",
+            username = escape_markup(username),
+            url = escape_markup(&url),
+            message = escape_markup(&message),
+            image_meta = image_meta,
+            avatar = avatar,
+            history = history
+        )
+    )
+}
+
+/// Renders the page on which a logged-in user
+/// approves or denies an OAuth application's
+/// request for access to their account. The
+/// authorization request is carried along in
+/// hidden form fields together with the CSRF
+/// token of the user's session.
+pub fn render_consent_page(
+    client: &OAuthClient,
+    username: &String,
+    scopes: &Vec<String>,
+    query: &AuthorizeQuery,
+    csrf_token: &String
+) -> String {
+    let mut scope_list: String = String::new();
+    for scope in scopes {
+        scope_list.push_str(&format!("<li><code>{}</code></li>", escape_markup(scope)));
+    }
+    if scope_list.is_empty() {
+        scope_list.push_str("<li>No permissions.</li>");
+    }
+    let mut fields: String = String::new();
+    let hidden: Vec<(&str, String)> = vec![
+        ("response_type", query.response_type.clone()),
+        ("client_id", query.client_id.clone()),
+        ("redirect_uri", query.redirect_uri.clone()),
+        ("scope", scopes.join(" ")),
+        ("state", query.state.clone().unwrap_or_default()),
+        ("code_challenge", query.code_challenge.clone()),
+        ("code_challenge_method", query.code_challenge_method.clone()),
+        ("csrf_token", csrf_token.clone())
+    ];
+    for (name, value) in hidden {
+        fields.push_str(
+            &format!(
+                "<input type=\"hidden\" name=\"{}\" value=\"{}\">",
+                name,
+                escape_markup(&value)
+            )
+        );
+    }
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"><title>Authorize {client_name}</title><style>body{{max-width:36rem;margin:2rem auto;padding:0 1rem;font-family:sans-serif}}</style></head><body><main><h1>Authorize {client_name}</h1><p>{client_name} would like to access the Jade account of {username} with the following permissions:</p><ul>{scope_list}</ul><form method=\"post\" action=\"/oauth/authorize\">{fields}<button type=\"submit\" name=\"decision\" value=\"allow\">Allow</button> <button type=\"submit\" name=\"decision\" value=\"deny\">Deny</button></form></main></body></html>",
+        client_name = escape_markup(&client.client_name),
+        username = escape_markup(username),
+        scope_list = scope_list,
+        fields = fields
+    )
+}
+
+/// Renders the page on which a user logs in
+/// with their password before they are sent
+/// back to the given path, for example to the
+/// consent screen of an OAuth application. If
+/// an earlier attempt failed, its message is
+/// shown above the form.
+pub fn render_login_page(return_to: &String, message: &Option<String>) -> String {
+    let notice: String = match message {
+        Some(message) => format!("<p role=\"alert\">{}</p>", escape_markup(message)),
+        None => String::new()
+    };
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"><title>Log in to Jade</title><style>body{{max-width:36rem;margin:2rem auto;padding:0 1rem;font-family:sans-serif}}label{{display:block;margin-bottom:1rem}}input{{display:block}}</style></head><body><main><h1>Log in to Jade</h1>{notice}<form method=\"post\" action=\"/auth/login/form\"><input type=\"hidden\" name=\"return_to\" value=\"{return_to}\"><label>Username<input type=\"text\" name=\"username\" autocomplete=\"username\" required></label><label>Password<input type=\"password\" name=\"password\" autocomplete=\"current-password\" required></label><label>Two-factor code, if enabled<input type=\"text\" name=\"totp_code\" autocomplete=\"one-time-code\"></label><button type=\"submit\">Log in</button></form></main></body></html>",
+        notice = notice,
+        return_to = escape_markup(return_to)
+    )
+}
+
+/// Renders a page for a link from an email.
+/// If an action is given, the page asks the user
+/// to confirm with a button that sends the form to
+/// the action, so that opening the link alone does
+/// not change anything. Otherwise the page only
+/// shows the given message.
+pub fn render_link_page(title: &str, message: &str, action: &Option<String>, button: &str) -> String {
+    let form: String = match action {
+        Some(action) => format!(
+            "<form method=\"post\" action=\"{}\"><button type=\"submit\">{}</button></form>",
+            escape_markup(action),
+            escape_markup(button)
+        ),
+        None => String::new()
+    };
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"><title>{title}</title><style>body{{max-width:36rem;margin:2rem auto;padding:0 1rem;font-family:sans-serif}}</style></head><body><main><h1>{title}</h1><p>{message}</p>{form}</main></body></html>",
+        title = escape_markup(title),
+        message = escape_markup(message),
+        form = form
+    )
+}
+
+/// Renders the page showing an authorization
+/// code to a user whose application cannot
+/// receive redirects.
+pub fn render_code_page(client: &OAuthClient, code: &String) -> String {
+    format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width, initial-scale=1\"><title>Authorization code</title><style>body{{max-width:36rem;margin:2rem auto;padding:0 1rem;font-family:sans-serif}}</style></head><body><main><h1>Authorization code</h1><p>Copy this code and paste it into {client_name}:</p><p><code>{code}</code></p></main></body></html>",
+        client_name = escape_markup(&client.client_name),
+        code = escape_markup(code)
+    )
+}
\ No newline at end of file
@@ -89,6 +89,27 @@ use super::api::create_user;
 /// a user's email.
 use super::api::verify_email;
 
+/// Importing the API route
+/// to show the page the link
+/// in a verification email
+/// opens.
+use super::api::verify_email_page;
+
+/// Importing the API route
+/// to verify an email address
+/// with the form on that page.
+use super::api::verify_email_form;
+
+/// Importing the function
+/// to give verification tokens
+/// without an expiry one.
+use super::rw::expire_legacy_verification_tokens;
+
+/// Importing the function
+/// to count email addresses
+/// stored as hashes.
+use super::rw::count_legacy_emails;
+
 /// Importing the "ConfigData"
 /// structure for explicit typing.
 use super::units::ConfigData;
@@ -103,6 +124,191 @@ use super::api::create_token;
 /// a user's API token.
 use super::api::delete_token;
 
+/// Importing the service
+/// function for logging in.
+use super::api::login;
+
+/// Importing the API route
+/// function for showing the
+/// login page.
+use super::api::login_page;
+
+/// Importing the API route
+/// function for logging in
+/// with the login form.
+use super::api::login_form;
+
+/// Importing the service
+/// function for logging out.
+use super::api::logout;
+
+/// Importing the service
+/// function for registering
+/// an OAuth application.
+use super::api::create_oauth_client;
+
+/// Importing the service
+/// function for registering
+/// an application the way
+/// Mastodon clients do.
+use super::api::create_mastodon_app;
+
+/// Importing the service
+/// function for showing the
+/// OAuth consent screen.
+use super::api::oauth_authorize;
+
+/// Importing the service
+/// function for recording
+/// the user's decision on
+/// the OAuth consent screen.
+use super::api::oauth_confirm;
+
+/// Importing the service
+/// function for issuing
+/// tokens through OAuth.
+use super::api::oauth_token;
+
+/// Importing the service
+/// function for starting
+/// a TOTP enrollment.
+use super::api::start_totp_enrollment;
+
+/// Importing the service
+/// function for confirming
+/// a TOTP enrollment.
+use super::api::confirm_totp_enrollment;
+
+/// Importing the service
+/// function for turning off
+/// two-factor authentication.
+use super::api::remove_totp;
+
+/// Importing the service
+/// function for requesting
+/// a password reset link.
+use super::api::forgot_password;
+
+/// Importing the service
+/// function for setting a
+/// new password with a reset
+/// token.
+use super::api::confirm_password_reset;
+
+/// Importing the store
+/// rate-limiting counters
+/// are kept in.
+use super::ratelimit::LimitStore;
+
+/// Importing the structure
+/// containing the keys email
+/// addresses are protected with.
+use super::crypto::EmailKeys;
+
+/// Importing the function
+/// to encrypt TOTP secrets
+/// stored in plain text.
+use super::rw::encrypt_legacy_totp_secrets;
+
+/// Importing the API route
+/// to send a new verification
+/// email.
+use super::api::resend_verification;
+
+/// Importing the API route
+/// to confirm a change of a
+/// user's email address.
+use super::api::confirm_email;
+
+/// Importing the API route
+/// to show the page the link
+/// to confirm a change of a
+/// user's email address opens.
+use super::api::confirm_email_page;
+
+/// Importing the API route
+/// to confirm a change of a
+/// user's email address with
+/// the form on that page.
+use super::api::confirm_email_form;
+
+/// Importing the API route
+/// to revert a change of a
+/// user's email address.
+use super::api::revert_email;
+
+/// Importing the API route
+/// to show the page the link
+/// to revert a change of a
+/// user's email address opens.
+use super::api::revert_email_page;
+
+/// Importing the API route
+/// to revert a change of a
+/// user's email address with
+/// the form on that page.
+use super::api::revert_email_form;
+
+/// Importing the API route
+/// to undo the deletion of a
+/// user's account.
+use super::api::undo_delete_user;
+
+/// Importing the API route
+/// to show the page the link
+/// to undo the deletion of a
+/// user's account opens.
+use super::api::undo_delete_user_page;
+
+/// Importing the API route
+/// to undo the deletion of a
+/// user's account with the
+/// form on that page.
+use super::api::undo_delete_user_form;
+
+/// Importing the API route
+/// to export a user's data.
+use super::api::export_user_data;
+
+/// Importing the API route
+/// to import a user's data.
+use super::api::import_user_data;
+
+/// Importing the function
+/// to purge accounts whose
+/// grace period has passed.
+use super::rw::purge_deleted_users;
+
+/// Importing the "Duration"
+/// structure to set the interval
+/// of the purge task.
+use std::time::Duration;
+
+/// Importing the "interval"
+/// function to run the purge task
+/// periodically.
+use tokio::time::interval;
+
+/// Importing the "Interval"
+/// structure the purge task
+/// waits on.
+use tokio::time::Interval;
+
+/// Importing the middleware
+/// limiting authentication
+/// endpoints.
+use super::ratelimit::auth_rate_limit;
+
+/// Importing the middleware
+/// enforcing the request budgets
+/// of all routes.
+use super::ratelimit::api_rate_limit;
+
+/// Importing the "from_fn"
+/// function to turn a function
+/// into middleware.
+use actix_web::middleware::from_fn;
+
 /// Importing the "Postgres"
 /// structure from the "sqlx"
 /// crate.
@@ -118,6 +324,56 @@ use super::api::change_user_pwd;
 /// a file.
 use super::api::upload_user_file;
 
+/// Importing the API route
+/// function for reading an
+/// uploaded file.
+use super::api::get_file;
+
+/// Importing the service
+/// function for streaming
+/// mood updates.
+use super::api::stream_moods;
+
+/// Importing the service
+/// function for opening a
+/// websocket for mood updates.
+use super::api::mood_socket;
+
+/// Importing the service
+/// function for a user's
+/// RSS feed.
+use super::api::user_feed_rss;
+
+/// Importing the service
+/// function for a user's
+/// Atom feed.
+use super::api::user_feed_atom;
+
+/// Importing the service
+/// function for a user's
+/// JSON feed.
+use super::api::user_feed_json;
+
+/// Importing the service
+/// function for a user's
+/// mood badge.
+use super::api::user_badge;
+
+/// Importing the service
+/// function for a user's
+/// mood widget.
+use super::api::user_widget;
+
+/// Importing the service
+/// function for a user's
+/// profile page.
+use super::api::user_profile;
+
+/// Importing the service
+/// function for a user's
+/// avatar.
+use super::api::user_avatar;
+
 /// Importing the service
 /// function for changing
 /// a user's email address.
@@ -128,45 +384,185 @@ use super::api::change_user_email;
 /// to the PostgreSQL database.
 use super::utils::create_connection;
 
+/// Importing the function
+/// to create the channel mood
+/// updates are handed out on.
+use tokio::sync::broadcast::channel;
+
+/// Importing the function
+/// to listen for mood updates.
+use super::events::run_mood_listener;
+
+/// Importing the number of
+/// mood updates kept for slow
+/// streams.
+use super::events::MOOD_EVENT_CAPACITY;
+
+/// Importing the "JsonConfig"
+/// structure to configure how JSON
+/// payloads are read.
+use actix_web::web::JsonConfig;
+
+/// Importing the function to
+/// turn unreadable JSON payloads
+/// into errors.
+use super::err::json_payload_error;
+
+/// Importing the "MultipartFormConfig"
+/// structure to configure how multipart
+/// forms are read.
+use actix_multipart::form::MultipartFormConfig;
+
+/// Importing the function to
+/// turn unreadable multipart forms
+/// into errors.
+use super::err::multipart_payload_error;
+
 /// Importing the "DefaultHeaders" structure
 /// to set custom headers.
 use actix_web::middleware::DefaultHeaders;
 
+/// The number of seconds between
+/// two runs of the task purging
+/// deleted accounts.
+pub const PURGE_INTERVAL_SECS: u64 = 300;
+
+/// Purges every account whose grace period
+/// has passed every few minutes for as long as
+/// the app runs. A failed run is reported and
+/// tried again on the next tick.
+pub async fn run_purge_task(pool: Pool<Postgres>) {
+    let mut ticker: Interval = interval(Duration::from_secs(PURGE_INTERVAL_SECS));
+    loop {
+        ticker.tick().await;
+        match purge_deleted_users(&pool).await {
+            Ok(purged) => if purged > 0 {
+                log::info!("Purged {} deleted accounts.", purged);
+            },
+            Err(e) => log::error!("Could not purge deleted accounts: {}", &e.to_string())
+        };
+    }
+}
+
 /// Attempts to run the app with the supplied instance of the
 /// "ConfigData" structure.s
 pub async fn run_app(config: &ConfigData) -> Result<(), JadeErr> {
     let app_addr: String = format!("{}:{}", config.actix_host, config.actix_port);
     let connection: Pool<Postgres> = match create_connection(&config.db_url).await{
         Ok(connection) => connection,
-        Err(e) => return Err::<(), JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    let limiter: LimitStore = match LimitStore::new(&config.auth_limits.store, &connection){
+        Ok(limiter) => limiter,
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    let email_keys: EmailKeys = match EmailKeys::new(&config.email_key){
+        Ok(email_keys) => email_keys,
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    match encrypt_legacy_totp_secrets(&email_keys, &connection).await {
+        Ok(0) => {},
+        Ok(count) => log::info!("Encrypted {} TOTP secrets stored in plain text.", count),
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    match expire_legacy_verification_tokens(&connection).await {
+        Ok(0) => {},
+        Ok(count) => log::info!("Set an expiry on {} verification tokens that had none.", count),
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    match count_legacy_emails(&connection).await {
+        Ok(0) => {},
+        Ok(count) => log::warn!("{} email addresses are still stored as hashes by an earlier version of Jade. They are encrypted once their users supply them again. Until then, every new address is checked against each of them.", count),
+        Err(e) => return Err::<(), JadeErr>(e)
     };
-    let data: Data<AppData> = Data::new(AppData::new(&connection, &config.smtp_server));
+    let (mood_events, _receiver) = channel::<String>(MOOD_EVENT_CAPACITY);
+    let data: Data<AppData> = Data::new(
+        AppData::new(
+            &connection,
+            &config.smtp_server,
+            &config.api_domain,
+            &config.auth_limits,
+            &config.api_limits,
+            &limiter,
+            &email_keys,
+            &config.deletion_grace_secs,
+            &mood_events
+        )
+    );
+    tokio::spawn(run_purge_task(connection.clone()));
+    tokio::spawn(run_mood_listener(connection.clone(), mood_events.clone()));
     let server = match HttpServer::new(
         move || {
-            let cors = Cors::permissive()
+            let cors = Cors::default()
                 .allow_any_origin()
-                .allowed_methods(vec!["GET", "POST"]);
+                .send_wildcard()
+                .allowed_methods(vec!["GET", "POST"])
+                .allow_any_header()
+                .expose_any_header()
+                .max_age(3600);
             App::new()
+                .wrap(from_fn(auth_rate_limit))
+                .wrap(from_fn(api_rate_limit))
                 .wrap(cors)
                 .wrap(DefaultHeaders::new()
                     .add(("Access-Control-Allow-Origin", "*"))
                     .add(("Access-Control-Allow-Methods", "GET,POST"))
-                    .add(("Access-Control-Allow-Headers", "Origin, X-Requested-With, Content-Type, Accept"))
+                    .add(("Access-Control-Allow-Headers", "Origin, X-Requested-With, Content-Type, Accept, Authorization, X-CSRF-Token"))
                 )
                 .app_data(data.clone())
+                .app_data(JsonConfig::default().error_handler(json_payload_error))
+                .app_data(MultipartFormConfig::default().error_handler(multipart_payload_error))
                 .route("/token/create", post().to(create_token))
                 .route("/token/delete", post().to(delete_token))
+                .route("/auth/login", post().to(login))
+                .route("/auth/login", get().to(login_page))
+                .route("/auth/login/form", post().to(login_form))
+                .route("/auth/logout", post().to(logout))
+                .route("/oauth/clients", post().to(create_oauth_client))
+                .route("/api/v1/apps", post().to(create_mastodon_app))
+                .route("/oauth/authorize", get().to(oauth_authorize))
+                .route("/oauth/authorize", post().to(oauth_confirm))
+                .route("/oauth/token", post().to(oauth_token))
+                .route("/user/totp/enroll", post().to(start_totp_enrollment))
+                .route("/user/totp/confirm", post().to(confirm_totp_enrollment))
+                .route("/user/totp/disable", post().to(remove_totp))
+                .route("/user/password/forgot", post().to(forgot_password))
+                .route("/user/password/reset", post().to(confirm_password_reset))
                 .route("/user/delete", post().to(delete_user))
+                .route("/user/delete/undo/{token}", get().to(undo_delete_user_page))
+                .route("/user/delete/undo/{token}", post().to(undo_delete_user))
+                .route("/user/delete/undo/{token}/form", post().to(undo_delete_user_form))
+                .route("/user/export", get().to(export_user_data))
+                .route("/user/import", post().to(import_user_data))
                 .route("/user/create", post().to(create_user))
+                .route("/email/resend", post().to(resend_verification))
                 .route("/mood/create", post().to(set_mood))
                 .route("/mood/delete", post().to(delete_mood))
                 .route("/user/update/pwd", post().to(change_user_pwd))
                 .route("/user/update/email", post().to(change_user_email))
+                .route("/email/change/confirm/{token}", get().to(confirm_email_page))
+                .route("/email/change/confirm/{token}", post().to(confirm_email))
+                .route("/email/change/confirm/{token}/form", post().to(confirm_email_form))
+                .route("/email/change/revert/{token}", get().to(revert_email_page))
+                .route("/email/change/revert/{token}", post().to(revert_email))
+                .route("/email/change/revert/{token}/form", post().to(revert_email_form))
                 .route("/mood/get", get().to(get_mood))
                 .route("/moods/get", get().to(get_moods))
                 .route("/tokens/get", get().to(get_tokens))
+                .route("/moods/stream", get().to(stream_moods))
+                .route("/moods/socket", get().to(mood_socket))
+                .route("/users/{username}/feed.rss", get().to(user_feed_rss))
+                .route("/users/{username}/feed.atom", get().to(user_feed_atom))
+                .route("/users/{username}/feed.json", get().to(user_feed_json))
+                .route("/users/{username}/badge.svg", get().to(user_badge))
+                .route("/users/{username}/widget", get().to(user_widget))
+                .route("/users/{username}/avatar", get().to(user_avatar))
+                .route("/users/{username}", get().to(user_profile))
                 .service(upload_user_file)
+                .route("/files/{file_id}", get().to(get_file))
                 .service(verify_email)
+                .route("/email/verify/{email_token}", get().to(verify_email_page))
+                .route("/email/verify/{email_token}/form", post().to(verify_email_form))
         }
     ).bind(app_addr){
         Ok(server) => server,
@@ -10,25 +10,20 @@ Licensed under the FSL v1.
 /// database connections.
 use sqlx::Pool;
 
-/// Importing the "hash"
-/// function so strings can
-/// be hashed.
-use bcrypt::hash;
-
-/// Importing the "verify"
-/// function so 
-/// hashed strings can
-/// be verified.
-use bcrypt::verify;
+/// Importing the
+/// "Transaction" structure
+/// to make several writes
+/// at once.
+use sqlx::Transaction;
 
 /// Importing this crate's
 /// error structure.
 use super::err::JadeErr;
 
-/// Importing the default
-/// hashing speed for hashing
-/// strings.
-use bcrypt::DEFAULT_COST;
+/// Importing the enum
+/// describing the kind of
+/// an error.
+use super::err::JadeErrKind;
 
 /// Importing the function
 /// to get the current time
@@ -54,6 +49,11 @@ use super::units::APIToken;
 /// to send an email.
 use super::email::send_email;
 
+/// Importing the structure
+/// describing a change to a
+/// user's mood.
+use super::units::MoodEvent;
+
 /// Importing the "Postgres"
 /// structure from the "sqlx"
 /// crate.
@@ -67,7 +67,7 @@ use crate::MoodActionPayload;
 /// Importing the structure
 /// to conduct operations on
 /// a user's account info.
-use crate::ChangeEntityPayload;
+use crate::ChangeEmailPayload;
 
 /// Importing the structure that
 /// helps store user-uploaded files.
@@ -78,11 +78,6 @@ use super::units::JadeUserFile;
 /// was successful or not.
 use super::units::StatusResponse;
 
-/// Importing the structure
-/// to conduct operations that only
-/// require a user's token.
-use super::units::TokenOnlyPayload;
-
 /// Importing the structure
 /// to conduct the creation of a 
 /// user account.
@@ -112,6 +107,507 @@ use super::units::UsernameOnlyPayload;
 /// API tokens.
 use super::units::UserAPITokensPayload;
 
+/// Importing the function to
+/// notify listeners about a change
+/// to a user's mood.
+use super::events::publish_mood_event;
+
+/// Importing the structure
+/// returned when a new API token
+/// is created.
+use super::units::NewTokenResponse;
+
+/// Importing the function
+/// to split an API token into
+/// its prefix and its secret.
+use super::tokens::split_api_token;
+
+/// Importing the function
+/// to hash the secret of an
+/// API token.
+use super::tokens::hash_token_secret;
+
+/// Importing the function
+/// to generate a new API
+/// token.
+use super::tokens::generate_api_token;
+
+/// Importing the function
+/// to check whether a timestamp
+/// lies in the past.
+use super::time::is_past;
+
+/// Importing the function
+/// to check how long ago a
+/// timestamp was.
+use super::time::is_older_than;
+
+/// Importing the number of
+/// seconds between recorded
+/// uses of an API token.
+use super::tokens::TOKEN_USE_RECORD_SECS;
+
+/// Importing the function
+/// to check that requested
+/// scopes exist.
+use super::scopes::validate_scopes;
+
+/// Importing the structure
+/// containing information on a
+/// browser session.
+use super::units::JadeSession;
+
+/// Importing the structure
+/// for logging in.
+use super::units::LoginPayload;
+
+/// Importing the function
+/// to generate random strings.
+use super::tokens::random_string;
+
+/// Importing the function
+/// to get a timestamp in the
+/// future.
+use super::time::time_from_now;
+
+/// Importing the number of
+/// characters in a session ID.
+use super::sessions::SESSION_ID_LENGTH;
+
+/// Importing the number of
+/// characters in a CSRF token.
+use super::sessions::CSRF_TOKEN_LENGTH;
+
+/// Importing the number of
+/// seconds a session stays
+/// valid for.
+use super::sessions::SESSION_LIFETIME_SECS;
+
+/// Importing the structure
+/// containing information on a
+/// registered OAuth application.
+use super::units::OAuthClient;
+
+/// Importing the structure
+/// containing information on an
+/// OAuth authorization code.
+use super::units::OAuthCode;
+
+/// Importing the structure
+/// containing information on an
+/// OAuth refresh token.
+use super::units::OAuthRefreshToken;
+
+/// Importing the structure
+/// for registering an OAuth
+/// application.
+use super::units::RegisterClientPayload;
+
+/// Importing the structure
+/// returned when an OAuth
+/// application is registered.
+use super::units::NewClientResponse;
+
+/// Importing the structure
+/// containing an OAuth
+/// authorization request.
+use super::units::AuthorizeQuery;
+
+/// Importing the structure
+/// for redeeming authorization
+/// codes and refresh tokens.
+use super::units::OAuthTokenPayload;
+
+/// Importing the structure
+/// returned when tokens are
+/// issued through OAuth.
+use super::units::OAuthTokenResponse;
+
+/// Importing the function
+/// to derive a PKCE code
+/// challenge.
+use super::oauth::pkce_challenge;
+
+/// Importing the function
+/// to check a PKCE code
+/// verifier.
+use super::oauth::validate_code_verifier;
+
+/// Importing the function
+/// to check a redirect URI.
+use super::oauth::validate_redirect_uri;
+
+/// Importing the function
+/// to work out the scopes an
+/// authorization request is for.
+use super::oauth::resolve_requested_scopes;
+
+/// Importing the only PKCE
+/// method Jade accepts.
+use super::oauth::PKCE_METHOD;
+
+/// Importing the function
+/// to work out the type of a
+/// new OAuth application.
+use super::oauth::is_confidential_client;
+
+/// Importing the function
+/// to check the client secret
+/// of an OAuth application.
+use super::oauth::check_client_secret;
+
+/// Importing the number of
+/// characters in the ID of an
+/// OAuth application.
+use super::oauth::CLIENT_ID_LENGTH;
+
+/// Importing the number of
+/// characters in the secret of
+/// an OAuth application.
+use super::oauth::CLIENT_SECRET_LENGTH;
+
+/// Importing the number of
+/// characters in an authorization
+/// code.
+use super::oauth::AUTH_CODE_LENGTH;
+
+/// Importing the number of
+/// characters in a refresh token.
+use super::oauth::REFRESH_TOKEN_LENGTH;
+
+/// Importing the number of
+/// seconds an authorization code
+/// can be redeemed for.
+use super::oauth::AUTH_CODE_LIFETIME_SECS;
+
+/// Importing the number of
+/// seconds an API token issued
+/// through OAuth stays valid for.
+use super::oauth::ACCESS_TOKEN_LIFETIME_SECS;
+
+/// Importing the structure
+/// containing a new TOTP
+/// enrollment.
+use super::units::TotpEnrollment;
+
+/// Importing the structure
+/// for submitting a TOTP code
+/// or a recovery code.
+use super::units::TotpCodePayload;
+
+/// Importing the structure
+/// containing newly-issued
+/// recovery codes.
+use super::units::RecoveryCodesResponse;
+
+/// Importing the function
+/// to find the time step of a
+/// TOTP code.
+use super::totp::current_totp_step;
+
+/// Importing the function
+/// to encrypt TOTP secrets.
+use super::crypto::encrypt_totp_secret;
+
+/// Importing the function
+/// to decrypt TOTP secrets.
+use super::crypto::decrypt_totp_secret;
+
+/// Importing the function
+/// to build the provisioning
+/// URI of a TOTP secret.
+use super::totp::provisioning_uri;
+
+/// Importing the function
+/// to generate a TOTP secret.
+use super::totp::generate_totp_secret;
+
+/// Importing the function
+/// to generate recovery codes.
+use super::totp::generate_recovery_codes;
+
+/// Importing the structure
+/// containing information on a
+/// password reset link.
+use super::units::PasswordReset;
+
+/// Importing the structure
+/// for requesting a password
+/// reset link.
+use super::units::ForgotPasswordPayload;
+
+/// Importing the structure
+/// for setting a new password
+/// with a reset token.
+use super::units::ResetPasswordPayload;
+
+/// Importing the number of
+/// characters in a password
+/// reset token.
+use super::tokens::RESET_TOKEN_LENGTH;
+
+/// Importing the number of
+/// seconds a password reset
+/// token can be used for.
+use super::tokens::RESET_TOKEN_LIFETIME_SECS;
+
+/// Importing the structure
+/// for changing a user's
+/// password.
+use super::units::ChangePasswordPayload;
+
+/// Importing the function
+/// to hash a password.
+use super::password::hash_password;
+
+/// Importing the function
+/// to check a password against
+/// a stored hash.
+use super::password::verify_password;
+
+/// Importing the function
+/// to check whether a stored
+/// password hash is outdated.
+use super::password::needs_rehash;
+
+/// Importing the structure
+/// containing information to
+/// request a new verification
+/// email.
+use super::units::ResendVerificationPayload;
+
+/// Importing the number of
+/// characters in an email
+/// verification token.
+use super::tokens::EMAIL_TOKEN_LENGTH;
+
+/// Importing the number of
+/// seconds an email verification
+/// token can be used for.
+use super::tokens::EMAIL_TOKEN_LIFETIME_SECS;
+
+/// Importing the number of
+/// seconds between two verification
+/// emails.
+use super::tokens::VERIFICATION_RESEND_COOLDOWN_SECS;
+
+/// Importing the function
+/// to check that a user may
+/// use their account.
+use super::auth::require_active_account;
+
+/// Importing the structure
+/// containing information on a
+/// change of a user's email
+/// address.
+use super::units::EmailChange;
+
+/// Importing the number of
+/// characters in the tokens
+/// confirming or reverting a
+/// change of an email address.
+use super::tokens::EMAIL_CHANGE_TOKEN_LENGTH;
+
+/// Importing the number of
+/// seconds a new email address
+/// can be confirmed for.
+use super::tokens::EMAIL_CHANGE_LIFETIME_SECS;
+
+/// Importing the number of
+/// seconds a change of an email
+/// address can be reverted for.
+use super::tokens::EMAIL_REVERT_LIFETIME_SECS;
+
+/// Importing the structure
+/// containing the keys email
+/// addresses are protected with.
+use super::crypto::EmailKeys;
+
+/// Importing the function
+/// to encrypt email addresses.
+use super::crypto::encrypt_email;
+
+/// Importing the function
+/// to compute the keyed hash
+/// of an email address.
+use super::crypto::email_lookup;
+
+/// Importing the function
+/// to check a supplied email
+/// address against a stored one.
+use super::crypto::email_matches;
+
+/// Importing the function
+/// to check whether a stored
+/// email address is encrypted.
+use super::crypto::is_encrypted_email;
+
+/// Importing the structure
+/// containing a problem with
+/// a single field of a payload.
+use super::units::FieldError;
+
+/// Importing the function
+/// to build the problem with
+/// a field.
+use super::validation::field_error;
+
+/// Importing the function
+/// to check the syntax of a
+/// username.
+use super::validation::validate_username;
+
+/// Importing the function
+/// to check the syntax of an
+/// email address.
+use super::validation::validate_email;
+
+/// Importing the structure
+/// containing information to
+/// delete a user's account.
+use super::units::DeleteAccountPayload;
+
+/// Importing the structure
+/// containing the time a user's
+/// account will be purged at.
+use super::units::DeletionScheduled;
+
+/// Importing the function
+/// to decrypt email addresses.
+use super::crypto::decrypt_email;
+
+/// Importing the number of
+/// characters in the token that
+/// undoes the deletion of an
+/// account.
+use super::tokens::DELETION_UNDO_TOKEN_LENGTH;
+
+/// Importing the number of
+/// characters in the ID of an
+/// uploaded file.
+use super::tokens::FILE_ID_LENGTH;
+
+/// This function attempts to get the active
+/// API token with the supplied value. The token
+/// is looked up by its prefix and its secret is
+/// checked against the stored hash. If this
+/// operation succeeds, an instance of the "APIToken"
+/// structure is returned. If the operation fails,
+/// an error is returned.
+pub async fn get_api_token(
+    api_token: &String,
+    pool: &Pool<Postgres>
+) -> Result<APIToken, JadeErr> {
+    let (prefix, secret): (String, String) = match split_api_token(api_token) {
+        Ok(split) => split,
+        Err(e) => return Err::<APIToken, JadeErr>(e)
+    };
+    let token: Option<APIToken> = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE token_prefix = $1 AND is_active = $2", prefix, true)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(token) => token,
+        Err(e) => return Err::<APIToken, JadeErr>(JadeErr::database(&e))
+    };
+    let token: APIToken = match token {
+        Some(token) if token.token_hash == hash_token_secret(&secret) => token,
+        _ => {
+            let e: String = "No user with the specified API token found.".to_string();
+            return Err::<APIToken, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    if let Some(expires_at) = &token.expires_at {
+        let expired: bool = match is_past(expires_at) {
+            Ok(expired) => expired,
+            Err(e) => return Err::<APIToken, JadeErr>(e)
+        };
+        if expired {
+            let e: String = "The supplied API token has expired.".to_string();
+            return Err::<APIToken, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    }
+    Ok(token)
+}
+
+/// Attempts to load the given API token again
+/// to check that it is still active and has not
+/// expired since it was first checked. If this
+/// operation succeeds, the current state of the
+/// token is returned. If this operation fails,
+/// an error is returned.
+pub async fn refresh_api_token(
+    token: &APIToken,
+    pool: &Pool<Postgres>
+) -> Result<APIToken, JadeErr> {
+    let current: Option<APIToken> = match sqlx::query_as!(
+        APIToken,
+        "SELECT * FROM api_tokens WHERE token_prefix = $1 AND token_hash = $2 AND is_active = $3",
+        token.token_prefix,
+        token.token_hash,
+        true
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(current) => current,
+        Err(e) => return Err::<APIToken, JadeErr>(JadeErr::database(&e))
+    };
+    let current: APIToken = match current {
+        Some(current) => current,
+        None => {
+            let e: String = "The API token has been revoked.".to_string();
+            return Err::<APIToken, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    if let Some(expires_at) = &current.expires_at {
+        let expired: bool = match is_past(expires_at) {
+            Ok(expired) => expired,
+            Err(e) => return Err::<APIToken, JadeErr>(e)
+        };
+        if expired {
+            let e: String = "The supplied API token has expired.".to_string();
+            return Err::<APIToken, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    }
+    Ok(current)
+}
+
+/// Attempts to record when and from which
+/// address an API token was last used. A use
+/// from the same address is only recorded once
+/// the last recorded use is older than
+/// "TOKEN_USE_RECORD_SECS" so that not every
+/// request writes to the database. If this
+/// operation fails, an error is returned.
+pub async fn record_token_use(
+    token: &APIToken,
+    client_ip: &Option<String>,
+    pool: &Pool<Postgres>
+) -> Result<(), JadeErr> {
+    if let Some(last_used_at) = &token.last_used_at {
+        let is_stale: bool = match is_older_than(last_used_at, TOKEN_USE_RECORD_SECS) {
+            Ok(is_stale) => is_stale,
+            Err(e) => return Err::<(), JadeErr>(e)
+        };
+        if !is_stale && &token.last_used_ip == client_ip {
+            return Ok(());
+        }
+    }
+    let _update_op = match sqlx::query!(
+        "UPDATE api_tokens SET last_used_at = $1, last_used_ip = $2 WHERE token_prefix = $3",
+        get_time(),
+        client_ip.clone(),
+        token.token_prefix
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+    };
+    Ok(())
+}
+
 /// This function attempts to get the
 /// user associated with the supplied API
 /// token. If this operation succeeds, an
@@ -122,538 +618,1056 @@ pub async fn get_user_from_token(
     api_token: &String, 
     pool: &Pool<Postgres>
 ) -> Result<JadeUser, JadeErr> {
-    let api_tokens: Vec<APIToken> = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens")
-        .fetch_all(pool)
-        .await
-    {
-        Ok(users) => users,
-        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
+    let token: APIToken = match get_api_token(api_token, pool).await {
+        Ok(token) => token,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
     };
-    let mut username: String = "".to_string();
-    for token in api_tokens {
-        if &token.token == api_token && token.is_active {
-            username = token.username;
-        }
-        else {}
-    }
-    if username == "".to_string(){
-        let e: String = "No user with the specified API token found.".to_string();
-        Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
-    }
-    else {
-        let user: JadeUser = match get_user_by_handle(&username, pool).await {
-            Ok(user) => user,
-            Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        Ok(user)
-    }
+    let user: JadeUser = match get_user_by_handle(&token.username, pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
+    };
+    Ok(user)
 }
 
+/// Attempts to store a file the given user
+/// has uploaded. File IDs only contain letters
+/// and digits, so that they can be used in
+/// paths as they are. If this operation succeeds,
+/// an instance of the "JadeUserFile" structure
+/// is returned. If this operation fails, an
+/// error is returned.
 pub async fn store_file(
     file: &Vec<u8>,
-    api_token: &String, 
+    user: &JadeUser,
     name: &String,
     pool: &Pool<Postgres>
 ) -> Result<JadeUserFile, JadeErr>{
-    let user: JadeUser = match get_user_from_token(api_token,pool).await {
-        Ok(user) => user,
-        Err(e) => return Err::<JadeUserFile, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    let hashed_id: String = match hash(format!("{}:{}", &name, get_time()), DEFAULT_COST){
-        Ok(hashed_id) => hashed_id,
-        Err(e) => return Err::<JadeUserFile, JadeErr>(JadeErr::new(&e.to_string()))
-    };
     let new_file: JadeUserFile = JadeUserFile{
-        file_id: hashed_id,
+        file_id: random_string(FILE_ID_LENGTH),
         file_name: name.to_owned(),
-        username: user.username,
+        username: user.username.clone(),
         data: file.to_owned()
     };
     let _insert_op = match sqlx::query!(
-        "INSERT INTO user_files (file_id, username, file_name, data) VALUES ($1, $2, $3, $4)",
+        "INSERT INTO user_files (file_id, username, file_name, data, uploaded_at) VALUES ($1, $2, $3, $4, $5)",
         new_file.file_id,
         new_file.username,
         new_file.file_name,
         new_file.data,
+        get_time()
     )
         .execute(pool)
         .await
     {
         Ok(_feedback) => {},
-        Err(e) => return Err::<JadeUserFile, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<JadeUserFile, JadeErr>(JadeErr::database(&e))
     };
     Ok(new_file)  
 }
 
+/// Attempts to send the email containing the
+/// link a user has to open to verify their email
+/// address. If this operation fails, an error
+/// is returned.
+pub async fn send_verification_email(
+    username: &String,
+    address: &String,
+    email_token: &String,
+    smtp_server: &String,
+    instance_url: &String
+) -> Result<(), JadeErr> {
+    let email_sub: String = format!("Confirm your email address, {}.", username);
+    let from_addr: String = format!("Jade <noreply@{}>", smtp_server);
+    let to_addr: String = format!("{} <{}>", username, address);
+    let message: String = format!(
+        "Please copy and paste this link into your browser within the next 24 hours to confirm your email address: {}/email/verify/{}",
+        instance_url,
+        email_token
+    );
+    let send_res: bool = match send_email(&from_addr, &to_addr, &email_sub, &message, smtp_server).await {
+        Ok(send_res) => send_res,
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    if send_res {
+        Ok(())
+    }
+    else {
+        let e: String = "Could not send verification email.".to_string();
+        Err::<(), JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
+
 /// This function attempts to verify the email
-/// the user has submitted. If the operation succeeds,
-/// a boolean "true" is returned. If the operation fails,
-/// an error is returned or a boolean "false" is returned.
+/// the user has submitted. Expired tokens are
+/// rejected. Once the email address is verified,
+/// the token cannot be used again. If the operation
+/// succeeds, a boolean "true" is returned. If the
+/// operation fails, an error is returned.
 pub async fn verify_user_email(
     email_token: &String,
     pool: &Pool<Postgres>
 ) -> Result<bool, JadeErr> {
-    let mut result: bool = false;
-    let users: Vec<JadeUser> = match sqlx::query_as!(JadeUser, "SELECT * FROM users")
-        .fetch_all(pool)
+    let user: JadeUser = match sqlx::query_as!(JadeUser, "SELECT * FROM users WHERE email_token = $1", email_token)
+        .fetch_optional(pool)
         .await
     {
-        Ok(users) => users,
-        Err(e) => return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let e: String = "No user with the specified token found.".to_string();
+            return Err::<bool, JadeErr>(JadeErr::not_found(&e.to_string()))
+        },
+        Err(e) => return Err::<bool, JadeErr>(JadeErr::database(&e))
     };
-    let mut user_vec: Vec<JadeUser> = Vec::new();
-    for user in users {
-        if &user.email_token == email_token {
-            result = true;
-            user_vec.push(user);
+    if let Some(expires_at) = &user.email_token_expires_at {
+        let expired: bool = match is_past(expires_at) {
+            Ok(expired) => expired,
+            Err(e) => return Err::<bool, JadeErr>(e)
+        };
+        if expired {
+            let e: String = "This verification link has expired. Please request a new one.".to_string();
+            return Err::<bool, JadeErr>(JadeErr::validation(&e.to_string()))
         }
-        else {}
     }
-    let hashed_time: String = match hash(get_time(), DEFAULT_COST){
-        Ok(hashed_time) => hashed_time,
-        Err(e) => return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    if user_vec.len() == 1 {}
-    else {
-        let e: String = "No user with the specified token found.".to_string();
-        return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
-    }
-    let user: JadeUser = user_vec[0].clone();
-    let _update_op_active: () = match sqlx::query!("UPDATE users SET is_active = $1 WHERE username = $2", true, user.username)
-            .execute(pool)
-            .await
+    match sqlx::query!(
+        "UPDATE users SET is_active = $1, email_token = $2, email_token_expires_at = NULL WHERE username = $3",
+        true,
+        random_string(EMAIL_TOKEN_LENGTH),
+        user.username
+    )
+        .execute(pool)
+        .await
     {
-        Ok(_feedback) => result = true,
-        Err(e) => return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    let _update_token: () = match sqlx::query!("UPDATE users SET email_token = $1 WHERE username = $2", hashed_time, user.username)
-            .execute(pool)
-            .await
+        Ok(_feedback) => Ok(true),
+        Err(e) => Err::<bool, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to give the verification tokens of
+/// users who signed up before verification tokens
+/// expired the usual lifetime, counted from now.
+/// Once they have expired, these users can ask for
+/// a new verification email like everybody else.
+/// If this operation succeeds, the number of tokens
+/// given an expiry is returned. If this operation
+/// fails, an error is returned.
+pub async fn expire_legacy_verification_tokens(
+    pool: &Pool<Postgres>
+) -> Result<u64, JadeErr> {
+    match sqlx::query!(
+        "UPDATE users SET email_token_expires_at = $1 WHERE is_active = $2 AND email_token_expires_at IS NULL",
+        time_from_now(EMAIL_TOKEN_LIFETIME_SECS),
+        false
+    )
+        .execute(pool)
+        .await
     {
-        Ok(_feedback) => result = true,
-        Err(e) => return Err::<bool, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    Ok(result)
+        Ok(feedback) => Ok(feedback.rows_affected()),
+        Err(e) => Err::<u64, JadeErr>(JadeErr::database(&e))
+    }
 }
 
-/// Attempts to create a new user with the given payload.
-/// If this operation succeeds, an instance of the "JadeUser" structure is
-/// returned. If this operation fails, an error is returned.
-pub async fn write_user(
-    payload: &CreateUserPayload,
+/// Attempts to send a new verification email to
+/// a user who has not verified their email address
+/// yet. The email address must match the one stored
+/// for the user. A new email is only sent once the
+/// cooldown since the last one has passed. Sending
+/// a new email makes earlier links unusable. If this
+/// operation succeeds, an instance of the "StatusResponse"
+/// structure is returned with a status code of 0. If
+/// this operation fails, an error is returned.
+pub async fn resend_verification_email(
+    payload: &ResendVerificationPayload,
+    keys: &EmailKeys,
     pool: &Pool<Postgres>,
-    smtp_server: &String
-) -> Result<JadeUser, JadeErr> {
-    let hashed_pwd = match hash(payload.password.clone(), DEFAULT_COST){
-        Ok(hashed) => hashed,
-        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    let hashed_email_token = match hash(&format!("{}{}{}", &payload.username, &payload.email, get_time()), DEFAULT_COST){
-        Ok(hashed) => hashed,
-        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
+    smtp_server: &String,
+    instance_url: &String
+) -> Result<StatusResponse, JadeErr> {
+    let user: JadeUser = match get_user_by_handle(&payload.username, pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
     };
-    let hashed_email = match hash(&payload.email, DEFAULT_COST){
-        Ok(hashed) => hashed,
-        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
+    let email_matches: bool = match check_user_email(&user, &payload.email, keys, pool).await {
+        Ok(email_matches) => email_matches,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
     };
-    let new_user: JadeUser = JadeUser{
-        username: payload.username.clone(),
-        email: hashed_email.clone(),
+    if email_matches {}
+    else {
+        let e: String = format!("The email address does not match the one stored for user \"{}\".", &payload.username);
+        return Err::<StatusResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    if user.is_active {
+        let e: String = format!("The email address of user \"{}\" has already been verified.", &payload.username);
+        return Err::<StatusResponse, JadeErr>(JadeErr::conflict(&e.to_string()))
+    }
+    if let Some(resend_after) = &user.verification_resend_after {
+        let cooled_down: bool = match is_past(resend_after) {
+            Ok(cooled_down) => cooled_down,
+            Err(e) => return Err::<StatusResponse, JadeErr>(e)
+        };
+        if cooled_down {}
+        else {
+            let e: String = format!("A verification email was sent recently. Please wait until {} before asking for another one.", resend_after);
+            return Err::<StatusResponse, JadeErr>(JadeErr::conflict(&e.to_string()))
+        }
+    }
+    let email_token: String = random_string(EMAIL_TOKEN_LENGTH);
+    let _update_op: () = match sqlx::query!(
+        "UPDATE users SET email_token = $1, email_token_expires_at = $2, verification_resend_after = $3 WHERE username = $4",
+        email_token,
+        time_from_now(EMAIL_TOKEN_LIFETIME_SECS),
+        time_from_now(VERIFICATION_RESEND_COOLDOWN_SECS),
+        user.username
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    match send_verification_email(&user.username, &payload.email, &email_token, smtp_server, instance_url).await {
+        Ok(_sent) => Ok(StatusResponse{ status: 0 }),
+        Err(e) => Err::<StatusResponse, JadeErr>(e)
+    }
+}
+
+/// Attempts to create a new user with the given payload.
+/// If this operation succeeds, an instance of the "JadeUser" structure is
+/// returned. If this operation fails, an error is returned.
+pub async fn write_user(
+    payload: &CreateUserPayload,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>,
+    smtp_server: &String,
+    instance_url: &String
+) -> Result<JadeUser, JadeErr> {
+    let hashed_pwd = match hash_password(&payload.password){
+        Ok(hashed) => hashed,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
+    };
+    let _email_check: () = match require_free_email(&payload.email, keys, pool).await {
+        Ok(_email_check) => _email_check,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
+    };
+    let encrypted_email: String = match encrypt_email(keys, &payload.email){
+        Ok(encrypted_email) => encrypted_email,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
+    };
+    let lookup: String = match email_lookup(keys, &payload.email){
+        Ok(lookup) => lookup,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
+    };
+    let new_user: JadeUser = JadeUser{
+        username: payload.username.clone(),
+        email: encrypted_email,
         pwd: hashed_pwd,
-        email_token: hashed_email_token.clone(),
-        is_active: false
+        email_token: random_string(EMAIL_TOKEN_LENGTH),
+        is_active: false,
+        totp_secret: None,
+        totp_enabled: false,
+        email_token_expires_at: Some(time_from_now(EMAIL_TOKEN_LIFETIME_SECS)),
+        verification_resend_after: Some(time_from_now(VERIFICATION_RESEND_COOLDOWN_SECS)),
+        email_lookup: Some(lookup),
+        purge_at: None,
+        deletion_undo_hash: None,
+        totp_last_step: None
     };
     let _insert_op = match sqlx::query!(
-        "INSERT INTO users (username, email, pwd, email_token, is_active) VALUES ($1, $2, $3, $4, $5)",
+        "INSERT INTO users (username, email, pwd, email_token, is_active, email_token_expires_at, verification_resend_after, email_lookup) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
         new_user.username,
         new_user.email,
         new_user.pwd,
         new_user.email_token,
-        new_user.is_active
+        new_user.is_active,
+        new_user.email_token_expires_at,
+        new_user.verification_resend_after,
+        new_user.email_lookup
     )
         .execute(pool)
         .await
     {
         Ok(_feedback) => {},
-        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::database(&e))
     };
-    let email_sub: String = format!("Confirm your email address, {}.", &payload.username);
-    let from_addr: String = format!("Jade <noreply@{}>", smtp_server);
-    let to_addr: String = format!("{} <{}>", &payload.username, &payload.email);
-    let message: String = format!("Please copy and paste this link into your browser to confirm your email address: {}/email/verify/{}",smtp_server, hashed_email_token.clone());
-    let send_res: bool = match send_email(&from_addr, &to_addr, &email_sub, &message, smtp_server).await {
-        Ok(send_res) => send_res,
-        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
+    let _send_op: () = match send_verification_email(&payload.username, &payload.email, &new_user.email_token, smtp_server, instance_url).await {
+        Ok(_send_op) => _send_op,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
     };
-    if send_res{
-        let res: JadeUser = match get_user_by_handle(&payload.username, pool).await {
-            Ok(res) => res,
-            Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        Ok(res)
-    }
-    else {
-        let e: String = "Could not send verification email.".to_string();
-        Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
+    match get_user_by_handle(&payload.username, pool).await {
+        Ok(res) => Ok(res),
+        Err(e) => Err::<JadeUser, JadeErr>(e)
     }
-    
-
 }
 
 /// Attempts to fetch the user with the given handle from the database.
-/// If this operation succeeds, an instance of the "JadeUser" structure is
-/// returned. If this operation fails, an error is returned. This function
-/// is NOT utilized in any API routes.
+/// This function is used by the public profile, feed, and embed routes,
+/// and by every route that looks a user up by name. If this operation
+/// succeeds, an instance of the "JadeUser" structure is returned. If this
+/// operation fails, an error is returned.
 pub async fn get_user_by_handle(
     username: &String,
     pool: &Pool<Postgres>
 ) -> Result<JadeUser, JadeErr> {
-    let users: Vec<JadeUser> = match sqlx::query_as!(JadeUser, "SELECT * FROM users")
+    let user: Option<JadeUser> = match sqlx::query_as!(JadeUser, "SELECT * FROM users WHERE LOWER(username) = LOWER($1)", username)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(user) => user,
+        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::database(&e))
+    };
+    match user {
+        Some(user) => Ok(user),
+        None => {
+            let e: String = format!("User \"{}\" does not exist.", &username);
+            Err::<JadeUser, JadeErr>(JadeErr::not_found(&e.to_string()))
+        }
+    }
+}
+
+/// Attempts to schedule the deletion of a user's
+/// account. The user has to enter their password
+/// again. All of the user's API tokens, refresh
+/// tokens and browser sessions are revoked and
+/// the account is purged once the grace period
+/// has passed. A link to undo the deletion is sent
+/// to the user's email address. The deletion stays
+/// scheduled if the email cannot be sent, this is
+/// reported as a warning. Users whose address
+/// was stored as a hash by an earlier version of Jade
+/// have to supply it, so that the link can be sent.
+/// If this operation succeeds, an instance of the
+/// "DeletionScheduled" structure is returned. If
+/// this operation fails, an error is returned.
+pub async fn schedule_user_deletion(
+    payload: &DeleteAccountPayload,
+    user: &JadeUser,
+    keys: &EmailKeys,
+    grace_secs: i64,
+    pool: &Pool<Postgres>,
+    smtp_server: &String,
+    instance_url: &String
+) -> Result<DeletionScheduled, JadeErr> {
+    let is_valid: bool = match check_user_password(user, &payload.password, pool).await {
+        Ok(is_valid) => is_valid,
+        Err(e) => return Err::<DeletionScheduled, JadeErr>(e)
+    };
+    if is_valid {}
+    else {
+        let e: String = format!("Passwords did not match for user \"{}\"!", &user.username);
+        return Err::<DeletionScheduled, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+    let address: String = if is_encrypted_email(&user.email) {
+        match decrypt_email(keys, &user.email) {
+            Ok(address) => address,
+            Err(e) => return Err::<DeletionScheduled, JadeErr>(e)
+        }
+    }
+    else {
+        let supplied: &String = match &payload.email {
+            Some(supplied) => supplied,
+            None => {
+                let e: String = "The email address of this account was stored by an earlier version of Jade and cannot be read. Please supply it in the \"email\" key, so that a link to undo the deletion can be sent to it.".to_string();
+                return Err::<DeletionScheduled, JadeErr>(JadeErr::validation(&e.to_string()))
+            }
+        };
+        let email_matches: bool = match check_user_email(user, supplied, keys, pool).await {
+            Ok(email_matches) => email_matches,
+            Err(e) => return Err::<DeletionScheduled, JadeErr>(e)
+        };
+        if email_matches {}
+        else {
+            let e: String = format!("The email address does not match the one stored for user \"{}\".", &user.username);
+            return Err::<DeletionScheduled, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+        supplied.trim().to_string()
+    };
+    let undo_token: String = random_string(DELETION_UNDO_TOKEN_LENGTH);
+    let purge_at: String = time_from_now(grace_secs);
+    let _update_op: () = match sqlx::query!(
+        "UPDATE users SET purge_at = $1, deletion_undo_hash = $2 WHERE username = $3",
+        purge_at,
+        hash_token_secret(&undo_token),
+        user.username
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<DeletionScheduled, JadeErr>(JadeErr::database(&e))
+    };
+    let _revoke_op: () = match revoke_user_credentials(&user.username, pool).await {
+        Ok(_revoke_op) => _revoke_op,
+        Err(e) => return Err::<DeletionScheduled, JadeErr>(e)
+    };
+    let email_sub: String = format!("Your Jade account will be deleted, {}.", &user.username);
+    let from_addr: String = format!("Jade <noreply@{}>", smtp_server);
+    let to_addr: String = format!("{} <{}>", &user.username, &address);
+    let message: String = format!(
+        "Your Jade account and all of its data will be deleted for good on {}. If you change your mind or this was not you, open this link before then to keep your account: {}/user/delete/undo/{}",
+        &purge_at,
+        instance_url,
+        undo_token
+    );
+    match send_email(&from_addr, &to_addr, &email_sub, &message, smtp_server).await {
+        Ok(_sent) => Ok(DeletionScheduled { purge_at: purge_at, warning: None }),
+        Err(e) => {
+            log::warn!("Could not send the link to undo the deletion of \"{}\": {}", &user.username, &e.to_string());
+            let warning: String = "Your account will be deleted, but the email with the link to undo the deletion could not be sent.".to_string();
+            Ok(DeletionScheduled { purge_at: purge_at, warning: Some(warning) })
+        }
+    }
+}
+
+/// Attempts to undo the scheduled deletion of
+/// a user's account with the token from the email
+/// sent to the user. If this operation succeeds, an
+/// instance of the "StatusResponse" structure is
+/// returned with a status code of 0. If this
+/// operation fails, an error is returned.
+pub async fn undo_user_deletion(
+    token: &String,
+    pool: &Pool<Postgres>
+) -> Result<StatusResponse, JadeErr> {
+    let user: JadeUser = match sqlx::query_as!(
+        JadeUser,
+        "SELECT * FROM users WHERE deletion_undo_hash = $1",
+        hash_token_secret(token)
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            let e: String = "This undo link is not valid.".to_string();
+            return Err::<StatusResponse, JadeErr>(JadeErr::not_found(&e.to_string()))
+        },
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    match sqlx::query!(
+        "UPDATE users SET purge_at = NULL, deletion_undo_hash = NULL WHERE username = $1",
+        user.username
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => Ok(StatusResponse{ status: 0 }),
+        Err(e) => Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to purge every account whose grace
+/// period has passed. Uploaded files are deleted
+/// first, and moods, tokens, sessions and all other
+/// data belonging to the user are deleted with the
+/// user. If this operation succeeds, the number of
+/// purged accounts is returned. If this operation
+/// fails, an error is returned.
+pub async fn purge_deleted_users(
+    pool: &Pool<Postgres>
+) -> Result<usize, JadeErr> {
+    let users: Vec<JadeUser> = match sqlx::query_as!(JadeUser, "SELECT * FROM users WHERE purge_at IS NOT NULL")
         .fetch_all(pool)
         .await
     {
         Ok(users) => users,
-        Err(e) => return Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<usize, JadeErr>(JadeErr::database(&e))
     };
-    let mut result: Vec<JadeUser> = Vec::new();
+    let mut purged: usize = 0;
     for user in users {
-        if &user.username == username {
-            result.push(user);
+        let due: bool = match &user.purge_at {
+            Some(purge_at) => match is_past(purge_at) {
+                Ok(due) => due,
+                Err(e) => return Err::<usize, JadeErr>(e)
+            },
+            None => false
+        };
+        if due {}
+        else {
+            continue;
         }
-        else {}
-    }
-    if result.len() == 1{
-        Ok(result[0].clone())
-    }
-    else{
-        let e: String = format!("User \"{}\" does not exist.", &username);
-        Err::<JadeUser, JadeErr>(JadeErr::new(&e.to_string()))
+        let _files_op: () = match sqlx::query!("DELETE FROM user_files WHERE username = $1", user.username)
+            .execute(pool)
+            .await
+        {
+            Ok(_feedback) => {},
+            Err(e) => return Err::<usize, JadeErr>(JadeErr::database(&e))
+        };
+        let _wipe_op: StatusResponse = match wipe_user(&user, pool).await {
+            Ok(_wipe_op) => _wipe_op,
+            Err(e) => return Err::<usize, JadeErr>(e)
+        };
+        purged += 1;
     }
+    Ok(purged)
 }
 
-/// Attempts to delete a user given one of their API tokens.
+/// Attempts to delete the given user.
 /// If this operation succeeds,  an instance of 
 /// the "StatusResponse" structure is returned 
 /// with a status code of 0. If this operation fails, 
 /// an error is returned or an instance of the "StatusResponse"
 /// structure with the status code of 1.
 pub async fn wipe_user(
-    payload: &TokenOnlyPayload,
+    user: &JadeUser,
     pool: &Pool<Postgres>
 ) -> Result<StatusResponse, JadeErr> {
-    let token: APIToken = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE token = $1", payload.api_token)
-        .fetch_one(pool)
-        .await
-    {
-        Ok(token) => token,
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    let _wipe_op: () = match sqlx::query!("DELETE FROM users WHERE username = $1", token.username)
+    let _wipe_op: () = match sqlx::query!("DELETE FROM users WHERE username = $1", user.username)
         .execute(pool)
         .await
     {
         Ok(_feedback) => {},
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
     };
     let status: StatusResponse = StatusResponse{ status: 0 };
     Ok(status)
 }
 
-/// Attempts to create a new mood for a user with the given
-/// payload. If this operation succeeds, an instance of 
+/// Attempts to create a new mood for the given user with
+/// the given payload. The user's previous mood is kept
+/// as an inactive mood. If this operation succeeds, an
+/// instance of 
 /// the "JadeMood" structure. If this operation fails, an 
 /// error is returned.
 pub async fn create_new_mood(
     payload: &MoodActionPayload,
+    user: &JadeUser,
     pool: &Pool<Postgres>,
 ) -> Result<JadeMood, JadeErr> {
-    let token: APIToken = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE token = $1", payload.api_token)
-        .fetch_one(pool)
+    let mut tx: Transaction<'_, Postgres> = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::database(&e))
+    };
+    let _update_op: () = match sqlx::query!("UPDATE moods SET is_active = $1 WHERE username = $2", false, user.username)
+        .execute(&mut *tx)
         .await
     {
-        Ok(token) => token,
-        Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::new(&e.to_string()))
+        Ok(_feedback) => {},
+        Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::database(&e))
     };
-    let username: String = token.username;
-    let all_moods: Vec<JadeMood> = match sqlx::query_as!(JadeMood, "SELECT * FROM moods")
-        .fetch_all(pool)
+    let new_mood: JadeMood = JadeMood {
+        mood: payload.mood.clone(),
+        is_active: true,
+        username: user.username.clone(),
+        created_at: get_time()
+    };
+    let _insert_op = match sqlx::query!(
+        "INSERT INTO moods (username, is_active, mood, created_at) VALUES ($1, $2, $3, $4)",
+        new_mood.username,
+        new_mood.is_active,
+        new_mood.mood,
+        new_mood.created_at,
+    )
+        .execute(&mut *tx)
         .await
     {
-        Ok(all_moods) => all_moods,
-        Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::new(&e.to_string()))
+        Ok(_feedback) => {},
+        Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::database(&e))
     };
-    for mood in all_moods{
-        let _update_op: () = match sqlx::query!("UPDATE moods SET is_active = $1 WHERE username = $2", false, mood.username)
-            .execute(pool)
-            .await
-        {
-            Ok(_feedback) => {},
-            Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-    }
-    if token.can_set_mood{
-        let new_mood: JadeMood = JadeMood {
-            mood: payload.mood.clone(),
-            is_active: true,
-            username: username,
-            created_at: get_time()
-        };
-        let _insert_op = match sqlx::query!(
-            "INSERT INTO moods (username, is_active, mood, created_at) VALUES ($1, $2, $3, $4)",
-            new_mood.username,
-            new_mood.is_active,
-            new_mood.mood,
-            new_mood.created_at,
-        )
-            .execute(pool)
-            .await
-        {
-            Ok(_feedback) => {},
-            Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        Ok(new_mood)
-    }
-    else {
-        let e: String = format!("User \"{}\" does not have the correct permissions.", &username);
-        Err::<JadeMood, JadeErr>(JadeErr::new(&e.to_string()))
+    let event: MoodEvent = MoodEvent {
+        username: new_mood.username.clone(),
+        event: "set".to_string(),
+        mood: Some(new_mood.clone())
+    };
+    let _publish_op: () = match publish_mood_event(&event, &mut tx).await {
+        Ok(_publish_op) => _publish_op,
+        Err(e) => return Err::<JadeMood, JadeErr>(e)
+    };
+    match tx.commit().await {
+        Ok(_feedback) => Ok(new_mood),
+        Err(e) => Err::<JadeMood, JadeErr>(JadeErr::database(&e))
     }
 }
 
-/// Attempts to delete a mood for a user given 
-/// one of their API tokens. If this operation 
+/// Attempts to clear the current mood of the
+/// given user. Earlier moods are kept, so that
+/// they stay in the user's history. If this operation 
 /// succeeds, an instance of  the "StatusResponse" 
 /// structure is returned with a status code of 0. 
 /// If this operation fails, an error is returned 
 /// or an instance of the "StatusResponse" structure 
 /// with the status code of 1.
 pub async fn wipe_mood(
-    payload: &MoodActionPayload,
+    user: &JadeUser,
     pool: &Pool<Postgres>
 ) -> Result<StatusResponse, JadeErr> {
-    let token: APIToken = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE token = $1", payload.api_token)
-        .fetch_one(pool)
+    let mut tx: Transaction<'_, Postgres> = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let _wipe_op: () = match sqlx::query!("UPDATE moods SET is_active = $1 WHERE username = $2 AND is_active", false, user.username)
+        .execute(&mut *tx)
         .await
     {
-        Ok(token) => token,
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let event: MoodEvent = MoodEvent {
+        username: user.username.clone(),
+        event: "delete".to_string(),
+        mood: None
+    };
+    let _publish_op: () = match publish_mood_event(&event, &mut tx).await {
+        Ok(_publish_op) => _publish_op,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    match tx.commit().await {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let status: StatusResponse = StatusResponse{ status: 0 };
+    Ok(status)
+}
+
+/// Attempts to store a new API token for the given
+/// user with the given scopes, expiry date and, for
+/// tokens issued through OAuth, the ID of the application.
+/// If this operation succeeds, an instance of the
+/// "NewTokenResponse" structure is returned. If this
+/// operation fails, an error is returned.
+pub async fn insert_api_token(
+    username: &String,
+    scopes: &Vec<String>,
+    expires_at: &Option<String>,
+    client_id: &Option<String>,
+    pool: &Pool<Postgres>
+) -> Result<NewTokenResponse, JadeErr> {
+    let (prefix, secret): (String, String) = generate_api_token();
+    let new_token: APIToken = APIToken{
+        username: username.clone(),
+        token_prefix: prefix.clone(),
+        token_hash: hash_token_secret(&secret),
+        created_at: get_time(),
+        is_active: true,
+        scopes: scopes.clone(),
+        expires_at: expires_at.clone(),
+        last_used_at: None,
+        last_used_ip: None,
+        revoked_at: None,
+        client_id: client_id.clone()
     };
-    let _wipe_op: () = match sqlx::query!("DELETE FROM moods WHERE username = $1", token.username)
+    let _insert_op = match sqlx::query!(
+        "INSERT INTO api_tokens (username, token_prefix, token_hash, created_at, is_active, scopes, expires_at, client_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        new_token.username,
+        new_token.token_prefix,
+        new_token.token_hash,
+        new_token.created_at,
+        new_token.is_active,
+        &new_token.scopes,
+        new_token.expires_at,
+        new_token.client_id
+    )
         .execute(pool)
         .await
     {
         Ok(_feedback) => {},
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<NewTokenResponse, JadeErr>(JadeErr::database(&e))
     };
-    let status: StatusResponse = StatusResponse{ status: 0 };
-    Ok(status)
+    Ok(NewTokenResponse{ api_token: format!("{}.{}", prefix, secret), details: new_token })
 }
 
 /// Attempts to create a new API token for a user with
 /// the given payload. If this operation succeeds, 
-/// an instance of  the "JadeMood" structure. If this 
-/// operation fails, an  error is returned.
+/// an instance of the "NewTokenResponse" structure
+/// is returned. This is the only time the full token
+/// is available, as only a hash of its secret is stored.
+/// If this operation fails, an  error is returned.
 pub async fn create_new_token(
     payload: &CreateTokenPayload,
+    keys: &EmailKeys,
     pool: &Pool<Postgres>
-) -> Result<APIToken, JadeErr> {
+) -> Result<NewTokenResponse, JadeErr> {
     let user: JadeUser = match get_user_by_handle(&payload.username, pool).await {
         Ok(user) => user,
-        Err(e) => return Err::<APIToken, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<NewTokenResponse, JadeErr>(e)
     };
-    let is_valid: bool = match verify(&user.pwd,&payload.password){
+    let is_valid: bool = match check_user_password(&user, &payload.password, pool).await {
         Ok(is_valid) => is_valid,
-        Err(e) => return Err::<APIToken, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<NewTokenResponse, JadeErr>(e)
     };
-    if is_valid {
-        let hashed: String = match hash(format!("{}:{}", get_time(), &payload.username), DEFAULT_COST){
-            Ok(hashed) => hashed,
-            Err(e) => return Err::<APIToken, JadeErr>(JadeErr::new(&e.to_string()))
+    let _validate_op: () = match validate_scopes(&payload.scopes) {
+        Ok(_validate_op) => _validate_op,
+        Err(e) => return Err::<NewTokenResponse, JadeErr>(e)
+    };
+    if let Some(expires_at) = &payload.expires_at {
+        let expired: bool = match is_past(expires_at) {
+            Ok(expired) => expired,
+            Err(e) => return Err::<NewTokenResponse, JadeErr>(e)
         };
-        let new_token: APIToken = APIToken{
-            username: payload.username.clone(),
-            token: hashed,
-            created_at: get_time(),
-            is_active: true,
-            can_change_pwd: payload.can_change_pwd,
-            can_set_mood: payload.can_set_mood,
-            can_delete_user: payload.can_delete_user,
-            can_change_email: payload.can_change_email.clone(),
+        if expired {
+            let e: String = "The expiry date of a new API token must lie in the future.".to_string();
+            return Err::<NewTokenResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    }
+    if is_valid {
+        let _verified_check: () = match require_active_account(&user) {
+            Ok(_verified_check) => _verified_check,
+            Err(e) => return Err::<NewTokenResponse, JadeErr>(e)
         };
-        let _insert_op = match sqlx::query!(
-            "INSERT INTO api_tokens (username, token, created_at, is_active, can_change_pwd, can_set_mood, can_delete_user, can_change_email) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
-            new_token.username,
-            new_token.token,
-            new_token.created_at,
-            new_token.is_active,
-            new_token.can_change_pwd,
-            new_token.can_set_mood,
-            new_token.can_delete_user,
-            new_token.can_change_email
-        )
-            .execute(pool)
-            .await
-        {
-            Ok(_feedback) => {},
-            Err(e) => return Err::<APIToken, JadeErr>(JadeErr::new(&e.to_string()))
+        let _second_factor: () = match verify_second_factor(&user, &payload.totp_code, keys, pool).await {
+            Ok(_second_factor) => _second_factor,
+            Err(e) => return Err::<NewTokenResponse, JadeErr>(e)
         };
-        Ok(new_token)
-
+        insert_api_token(&user.username, &payload.scopes, &payload.expires_at, &None, pool).await
     }
     else {
         let e: String = format!("Passwords did not match for user \"{}\"!", &payload.username);
-        Err::<APIToken, JadeErr>(JadeErr::new(&e.to_string()))
+        Err::<NewTokenResponse, JadeErr>(JadeErr::unauthorized(&e.to_string()))
     }
 }
 
-/// Attempts to delete an API token of a user.
+/// Attempts to revoke an API token of a user.
+/// The token is picked by its prefix, so that
+/// the token itself is never sent in a request
+/// body. The token is kept so that it shows up
+/// in the user's token history.
 /// If this operation succeeds,  an instance of 
 /// the "StatusResponse" structure is returned 
-/// with a status code of 0. If this operation fails, 
-/// an error is returned or an instance of the "StatusResponse"
-/// structure with the status code of 1.
+/// with a status code of 0. If the user has no
+/// active token with the given prefix, the status
+/// code is 1. If this operation fails, an error
+/// is returned.
 pub async fn wipe_token(
     payload: &DeleteTokenPayload,
     pool: &Pool<Postgres>
 ) -> Result<StatusResponse, JadeErr> {
-    let token: APIToken = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE token = $1", payload.api_token)
-        .fetch_one(pool)
-        .await
-    {
-        Ok(token) => token,
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    let user: JadeUser = match get_user_by_handle(&token.username, pool).await {
+    let user: JadeUser = match get_user_by_handle(&payload.username, pool).await {
         Ok(user) => user,
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
     };
-    if user.pwd == payload.password{
-        let _wipe_op: () = match sqlx::query!("DELETE FROM users WHERE username = $1", token.username)
-            .execute(pool)
-            .await
-        {
-            Ok(_feedback) => {},
-            Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        let status: StatusResponse = StatusResponse{ status: 0 };
-        Ok(status)
-    }
+    let is_valid: bool = match check_user_password(&user, &payload.password, pool).await {
+        Ok(is_valid) => is_valid,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    if is_valid {}
     else {
         let e: String = format!("Passwords did not match for user \"{}\"!", &payload.username);
-        Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        return Err::<StatusResponse, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+    let revoked: u64 = match sqlx::query!(
+        "UPDATE api_tokens SET is_active = $1, revoked_at = $2 WHERE token_prefix = $3 AND username = $4 AND is_active",
+        false,
+        get_time(),
+        payload.token_prefix,
+        user.username
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(feedback) => feedback.rows_affected(),
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let status: StatusResponse = if revoked > 0 {
+        StatusResponse{ status: 0 }
     }
+    else {
+        StatusResponse{ status: 1 }
+    };
+    Ok(status)
 }
 
 /// Attempts to update the password for a user.
-/// If this operation succeeds,  an instance of 
+/// The user's current password must be supplied
+/// and the new password is stored as an Argon2id
+/// hash. If this operation succeeds,  an instance of 
 /// the "StatusResponse" structure is returned 
 /// with a status code of 0. If this operation fails, 
 /// an error is returned or an instance of the "StatusResponse"
 /// structure with the status code of 1.
 pub async fn update_user_password(
-    payload: &ChangeEntityPayload,
+    payload: &ChangePasswordPayload,
+    user: &JadeUser,
     pool: &Pool<Postgres>
 ) -> Result<StatusResponse, JadeErr>{
-    let token: APIToken = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE token = $1", payload.api_token)
-        .fetch_one(pool)
-        .await
-    {
-        Ok(token) => token,
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-    };
-    let user: JadeUser = match get_user_by_handle(&token.username, pool).await {
-        Ok(user) => user,
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+    let is_valid: bool = match check_user_password(user, &payload.current_password, pool).await {
+        Ok(is_valid) => is_valid,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
     };
-    if token.is_active && 
-       token.can_change_pwd && 
-       token.username == user.username
-    {
-        let _update_op: () = match sqlx::query!("UPDATE users SET pwd = $1 WHERE username = $2", payload.new_entity, user.username)
-            .execute(pool)
-            .await
-        {
-            Ok(_feedback) => {},
-            Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        let status: StatusResponse = StatusResponse{ status: 0 };
-        Ok(status)
-    }
+    if is_valid {}
     else {
-        let e: String = "Token not active or usernames did not match!".to_string();
-        Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        let e: String = format!("Passwords did not match for user \"{}\"!", &user.username);
+        return Err::<StatusResponse, JadeErr>(JadeErr::unauthorized(&e.to_string()))
     }
+    let hashed_pwd: String = match hash_password(&payload.new_password){
+        Ok(hashed_pwd) => hashed_pwd,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let _update_op: () = match sqlx::query!("UPDATE users SET pwd = $1 WHERE username = $2", hashed_pwd, user.username)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let status: StatusResponse = StatusResponse{ status: 0 };
+    Ok(status)
 }
 
-/// Attempts to update the mail address for a user.
-/// If this operation succeeds,  an instance of 
-/// the "StatusResponse" structure is returned 
-/// with a status code of 0. If this operation fails, 
-/// an error is returned or an instance of the "StatusResponse"
-/// structure with the status code of 1.
+
+/// Attempts to start changing the email address
+/// of a user. The current address must match the
+/// one stored for the user. The new address is kept
+/// pending and a link to confirm it is sent to it. The
+/// current address is told about the change and gets a
+/// link to revert it. A new request replaces an earlier
+/// one that has not been confirmed yet. Once a change
+/// has been confirmed, no new change can be made while
+/// the link to revert it still works, so that a new
+/// change cannot replace that link. If this operation
+/// succeeds, an instance of the "StatusResponse" structure
+/// is returned with a status code of 0. If this operation
+/// fails, an error is returned.
 pub async fn update_user_email(
-    payload: &ChangeEntityPayload,
+    payload: &ChangeEmailPayload,
+    user: &JadeUser,
+    keys: &EmailKeys,
     pool: &Pool<Postgres>,
-    smtp_server: &String
+    smtp_server: &String,
+    instance_url: &String
 ) -> Result<StatusResponse, JadeErr>{
-    let token: APIToken = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE token = $1", payload.api_token)
-        .fetch_one(pool)
+    let email_matches: bool = match check_user_email(user, &payload.current_email, keys, pool).await {
+        Ok(email_matches) => email_matches,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    if email_matches {}
+    else {
+        let e: String = format!("The current email address does not match the one stored for user \"{}\".", &user.username);
+        return Err::<StatusResponse, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+    let _email_check: () = match require_free_email(&payload.new_email, keys, pool).await {
+        Ok(_email_check) => _email_check,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let old_email: String = match encrypt_email(keys, &payload.current_email){
+        Ok(old_email) => old_email,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let old_lookup: String = match email_lookup(keys, &payload.current_email){
+        Ok(old_lookup) => old_lookup,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let new_email: String = match encrypt_email(keys, &payload.new_email){
+        Ok(new_email) => new_email,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let new_lookup: String = match email_lookup(keys, &payload.new_email){
+        Ok(new_lookup) => new_lookup,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let confirm_token: String = random_string(EMAIL_CHANGE_TOKEN_LENGTH);
+    let revert_token: String = random_string(EMAIL_CHANGE_TOKEN_LENGTH);
+    let change: EmailChange = EmailChange {
+        username: user.username.clone(),
+        old_email: old_email,
+        new_email: new_email,
+        confirm_token_hash: hash_token_secret(&confirm_token),
+        revert_token_hash: hash_token_secret(&revert_token),
+        created_at: get_time(),
+        expires_at: time_from_now(EMAIL_CHANGE_LIFETIME_SECS),
+        confirmed_at: None,
+        revert_expires_at: time_from_now(EMAIL_REVERT_LIFETIME_SECS),
+        old_email_lookup: Some(old_lookup),
+        new_email_lookup: Some(new_lookup)
+    };
+    let written: u64 = match sqlx::query!(
+        "INSERT INTO email_changes (username, old_email, new_email, confirm_token_hash, revert_token_hash, created_at, expires_at, revert_expires_at, old_email_lookup, new_email_lookup) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) ON CONFLICT (username) DO UPDATE SET old_email = EXCLUDED.old_email, new_email = EXCLUDED.new_email, confirm_token_hash = EXCLUDED.confirm_token_hash, revert_token_hash = EXCLUDED.revert_token_hash, created_at = EXCLUDED.created_at, expires_at = EXCLUDED.expires_at, confirmed_at = NULL, revert_expires_at = EXCLUDED.revert_expires_at, old_email_lookup = EXCLUDED.old_email_lookup, new_email_lookup = EXCLUDED.new_email_lookup WHERE email_changes.confirmed_at IS NULL OR email_changes.revert_expires_at <= EXCLUDED.created_at",
+        change.username,
+        change.old_email,
+        change.new_email,
+        change.confirm_token_hash,
+        change.revert_token_hash,
+        change.created_at,
+        change.expires_at,
+        change.revert_expires_at,
+        change.old_email_lookup,
+        change.new_email_lookup
+    )
+        .execute(pool)
         .await
     {
-        Ok(token) => token,
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Ok(feedback) => feedback.rows_affected(),
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
     };
-    let user: JadeUser = match get_user_by_handle(&token.username, pool).await {
-        Ok(user) => user,
-        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+    if written == 0 {
+        let e: String = "The email address of this account was changed recently. Another change can be made once the link to revert the last change has expired.".to_string();
+        return Err::<StatusResponse, JadeErr>(JadeErr::conflict(&e.to_string()))
+    }
+    let from_addr: String = format!("Jade <noreply@{}>", smtp_server);
+    let confirm_sub: String = format!("Confirm your new email address, {}.", &user.username);
+    let confirm_to: String = format!("{} <{}>", &user.username, &payload.new_email);
+    let confirm_msg: String = format!(
+        "Please open this link within the next 24 hours to make this the email address of your Jade account: {}/email/change/confirm/{}",
+        instance_url,
+        confirm_token
+    );
+    let _confirm_send: bool = match send_email(&from_addr, &confirm_to, &confirm_sub, &confirm_msg, smtp_server).await {
+        Ok(sent) => sent,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
     };
-    if token.is_active && 
-       token.can_change_pwd && 
-       token.username == user.username 
-    {
-        let hashed_email: String = match hash(&payload.new_entity, DEFAULT_COST){
-            Ok(hashed_email) => hashed_email,
-            Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        let _update_op: () = match sqlx::query!("UPDATE users SET email = $1 WHERE username = $2", hashed_email, user.username)
-            .execute(pool)
-            .await
-        {
+    let notice_sub: String = format!("The email address of your Jade account is being changed, {}.", &user.username);
+    let notice_to: String = format!("{} <{}>", &user.username, &payload.current_email);
+    let notice_msg: String = format!(
+        "Somebody asked to change the email address of your Jade account to {}. If this was you, you do not need to do anything. If this was not you, open this link within the next seven days to keep this address and sign out everywhere: {}/email/change/revert/{}",
+        &payload.new_email,
+        instance_url,
+        revert_token
+    );
+    match send_email(&from_addr, &notice_to, &notice_sub, &notice_msg, smtp_server).await {
+        Ok(_sent) => Ok(StatusResponse{ status: 0 }),
+        Err(e) => Err::<StatusResponse, JadeErr>(e)
+    }
+}
 
-            Ok(_feedback) => {},
-            Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        let hashed_email_token = match hash(&format!("{}{}{}", &user.username, &payload.new_entity, get_time()), DEFAULT_COST){
-            Ok(hashed) => hashed,
-            Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        let _update_token_op: () = match sqlx::query!("UPDATE users SET email_token = $1 WHERE username = $2", hashed_email_token, user.username)
-            .execute(pool)
-            .await
-        {
+/// Attempts to confirm a pending change of a
+/// user's email address with the token sent to
+/// the new address. Only then is the stored address
+/// replaced, provided no other user has taken the new
+/// address in the meantime. The address is replaced and
+/// the change is marked as confirmed together. If this
+/// operation succeeds, an instance of the "StatusResponse"
+/// structure is returned with a status code of 0. If this
+/// operation fails, an error is returned.
+pub async fn confirm_email_change(
+    token: &String,
+    pool: &Pool<Postgres>
+) -> Result<StatusResponse, JadeErr> {
+    let mut tx: Transaction<'_, Postgres> = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let change: EmailChange = match sqlx::query_as!(
+        EmailChange,
+        "SELECT * FROM email_changes WHERE confirm_token_hash = $1 AND confirmed_at IS NULL FOR UPDATE",
+        hash_token_secret(token)
+    )
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(Some(change)) => change,
+        Ok(None) => {
+            let e: String = "This confirmation link is not valid.".to_string();
+            return Err::<StatusResponse, JadeErr>(JadeErr::not_found(&e.to_string()))
+        },
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let expired: bool = match is_past(&change.expires_at) {
+        Ok(expired) => expired,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    if expired {
+        let e: String = "This confirmation link has expired. Please ask to change your email address again.".to_string();
+        return Err::<StatusResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    let taken: bool = match sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE email_lookup = $1 AND username <> $2)",
+        change.new_email_lookup,
+        change.username
+    )
+        .fetch_one(&mut *tx)
+        .await
+    {
+        Ok(taken) => taken.unwrap_or(false),
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    if taken {
+        let e: String = "This email address is already in use.".to_string();
+        return Err::<StatusResponse, JadeErr>(JadeErr::conflict(&e.to_string()))
+    }
+    let _update_op: () = match sqlx::query!(
+        "UPDATE users SET email = $1, email_lookup = $2 WHERE username = $3",
+        change.new_email,
+        change.new_email_lookup,
+        change.username
+    )
+        .execute(&mut *tx)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let _confirm_op: () = match sqlx::query!(
+        "UPDATE email_changes SET confirmed_at = $1 WHERE username = $2",
+        get_time(),
+        change.username
+    )
+        .execute(&mut *tx)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    match tx.commit().await {
+        Ok(_feedback) => Ok(StatusResponse{ status: 0 }),
+        Err(e) => Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    }
+}
 
-            Ok(_feedback) => {},
-            Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        let email_sub: String = format!("Confirm your new email address, {}.", &user.username);
-        let from_addr: String = format!("Jade <noreply@{}>", smtp_server);
-        let to_addr: String = format!("{} <{}>", &user.username, &payload.new_entity);
-        let message: String = format!("Please copy and paste this link into your browser to confirm your email address: {}/email/verify/{}",smtp_server, hashed_email_token.clone());
-        let send_res: bool = match send_email(&from_addr, &to_addr, &email_sub, &message, smtp_server).await {
-            Ok(send_res) => send_res,
-            Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-        };
-        if send_res{
-            let status: StatusResponse = StatusResponse{ status: 0};
-            Ok(status)
-        }
-        else {
-            let e: String = "Could not send verification email.".to_string();
-            Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
-        }
+/// Attempts to revert a change of a user's email
+/// address with the token sent to the old address.
+/// A change that has not been confirmed yet is
+/// dropped and a confirmed change is undone. The
+/// old address is restored and the change is dropped
+/// together. As the change was not made by the user,
+/// all of their API tokens, refresh tokens and browser
+/// sessions are revoked. If this operation succeeds,
+/// an instance of the "StatusResponse" structure is
+/// returned with a status code of 0. If this operation
+/// fails, an error is returned.
+pub async fn revert_email_change(
+    token: &String,
+    pool: &Pool<Postgres>
+) -> Result<StatusResponse, JadeErr> {
+    let mut tx: Transaction<'_, Postgres> = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let change: EmailChange = match sqlx::query_as!(
+        EmailChange,
+        "SELECT * FROM email_changes WHERE revert_token_hash = $1 FOR UPDATE",
+        hash_token_secret(token)
+    )
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(Some(change)) => change,
+        Ok(None) => {
+            let e: String = "This revert link is not valid.".to_string();
+            return Err::<StatusResponse, JadeErr>(JadeErr::not_found(&e.to_string()))
+        },
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let expired: bool = match is_past(&change.revert_expires_at) {
+        Ok(expired) => expired,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    if expired {
+        let e: String = "This revert link has expired.".to_string();
+        return Err::<StatusResponse, JadeErr>(JadeErr::validation(&e.to_string()))
     }
-    else {
-        let e: String = "Token not active or usernames did not match!".to_string();
-        Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+    let _restore_op: () = match sqlx::query!(
+        "UPDATE users SET email = $1, email_lookup = $2 WHERE username = $3",
+        change.old_email,
+        change.old_email_lookup,
+        change.username
+    )
+        .execute(&mut *tx)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let _delete_op: () = match sqlx::query!("DELETE FROM email_changes WHERE username = $1", change.username)
+        .execute(&mut *tx)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    match tx.commit().await {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    match revoke_user_credentials(&change.username, pool).await {
+        Ok(_revoked) => Ok(StatusResponse{ status: 0 }),
+        Err(e) => Err::<StatusResponse, JadeErr>(e)
     }
 }
 
@@ -667,14 +1681,14 @@ pub async fn get_user_mood(
 )-> Result<JadeMood, JadeErr>{
     let user: JadeUser = match get_user_by_handle(&payload.username, pool).await {
         Ok(user) => user,
-        Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<JadeMood, JadeErr>(e)
     };
     let moods: Vec<JadeMood> = match sqlx::query_as!(JadeMood, "SELECT * FROM moods WHERE username = $1", user.username)
         .fetch_all(pool)
         .await
     {
         Ok(moods) => moods,
-        Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<JadeMood, JadeErr>(JadeErr::database(&e))
     };
     let mut result: Vec<JadeMood> = Vec::new();
     for mood in moods {
@@ -688,7 +1702,7 @@ pub async fn get_user_mood(
     }
     else {
         let e: String = format!("The user \"{}\" either does not exist or has not created any moods.", &user.username);
-        Err::<JadeMood, JadeErr>(JadeErr::new(&e.to_string()))
+        Err::<JadeMood, JadeErr>(JadeErr::not_found(&e.to_string()))
     }
 }
 
@@ -702,14 +1716,14 @@ pub async fn get_user_moods(
 ) -> Result<UserMoodsResponse, JadeErr>{
     let user: JadeUser = match get_user_by_handle(&payload.username, pool).await {
         Ok(user) => user,
-        Err(e) => return Err::<UserMoodsResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<UserMoodsResponse, JadeErr>(e)
     };
     let moods: Vec<JadeMood> = match sqlx::query_as!(JadeMood, "SELECT * FROM moods")
         .fetch_all(pool)
         .await
     {
         Ok(moods) => moods,
-        Err(e) => return Err::<UserMoodsResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<UserMoodsResponse, JadeErr>(JadeErr::database(&e))
     };
     let mut result: Vec<JadeMood> = Vec::new();
     for mood in moods {
@@ -723,13 +1737,14 @@ pub async fn get_user_moods(
     }
     let active_mood: JadeMood = match get_user_mood(payload, pool).await {
         Ok(active_mood) => active_mood,
-        Err(e) => return Err::<UserMoodsResponse, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<UserMoodsResponse, JadeErr>(e)
     };
     Ok(UserMoodsResponse{ active_mood: active_mood, inactive_moods: result})
 }
 
-/// Attempts to retrieve all active API tokens for a user.
-/// If this operation is successful, a vector of the
+/// Attempts to retrieve all API tokens for a user,
+/// including expired and revoked ones, newest token
+/// first. If this operation is successful, a vector of the
 /// instances of the "APIToken" structure is returned.
 /// If this operation fails, an error is returned.
 pub async fn get_user_tokens(
@@ -738,30 +1753,1518 @@ pub async fn get_user_tokens(
 ) -> Result<Vec<APIToken>, JadeErr>{
     let user: JadeUser = match get_user_by_handle(&payload.username, pool).await {
         Ok(user) => user,
-        Err(e) => return Err::<Vec<APIToken>, JadeErr>(JadeErr::new(&e.to_string()))
+        Err(e) => return Err::<Vec<APIToken>, JadeErr>(e)
+    };
+    let is_valid: bool = match check_user_password(&user, &payload.password, pool).await {
+        Ok(is_valid) => is_valid,
+        Err(e) => return Err::<Vec<APIToken>, JadeErr>(e)
     };
-    if user.pwd == payload.password{
-        let tokens: Vec<APIToken>  = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens")
+    if is_valid {
+        let tokens: Vec<APIToken>  = match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE username = $1 ORDER BY created_at DESC", user.username)
             .fetch_all(pool)
             .await
         {
             Ok(tokens) => tokens,
-            Err(e) => return Err::<Vec<APIToken>, JadeErr>(JadeErr::new(&e.to_string()))
+            Err(e) => return Err::<Vec<APIToken>, JadeErr>(JadeErr::database(&e))
         };
-        let mut result: Vec<APIToken> = Vec::new();
-        for token in tokens {
-            if token.username == user.username {
-                if token.is_active {}
-                else {
-                    result.push(token);
-                }
-            }
-            else {}
-        }
-        Ok(result)
+        Ok(tokens)
     }
     else {
         let e: String = format!("Passwords do not match for user \"{}\"!", &user.username);
-        Err::<Vec<APIToken>, JadeErr>(JadeErr::new(&e.to_string()))
+        Err::<Vec<APIToken>, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+}
+
+/// Attempts to retrieve a user whose moods are
+/// publicly visible. Only users who have verified
+/// their email address have public moods. If this
+/// operation fails, an error is returned.
+pub async fn get_public_user(
+    username: &String,
+    pool: &Pool<Postgres>
+) -> Result<JadeUser, JadeErr>{
+    let user: JadeUser = match get_user_by_handle(username, pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
+    };
+    if user.is_active {
+        Ok(user)
+    }
+    else {
+        let e: String = format!("The moods of user \"{}\" are not public.", &user.username);
+        Err::<JadeUser, JadeErr>(JadeErr::forbidden(&e.to_string()))
+    }
+}
+
+/// Attempts to retrieve at most the given number of
+/// moods from the publicly-visible mood history of a
+/// user, newest mood first. If this operation fails,
+/// an error is returned.
+pub async fn get_public_mood_history(
+    username: &String,
+    limit: i64,
+    pool: &Pool<Postgres>
+) -> Result<Vec<JadeMood>, JadeErr>{
+    let user: JadeUser = match get_public_user(username, pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<Vec<JadeMood>, JadeErr>(e)
+    };
+    let moods: Vec<JadeMood> = match sqlx::query_as!(JadeMood, "SELECT * FROM moods WHERE username = $1 ORDER BY created_at DESC LIMIT $2", user.username, limit)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(moods) => moods,
+        Err(e) => return Err::<Vec<JadeMood>, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(moods)
+}
+
+/// Attempts to retrieve the publicly-visible current
+/// mood of a user. If the user has not set a mood, nothing
+/// is returned. If this operation fails, an error is returned.
+pub async fn get_public_mood(
+    username: &String,
+    pool: &Pool<Postgres>
+) -> Result<Option<JadeMood>, JadeErr>{
+    let user: JadeUser = match get_public_user(username, pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<Option<JadeMood>, JadeErr>(e)
+    };
+    match sqlx::query_as!(JadeMood, "SELECT * FROM moods WHERE username = $1 AND is_active LIMIT 1", user.username)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(mood) => Ok(mood),
+        Err(e) => Err::<Option<JadeMood>, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to retrieve the file a user has uploaded
+/// as their avatar. Users can hold several files, the
+/// one uploaded last is their avatar. If the user has
+/// not uploaded a file, nothing is returned. If this operation fails, an error
+/// is returned.
+pub async fn get_user_avatar(
+    username: &String,
+    pool: &Pool<Postgres>
+) -> Result<Option<JadeUserFile>, JadeErr>{
+    let avatar: Option<JadeUserFile> = match sqlx::query_as!(
+        JadeUserFile,
+        "SELECT file_id, username, file_name, data AS \"data!\" FROM user_files WHERE username = $1 AND data IS NOT NULL ORDER BY uploaded_at DESC LIMIT 1",
+        username
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(avatar) => avatar,
+        Err(e) => return Err::<Option<JadeUserFile>, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(avatar)
+}
+
+/// Attempts to log a user in with the given
+/// payload and to start a new browser session
+/// for them. If this operation succeeds, the
+/// session ID for the session cookie and an
+/// instance of the "JadeSession" structure are
+/// returned. If this operation fails, an error
+/// is returned.
+pub async fn login_user(
+    payload: &LoginPayload,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<(String, JadeSession), JadeErr> {
+    let user: JadeUser = match get_user_by_handle(&payload.username, pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<(String, JadeSession), JadeErr>(e)
+    };
+    let is_valid: bool = match check_user_password(&user, &payload.password, pool).await {
+        Ok(is_valid) => is_valid,
+        Err(e) => return Err::<(String, JadeSession), JadeErr>(e)
+    };
+    if is_valid {}
+    else {
+        let e: String = "Wrong username or password.".to_string();
+        return Err::<(String, JadeSession), JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+    let _verified_check: () = match require_active_account(&user) {
+        Ok(_verified_check) => _verified_check,
+        Err(e) => return Err::<(String, JadeSession), JadeErr>(e)
+    };
+    let _second_factor: () = match verify_second_factor(&user, &payload.totp_code, keys, pool).await {
+        Ok(_second_factor) => _second_factor,
+        Err(e) => return Err::<(String, JadeSession), JadeErr>(e)
+    };
+    let session_id: String = random_string(SESSION_ID_LENGTH);
+    let session: JadeSession = JadeSession {
+        session_hash: hash_token_secret(&session_id),
+        username: user.username,
+        csrf_token: random_string(CSRF_TOKEN_LENGTH),
+        created_at: get_time(),
+        expires_at: time_from_now(SESSION_LIFETIME_SECS)
+    };
+    let _insert_op = match sqlx::query!(
+        "INSERT INTO sessions (session_hash, username, csrf_token, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)",
+        session.session_hash,
+        session.username,
+        session.csrf_token,
+        session.created_at,
+        session.expires_at
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<(String, JadeSession), JadeErr>(JadeErr::database(&e))
+    };
+    Ok((session_id, session))
+}
+
+/// Attempts to get the browser session with
+/// the given session ID. Expired sessions are
+/// rejected. If this operation succeeds, an
+/// instance of the "JadeSession" structure is
+/// returned. If this operation fails, an error
+/// is returned.
+pub async fn get_session(
+    session_id: &String,
+    pool: &Pool<Postgres>
+) -> Result<JadeSession, JadeErr> {
+    let session: Option<JadeSession> = match sqlx::query_as!(
+        JadeSession,
+        "SELECT * FROM sessions WHERE session_hash = $1",
+        hash_token_secret(session_id)
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(session) => session,
+        Err(e) => return Err::<JadeSession, JadeErr>(JadeErr::database(&e))
+    };
+    let session: JadeSession = match session {
+        Some(session) => session,
+        None => {
+            let e: String = "No session with the specified ID found.".to_string();
+            return Err::<JadeSession, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    let expired: bool = match is_past(&session.expires_at) {
+        Ok(expired) => expired,
+        Err(e) => return Err::<JadeSession, JadeErr>(e)
+    };
+    if expired {
+        let e: String = "The session has expired. Please log in again.".to_string();
+        return Err::<JadeSession, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+    Ok(session)
+}
+
+/// Attempts to load the given browser session
+/// again to check that it has neither ended nor
+/// expired since it was first checked. If this
+/// operation fails, an error is returned.
+pub async fn refresh_session(
+    session: &JadeSession,
+    pool: &Pool<Postgres>
+) -> Result<JadeSession, JadeErr> {
+    let current: Option<JadeSession> = match sqlx::query_as!(
+        JadeSession,
+        "SELECT * FROM sessions WHERE session_hash = $1",
+        session.session_hash
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(current) => current,
+        Err(e) => return Err::<JadeSession, JadeErr>(JadeErr::database(&e))
+    };
+    let current: JadeSession = match current {
+        Some(current) => current,
+        None => {
+            let e: String = "The session has ended. Please log in again.".to_string();
+            return Err::<JadeSession, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    let expired: bool = match is_past(&current.expires_at) {
+        Ok(expired) => expired,
+        Err(e) => return Err::<JadeSession, JadeErr>(e)
+    };
+    if expired {
+        let e: String = "The session has expired. Please log in again.".to_string();
+        return Err::<JadeSession, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+    Ok(current)
+}
+
+/// Attempts to end the given browser session.
+/// If this operation succeeds, an instance of the
+/// "StatusResponse" structure is returned with a
+/// status code of 0. If this operation fails, an
+/// error is returned.
+pub async fn wipe_session(
+    session: &JadeSession,
+    pool: &Pool<Postgres>
+) -> Result<StatusResponse, JadeErr> {
+    let _wipe_op: () = match sqlx::query!("DELETE FROM sessions WHERE session_hash = $1", session.session_hash)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let status: StatusResponse = StatusResponse{ status: 0 };
+    Ok(status)
+}
+
+/// Attempts to register a new OAuth application
+/// with the given payload. If this operation
+/// succeeds, an instance of the "NewClientResponse"
+/// structure is returned. Confidential applications
+/// get a client secret. This is the only time the
+/// client secret is available, as only its hash is
+/// stored. If this operation fails, an error is
+/// returned.
+pub async fn register_client(
+    payload: &RegisterClientPayload,
+    pool: &Pool<Postgres>
+) -> Result<NewClientResponse, JadeErr> {
+    let _validate_op: () = match validate_scopes(&payload.scopes) {
+        Ok(_validate_op) => _validate_op,
+        Err(e) => return Err::<NewClientResponse, JadeErr>(e)
+    };
+    if payload.redirect_uris.is_empty() {
+        let e: String = "At least one redirect URI must be supplied.".to_string();
+        return Err::<NewClientResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    for redirect_uri in &payload.redirect_uris {
+        let _validate_op: () = match validate_redirect_uri(redirect_uri) {
+            Ok(_validate_op) => _validate_op,
+            Err(e) => return Err::<NewClientResponse, JadeErr>(e)
+        };
+    }
+    let is_confidential: bool = match is_confidential_client(&payload.client_type) {
+        Ok(is_confidential) => is_confidential,
+        Err(e) => return Err::<NewClientResponse, JadeErr>(e)
+    };
+    let client_secret: Option<String> = if is_confidential {
+        Some(random_string(CLIENT_SECRET_LENGTH))
+    }
+    else {
+        None
+    };
+    let client: OAuthClient = OAuthClient {
+        client_id: random_string(CLIENT_ID_LENGTH),
+        client_secret_hash: client_secret.as_ref().map(|secret| hash_token_secret(secret)),
+        client_name: payload.client_name.clone(),
+        redirect_uris: payload.redirect_uris.clone(),
+        scopes: payload.scopes.clone(),
+        website: payload.website.clone(),
+        created_at: get_time(),
+        is_confidential: is_confidential
+    };
+    let _insert_op = match sqlx::query!(
+        "INSERT INTO oauth_clients (client_id, client_secret_hash, client_name, redirect_uris, scopes, website, created_at, is_confidential) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        client.client_id,
+        client.client_secret_hash,
+        client.client_name,
+        &client.redirect_uris,
+        &client.scopes,
+        client.website,
+        client.created_at,
+        client.is_confidential
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<NewClientResponse, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(NewClientResponse { client_secret: client_secret, details: client })
+}
+
+/// Attempts to fetch the OAuth application with
+/// the given client ID. If this operation succeeds,
+/// an instance of the "OAuthClient" structure is
+/// returned. If this operation fails, an error is
+/// returned.
+pub async fn get_oauth_client(
+    client_id: &String,
+    pool: &Pool<Postgres>
+) -> Result<OAuthClient, JadeErr> {
+    let client: Option<OAuthClient> = match sqlx::query_as!(OAuthClient, "SELECT * FROM oauth_clients WHERE client_id = $1", client_id)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(client) => client,
+        Err(e) => return Err::<OAuthClient, JadeErr>(JadeErr::database(&e))
+    };
+    match client {
+        Some(client) => Ok(client),
+        None => {
+            let e: String = format!("No application with the client ID \"{}\" found.", client_id);
+            Err::<OAuthClient, JadeErr>(JadeErr::not_found(&e.to_string()))
+        }
+    }
+}
+
+/// Attempts to check an OAuth authorization
+/// request. The application must exist, the
+/// redirect URI must have been registered, PKCE
+/// with the "S256" method must be used, and only
+/// registered scopes may be requested. If this
+/// operation succeeds, the application and the
+/// scopes requested are returned. If this operation
+/// fails, an error is returned.
+pub async fn check_authorization_request(
+    query: &AuthorizeQuery,
+    pool: &Pool<Postgres>
+) -> Result<(OAuthClient, Vec<String>), JadeErr> {
+    if query.response_type != "code" {
+        let e: String = "Only the \"code\" response type is supported.".to_string();
+        return Err::<(OAuthClient, Vec<String>), JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    if query.code_challenge_method != PKCE_METHOD || query.code_challenge.is_empty() {
+        let e: String = format!("A PKCE code challenge with the \"{}\" method is required.", PKCE_METHOD);
+        return Err::<(OAuthClient, Vec<String>), JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    let client: OAuthClient = match get_oauth_client(&query.client_id, pool).await {
+        Ok(client) => client,
+        Err(e) => return Err::<(OAuthClient, Vec<String>), JadeErr>(e)
+    };
+    if client.redirect_uris.contains(&query.redirect_uri) {}
+    else {
+        let e: String = format!("The redirect URI \"{}\" was not registered for this application.", &query.redirect_uri);
+        return Err::<(OAuthClient, Vec<String>), JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    let scopes: Vec<String> = match resolve_requested_scopes(&query.scope, &client) {
+        Ok(scopes) => scopes,
+        Err(e) => return Err::<(OAuthClient, Vec<String>), JadeErr>(e)
+    };
+    Ok((client, scopes))
+}
+
+/// Attempts to create an authorization code
+/// for the given user after they have approved
+/// an authorization request. If this operation
+/// succeeds, the code is returned. Only a hash of
+/// the code is stored. If this operation fails,
+/// an error is returned.
+pub async fn create_authorization_code(
+    query: &AuthorizeQuery,
+    scopes: &Vec<String>,
+    user: &JadeUser,
+    pool: &Pool<Postgres>
+) -> Result<String, JadeErr> {
+    let code: String = random_string(AUTH_CODE_LENGTH);
+    let auth_code: OAuthCode = OAuthCode {
+        code_hash: hash_token_secret(&code),
+        client_id: query.client_id.clone(),
+        username: user.username.clone(),
+        redirect_uri: query.redirect_uri.clone(),
+        scopes: scopes.clone(),
+        code_challenge: query.code_challenge.clone(),
+        expires_at: time_from_now(AUTH_CODE_LIFETIME_SECS)
+    };
+    let _insert_op = match sqlx::query!(
+        "INSERT INTO oauth_codes (code_hash, client_id, username, redirect_uri, scopes, code_challenge, expires_at) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        auth_code.code_hash,
+        auth_code.client_id,
+        auth_code.username,
+        auth_code.redirect_uri,
+        &auth_code.scopes,
+        auth_code.code_challenge,
+        auth_code.expires_at
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<String, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(code)
+}
+
+/// Attempts to fetch the OAuth application a
+/// token request was made by. Confidential
+/// applications must send their client secret.
+/// Public applications rely on PKCE, which every
+/// authorization request must use, and on refresh
+/// tokens being bound to them.
+/// If this operation fails, an error is returned.
+pub async fn authenticate_client(
+    payload: &OAuthTokenPayload,
+    pool: &Pool<Postgres>
+) -> Result<OAuthClient, JadeErr> {
+    let client: OAuthClient = match get_oauth_client(&payload.client_id, pool).await {
+        Ok(client) => client,
+        Err(e) => return Err::<OAuthClient, JadeErr>(e)
+    };
+    match check_client_secret(&client, &payload.client_secret) {
+        Ok(_f) => Ok(client),
+        Err(e) => Err::<OAuthClient, JadeErr>(e)
+    }
+}
+
+/// Attempts to issue a new API token and a
+/// new refresh token to an OAuth application
+/// on behalf of the given user. If this operation
+/// succeeds, an instance of the "OAuthTokenResponse"
+/// structure is returned. If this operation fails,
+/// an error is returned.
+pub async fn issue_oauth_tokens(
+    client: &OAuthClient,
+    username: &String,
+    scopes: &Vec<String>,
+    pool: &Pool<Postgres>
+) -> Result<OAuthTokenResponse, JadeErr> {
+    let access_token: NewTokenResponse = match insert_api_token(
+        username,
+        scopes,
+        &Some(time_from_now(ACCESS_TOKEN_LIFETIME_SECS)),
+        &Some(client.client_id.clone()),
+        pool
+    ).await {
+        Ok(access_token) => access_token,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(e)
+    };
+    let refresh_token: String = random_string(REFRESH_TOKEN_LENGTH);
+    let stored: OAuthRefreshToken = OAuthRefreshToken {
+        token_hash: hash_token_secret(&refresh_token),
+        client_id: client.client_id.clone(),
+        username: username.clone(),
+        token_prefix: access_token.details.token_prefix.clone(),
+        scopes: scopes.clone(),
+        created_at: get_time(),
+        is_active: true
+    };
+    let _insert_op = match sqlx::query!(
+        "INSERT INTO oauth_refresh_tokens (token_hash, client_id, username, token_prefix, scopes, created_at, is_active) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        stored.token_hash,
+        stored.client_id,
+        stored.username,
+        stored.token_prefix,
+        &stored.scopes,
+        stored.created_at,
+        stored.is_active
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(
+        OAuthTokenResponse {
+            access_token: access_token.api_token,
+            token_type: "Bearer".to_string(),
+            expires_in: ACCESS_TOKEN_LIFETIME_SECS,
+            refresh_token: refresh_token,
+            scope: scopes.join(" ")
+        }
+    )
+}
+
+/// Attempts to exchange an authorization code
+/// for an API token and a refresh token. The code
+/// can only be redeemed once, by the application it
+/// was issued to, with the same redirect URI, and
+/// with the PKCE code verifier matching the code
+/// challenge. The code is only used up once all of
+/// this has been checked, so that a request from
+/// another application cannot burn it. If this
+/// operation fails, an error is returned.
+pub async fn redeem_authorization_code(
+    payload: &OAuthTokenPayload,
+    pool: &Pool<Postgres>
+) -> Result<OAuthTokenResponse, JadeErr> {
+    let client: OAuthClient = match authenticate_client(payload, pool).await {
+        Ok(client) => client,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(e)
+    };
+    let (code, redirect_uri, code_verifier): (&String, &String, &String) = match (&payload.code, &payload.redirect_uri, &payload.code_verifier) {
+        (Some(code), Some(redirect_uri), Some(code_verifier)) => (code, redirect_uri, code_verifier),
+        _ => {
+            let e: String = "The \"code\", \"redirect_uri\", and \"code_verifier\" fields are required.".to_string();
+            return Err::<OAuthTokenResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    let _verifier_check: () = match validate_code_verifier(code_verifier) {
+        Ok(_verifier_check) => _verifier_check,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(e)
+    };
+    let mut tx: Transaction<'_, Postgres> = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let auth_code: Option<OAuthCode> = match sqlx::query_as!(
+        OAuthCode,
+        "SELECT * FROM oauth_codes WHERE code_hash = $1 FOR UPDATE",
+        hash_token_secret(code)
+    )
+        .fetch_optional(&mut *tx)
+        .await
+    {
+        Ok(auth_code) => auth_code,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let auth_code: OAuthCode = match auth_code {
+        Some(auth_code) if auth_code.client_id == client.client_id && &auth_code.redirect_uri == redirect_uri => auth_code,
+        _ => {
+            let e: String = "The authorization code is invalid.".to_string();
+            return Err::<OAuthTokenResponse, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    let expired: bool = match is_past(&auth_code.expires_at) {
+        Ok(expired) => expired,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(e)
+    };
+    if expired {
+        let e: String = "The authorization code has expired.".to_string();
+        return Err::<OAuthTokenResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    if pkce_challenge(code_verifier) != auth_code.code_challenge {
+        let e: String = "The PKCE code verifier does not match the code challenge.".to_string();
+        return Err::<OAuthTokenResponse, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+    let _delete_op: () = match sqlx::query!(
+        "DELETE FROM oauth_codes WHERE code_hash = $1",
+        auth_code.code_hash
+    )
+        .execute(&mut *tx)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let _commit_op: () = match tx.commit().await {
+        Ok(_commit_op) => _commit_op,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(JadeErr::database(&e))
+    };
+    issue_oauth_tokens(&client, &auth_code.username, &auth_code.scopes, pool).await
+}
+
+/// Attempts to exchange a refresh token for a
+/// new API token and a new refresh token. The old
+/// refresh token and the API token issued with it
+/// are revoked. If this operation fails, an error
+/// is returned.
+pub async fn refresh_oauth_token(
+    payload: &OAuthTokenPayload,
+    pool: &Pool<Postgres>
+) -> Result<OAuthTokenResponse, JadeErr> {
+    let client: OAuthClient = match authenticate_client(payload, pool).await {
+        Ok(client) => client,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(e)
+    };
+    let refresh_token: &String = match &payload.refresh_token {
+        Some(refresh_token) => refresh_token,
+        None => {
+            let e: String = "The \"refresh_token\" field is required.".to_string();
+            return Err::<OAuthTokenResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    let stored: Option<OAuthRefreshToken> = match sqlx::query_as!(
+        OAuthRefreshToken,
+        "UPDATE oauth_refresh_tokens SET is_active = $1 WHERE token_hash = $2 AND client_id = $3 AND is_active = $4 RETURNING *",
+        false,
+        hash_token_secret(refresh_token),
+        client.client_id,
+        true
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(stored) => stored,
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let stored: OAuthRefreshToken = match stored {
+        Some(stored) => stored,
+        None => {
+            let e: String = "The refresh token is invalid.".to_string();
+            return Err::<OAuthTokenResponse, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    let _revoke_op: () = match sqlx::query!(
+        "UPDATE api_tokens SET is_active = $1, revoked_at = $2 WHERE token_prefix = $3 AND is_active = $4",
+        false,
+        get_time(),
+        stored.token_prefix,
+        true
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<OAuthTokenResponse, JadeErr>(JadeErr::database(&e))
+    };
+    issue_oauth_tokens(&client, &stored.username, &stored.scopes, pool).await
+}
+
+/// Attempts to use up one of a user's recovery
+/// codes. If the code is valid and has not been
+/// used before, "true" is returned. If this
+/// operation fails, an error is returned.
+pub async fn use_recovery_code(
+    user: &JadeUser,
+    code: &String,
+    pool: &Pool<Postgres>
+) -> Result<bool, JadeErr> {
+    let used: Option<String> = match sqlx::query_scalar!(
+        "UPDATE recovery_codes SET used_at = $1 WHERE code_hash = $2 AND username = $3 AND used_at IS NULL RETURNING code_hash",
+        get_time(),
+        hash_token_secret(&code.trim().to_lowercase()),
+        user.username
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(used) => used,
+        Err(e) => return Err::<bool, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(used.is_some())
+}
+
+/// Attempts to record that a TOTP code from
+/// the given time step was used by a user. Each
+/// step can only be used once and only steps after
+/// the last used one are accepted, so that a code
+/// cannot be replayed. If the step was already used,
+/// "false" is returned. If this operation fails, an
+/// error is returned.
+pub async fn claim_totp_step(
+    user: &JadeUser,
+    step: u64,
+    pool: &Pool<Postgres>
+) -> Result<bool, JadeErr> {
+    let claimed: Option<String> = match sqlx::query_scalar!(
+        "UPDATE users SET totp_last_step = $1 WHERE username = $2 AND (totp_last_step IS NULL OR totp_last_step < $1) RETURNING username",
+        step as i64,
+        user.username
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(claimed) => claimed,
+        Err(e) => return Err::<bool, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(claimed.is_some())
+}
+
+/// Attempts to check a TOTP code against the
+/// encrypted secret of a user. Codes from a time
+/// step that was already used are rejected. If
+/// this operation fails, an error is returned.
+pub async fn check_user_totp(
+    user: &JadeUser,
+    stored_secret: &String,
+    code: &String,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<bool, JadeErr> {
+    let secret: String = match decrypt_totp_secret(keys, stored_secret) {
+        Ok(secret) => secret,
+        Err(e) => return Err::<bool, JadeErr>(e)
+    };
+    let step: u64 = match current_totp_step(&secret, &user.username, code) {
+        Ok(Some(step)) => step,
+        Ok(None) => return Ok(false),
+        Err(e) => return Err::<bool, JadeErr>(e)
+    };
+    let claimed: bool = match claim_totp_step(user, step, pool).await {
+        Ok(claimed) => claimed,
+        Err(e) => return Err::<bool, JadeErr>(e)
+    };
+    if claimed {
+        Ok(true)
+    }
+    else {
+        let e: String = "This two-factor authentication code has already been used.".to_string();
+        Err::<bool, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+}
+
+/// Attempts to check the second factor of a
+/// password-based login. Users without two-factor
+/// authentication pass straight through. All other
+/// users must supply a current TOTP code that has
+/// not been used before or an unused recovery code. If the check fails, an
+/// error is returned.
+pub async fn verify_second_factor(
+    user: &JadeUser,
+    code: &Option<String>,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<(), JadeErr> {
+    if user.totp_enabled {}
+    else {
+        return Ok(());
+    }
+    let (secret, code): (&String, &String) = match (&user.totp_secret, code) {
+        (Some(secret), Some(code)) => (secret, code),
+        _ => {
+            let e: String = "A two-factor authentication code is required.".to_string();
+            return Err::<(), JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    let totp_valid: bool = match check_user_totp(user, secret, code, keys, pool).await {
+        Ok(totp_valid) => totp_valid,
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    if totp_valid {
+        return Ok(());
+    }
+    let recovery_valid: bool = match use_recovery_code(user, code, pool).await {
+        Ok(recovery_valid) => recovery_valid,
+        Err(e) => return Err::<(), JadeErr>(e)
+    };
+    if recovery_valid {
+        Ok(())
+    }
+    else {
+        let e: String = "The two-factor authentication code is invalid.".to_string();
+        Err::<(), JadeErr>(JadeErr::unauthorized(&e.to_string()))
+    }
+}
+
+/// Attempts to start setting up two-factor
+/// authentication for a user. A new secret is
+/// stored encrypted, but codes are only required once the
+/// user has confirmed the enrollment with a valid
+/// code. If this operation succeeds, an instance
+/// of the "TotpEnrollment" structure is returned.
+/// If this operation fails, an error is returned.
+pub async fn enroll_totp(
+    user: &JadeUser,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<TotpEnrollment, JadeErr> {
+    if user.totp_enabled {
+        let e: String = "Two-factor authentication is already enabled.".to_string();
+        return Err::<TotpEnrollment, JadeErr>(JadeErr::conflict(&e.to_string()))
+    }
+    let secret: String = generate_totp_secret();
+    let otpauth_uri: String = match provisioning_uri(&secret, &user.username) {
+        Ok(otpauth_uri) => otpauth_uri,
+        Err(e) => return Err::<TotpEnrollment, JadeErr>(e)
+    };
+    let encrypted_secret: String = match encrypt_totp_secret(keys, &secret) {
+        Ok(encrypted_secret) => encrypted_secret,
+        Err(e) => return Err::<TotpEnrollment, JadeErr>(e)
+    };
+    let _update_op: () = match sqlx::query!(
+        "UPDATE users SET totp_secret = $1, totp_last_step = NULL WHERE username = $2",
+        encrypted_secret,
+        user.username
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<TotpEnrollment, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(TotpEnrollment { secret: secret, otpauth_uri: otpauth_uri })
+}
+
+/// Attempts to enable two-factor authentication
+/// for a user after they have supplied a valid code
+/// for the secret from their enrollment. Any earlier
+/// recovery codes are replaced. If this operation
+/// succeeds, an instance of the "RecoveryCodesResponse"
+/// structure is returned. This is the only time the
+/// recovery codes are available, as only their hashes
+/// are stored. If this operation fails, an error is
+/// returned.
+pub async fn confirm_totp(
+    user: &JadeUser,
+    payload: &TotpCodePayload,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<RecoveryCodesResponse, JadeErr> {
+    let secret: &String = match &user.totp_secret {
+        Some(secret) if !user.totp_enabled => secret,
+        _ => {
+            let e: String = "There is no pending two-factor authentication enrollment.".to_string();
+            return Err::<RecoveryCodesResponse, JadeErr>(JadeErr::conflict(&e.to_string()))
+        }
+    };
+    let is_valid: bool = match check_user_totp(user, secret, &payload.code, keys, pool).await {
+        Ok(is_valid) => is_valid,
+        Err(e) => return Err::<RecoveryCodesResponse, JadeErr>(e)
+    };
+    if is_valid {}
+    else {
+        let e: String = "The two-factor authentication code is invalid.".to_string();
+        return Err::<RecoveryCodesResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    let _wipe_op: () = match sqlx::query!("DELETE FROM recovery_codes WHERE username = $1", user.username)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<RecoveryCodesResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let recovery_codes: Vec<String> = generate_recovery_codes();
+    for code in &recovery_codes {
+        let _insert_op = match sqlx::query!(
+            "INSERT INTO recovery_codes (code_hash, username, created_at) VALUES ($1, $2, $3)",
+            hash_token_secret(code),
+            user.username,
+            get_time()
+        )
+            .execute(pool)
+            .await
+        {
+            Ok(_feedback) => {},
+            Err(e) => return Err::<RecoveryCodesResponse, JadeErr>(JadeErr::database(&e))
+        };
+    }
+    let _update_op: () = match sqlx::query!("UPDATE users SET totp_enabled = $1 WHERE username = $2", true, user.username)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<RecoveryCodesResponse, JadeErr>(JadeErr::database(&e))
+    };
+    Ok(RecoveryCodesResponse { recovery_codes: recovery_codes })
+}
+
+/// Attempts to turn off two-factor authentication
+/// for a user. A current TOTP code or an unused
+/// recovery code must be supplied. If this operation
+/// succeeds, an instance of the "StatusResponse"
+/// structure is returned with a status code of 0.
+/// If this operation fails, an error is returned.
+pub async fn disable_totp(
+    user: &JadeUser,
+    payload: &TotpCodePayload,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<StatusResponse, JadeErr> {
+    if user.totp_enabled {}
+    else {
+        let e: String = "Two-factor authentication is not enabled.".to_string();
+        return Err::<StatusResponse, JadeErr>(JadeErr::conflict(&e.to_string()))
+    }
+    let _second_factor: () = match verify_second_factor(user, &Some(payload.code.clone()), keys, pool).await {
+        Ok(_second_factor) => _second_factor,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let _update_op: () = match sqlx::query!(
+        "UPDATE users SET totp_enabled = $1, totp_secret = NULL, totp_last_step = NULL WHERE username = $2",
+        false,
+        user.username
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let _wipe_op: () = match sqlx::query!("DELETE FROM recovery_codes WHERE username = $1", user.username)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let status: StatusResponse = StatusResponse{ status: 0 };
+    Ok(status)
+}
+
+/// Attempts to encrypt the TOTP secrets earlier
+/// versions of Jade stored in plain text. This runs
+/// when the server starts, so that no plain-text
+/// secrets are left afterwards. If this operation
+/// succeeds, the number of encrypted secrets is
+/// returned. If this operation fails, an error is
+/// returned.
+pub async fn encrypt_legacy_totp_secrets(
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<usize, JadeErr> {
+    let legacy: Vec<(String, String)> = match sqlx::query!(
+        "SELECT username, totp_secret AS \"totp_secret!\" FROM users WHERE totp_secret IS NOT NULL AND totp_secret NOT LIKE 'v1:%'"
+    )
+        .fetch_all(pool)
+        .await
+    {
+        Ok(rows) => rows.into_iter().map(|row| (row.username, row.totp_secret)).collect(),
+        Err(e) => return Err::<usize, JadeErr>(JadeErr::database(&e))
+    };
+    for (username, secret) in &legacy {
+        let encrypted_secret: String = match encrypt_totp_secret(keys, secret) {
+            Ok(encrypted_secret) => encrypted_secret,
+            Err(e) => return Err::<usize, JadeErr>(e)
+        };
+        let _update_op: () = match sqlx::query!(
+            "UPDATE users SET totp_secret = $1 WHERE username = $2 AND totp_secret = $3",
+            encrypted_secret,
+            username,
+            secret
+        )
+            .execute(pool)
+            .await
+        {
+            Ok(_feedback) => {},
+            Err(e) => return Err::<usize, JadeErr>(JadeErr::database(&e))
+        };
+    }
+    Ok(legacy.len())
+}
+
+/// Attempts to email a single-use password reset
+/// link to a user. The supplied email address must
+/// match the one stored for the user. To avoid
+/// revealing which accounts exist, a status code of
+/// 0 is returned whether or not a link was sent. If
+/// this operation fails, an error is returned.
+pub async fn request_password_reset(
+    payload: &ForgotPasswordPayload,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>,
+    smtp_server: &String,
+    instance_url: &String
+) -> Result<StatusResponse, JadeErr> {
+    let status: StatusResponse = StatusResponse{ status: 0 };
+    let user: JadeUser = match get_user_by_handle(&payload.username, pool).await {
+        Ok(user) => user,
+        Err(_e) => return Ok(status)
+    };
+    let email_matches: bool = check_user_email(&user, &payload.email, keys, pool).await.unwrap_or(false);
+    if email_matches {}
+    else {
+        return Ok(status);
+    }
+    let token: String = random_string(RESET_TOKEN_LENGTH);
+    let reset: PasswordReset = PasswordReset {
+        token_hash: hash_token_secret(&token),
+        username: user.username.clone(),
+        created_at: get_time(),
+        expires_at: time_from_now(RESET_TOKEN_LIFETIME_SECS),
+        used_at: None
+    };
+    let _insert_op = match sqlx::query!(
+        "INSERT INTO password_resets (token_hash, username, created_at, expires_at) VALUES ($1, $2, $3, $4)",
+        reset.token_hash,
+        reset.username,
+        reset.created_at,
+        reset.expires_at
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let email_sub: String = format!("Reset your password, {}.", &user.username);
+    let from_addr: String = format!("Jade <noreply@{}>", smtp_server);
+    let to_addr: String = format!("{} <{}>", &user.username, &payload.email);
+    let message: String = format!(
+        "Somebody asked to reset the password of your Jade account. If this was you, send the following token to {}/user/password/reset within the next hour: {}\n\nIf this was not you, you can ignore this email.",
+        instance_url,
+        token
+    );
+    let send_res: bool = match send_email(&from_addr, &to_addr, &email_sub, &message, smtp_server).await {
+        Ok(send_res) => send_res,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    if send_res {
+        Ok(status)
+    }
+    else {
+        let e: String = "Could not send password reset email.".to_string();
+        Err::<StatusResponse, JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
+
+/// Attempts to revoke every API token, refresh
+/// token and browser session of a user. If this
+/// operation fails, an error is returned.
+pub async fn revoke_user_credentials(
+    username: &String,
+    pool: &Pool<Postgres>
+) -> Result<(), JadeErr> {
+    let _revoke_op: () = match sqlx::query!(
+        "UPDATE api_tokens SET is_active = $1, revoked_at = $2 WHERE username = $3 AND is_active = $4",
+        false,
+        get_time(),
+        username,
+        true
+    )
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+    };
+    let _refresh_op: () = match sqlx::query!("UPDATE oauth_refresh_tokens SET is_active = $1 WHERE username = $2", false, username)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+    };
+    let _session_op: () = match sqlx::query!("DELETE FROM sessions WHERE username = $1", username)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+    };
+    Ok(())
+}
+
+/// Attempts to set a new password with a
+/// password reset token. The token can only be
+/// used once and all of the user's API tokens
+/// and sessions are revoked. Users with two-factor
+/// authentication enabled must also supply a current
+/// TOTP code or an unused recovery code. If this
+/// operation succeeds, an instance of the "StatusResponse"
+/// structure is returned with a status code of 0.
+/// If this operation fails, an error is returned.
+pub async fn reset_password(
+    payload: &ResetPasswordPayload,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<StatusResponse, JadeErr> {
+    let token_hash: String = hash_token_secret(&payload.token);
+    let pending: Option<PasswordReset> = match sqlx::query_as!(
+        PasswordReset,
+        "SELECT * FROM password_resets WHERE token_hash = $1 AND used_at IS NULL",
+        token_hash
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(pending) => pending,
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let pending: PasswordReset = match pending {
+        Some(pending) => pending,
+        None => {
+            let e: String = "The password reset token is invalid.".to_string();
+            return Err::<StatusResponse, JadeErr>(JadeErr::not_found(&e.to_string()))
+        }
+    };
+    let expired: bool = match is_past(&pending.expires_at) {
+        Ok(expired) => expired,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    if expired {
+        let e: String = "The password reset token has expired.".to_string();
+        return Err::<StatusResponse, JadeErr>(JadeErr::validation(&e.to_string()))
+    }
+    let user: JadeUser = match get_user_by_handle(&pending.username, pool).await {
+        Ok(user) => user,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let _factor_op: () = match verify_second_factor(&user, &payload.totp_code, keys, pool).await {
+        Ok(_factor_op) => _factor_op,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let reset: Option<PasswordReset> = match sqlx::query_as!(
+        PasswordReset,
+        "UPDATE password_resets SET used_at = $1 WHERE token_hash = $2 AND used_at IS NULL RETURNING *",
+        get_time(),
+        token_hash
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(reset) => reset,
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let reset: PasswordReset = match reset {
+        Some(reset) => reset,
+        None => {
+            let e: String = "The password reset token is invalid.".to_string();
+            return Err::<StatusResponse, JadeErr>(JadeErr::not_found(&e.to_string()))
+        }
+    };
+    let hashed_pwd: String = match hash_password(&payload.new_password){
+        Ok(hashed_pwd) => hashed_pwd,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let _update_op: () = match sqlx::query!("UPDATE users SET pwd = $1 WHERE username = $2", hashed_pwd, reset.username)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let _wipe_op: () = match sqlx::query!("DELETE FROM password_resets WHERE username = $1 AND used_at IS NULL", reset.username)
+        .execute(pool)
+        .await
+    {
+        Ok(_feedback) => {},
+        Err(e) => return Err::<StatusResponse, JadeErr>(JadeErr::database(&e))
+    };
+    let _revoke_op: () = match revoke_user_credentials(&reset.username, pool).await {
+        Ok(_revoke_op) => _revoke_op,
+        Err(e) => return Err::<StatusResponse, JadeErr>(e)
+    };
+    let status: StatusResponse = StatusResponse{ status: 0 };
+    Ok(status)
+}
+
+/// Attempts to check a password a user has
+/// supplied against their stored password hash.
+/// Hashes that are not Argon2id hashes yet are
+/// replaced once the password has been checked.
+/// If this operation fails, an error is returned.
+pub async fn check_user_password(
+    user: &JadeUser,
+    password: &String,
+    pool: &Pool<Postgres>
+) -> Result<bool, JadeErr> {
+    let is_valid: bool = match verify_password(password, &user.pwd) {
+        Ok(is_valid) => is_valid,
+        Err(e) => return Err::<bool, JadeErr>(e)
+    };
+    if is_valid && needs_rehash(&user.pwd) {
+        let rehashed: String = match hash_password(password) {
+            Ok(rehashed) => rehashed,
+            Err(e) => return Err::<bool, JadeErr>(e)
+        };
+        let _update_op: () = match sqlx::query!("UPDATE users SET pwd = $1 WHERE username = $2", rehashed, user.username)
+            .execute(pool)
+            .await
+        {
+            Ok(_feedback) => {},
+            Err(e) => return Err::<bool, JadeErr>(JadeErr::database(&e))
+        };
+    }
+    Ok(is_valid)
+}
+
+/// Attempts to check an email address a user
+/// has supplied against their stored address.
+/// Addresses stored as bcrypt hashes by earlier
+/// versions of Jade are replaced with the encrypted
+/// address once it has been checked. If this
+/// operation fails, an error is returned.
+pub async fn check_user_email(
+    user: &JadeUser,
+    email: &String,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<bool, JadeErr> {
+    let is_valid: bool = match email_matches(keys, email, &user.email) {
+        Ok(is_valid) => is_valid,
+        Err(e) => return Err::<bool, JadeErr>(e)
+    };
+    if is_valid && !is_encrypted_email(&user.email) {
+        let encrypted: String = match encrypt_email(keys, email) {
+            Ok(encrypted) => encrypted,
+            Err(e) => return Err::<bool, JadeErr>(e)
+        };
+        let lookup: String = match email_lookup(keys, email) {
+            Ok(lookup) => lookup,
+            Err(e) => return Err::<bool, JadeErr>(e)
+        };
+        let _update_op: () = match sqlx::query!(
+            "UPDATE users SET email = $1, email_lookup = $2 WHERE username = $3",
+            encrypted,
+            lookup,
+            user.username
+        )
+            .execute(pool)
+            .await
+        {
+            Ok(_feedback) => {},
+            Err(e) => return Err::<bool, JadeErr>(JadeErr::database(&e))
+        };
+    }
+    Ok(is_valid)
+}
+
+/// Attempts to fetch the user with the given
+/// email address from the database. If this
+/// operation succeeds, an instance of the
+/// "JadeUser" structure is returned. If no user
+/// has this address or this operation fails, an
+/// error is returned.
+pub async fn get_user_by_email(
+    email: &String,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<JadeUser, JadeErr> {
+    let lookup: String = match email_lookup(keys, email) {
+        Ok(lookup) => lookup,
+        Err(e) => return Err::<JadeUser, JadeErr>(e)
+    };
+    match sqlx::query_as!(JadeUser, "SELECT * FROM users WHERE email_lookup = $1", lookup)
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => {
+            let e: String = "No user with this email address exists.".to_string();
+            Err::<JadeUser, JadeErr>(JadeErr::not_found(&e.to_string()))
+        },
+        Err(e) => Err::<JadeUser, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to check that no user has the given
+/// email address yet. Addresses stored as bcrypt
+/// hashes by earlier versions of Jade have no lookup
+/// hash, so the address is checked against each of
+/// them as well. There are fewer of them every time
+/// one of these users supplies their address. If the
+/// address is taken or this operation fails, an error
+/// is returned.
+pub async fn require_free_email(
+    email: &String,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<(), JadeErr> {
+    let e: String = "This email address is already in use.".to_string();
+    match get_user_by_email(email, keys, pool).await {
+        Ok(_user) => return Err::<(), JadeErr>(JadeErr::conflict(&e.to_string())),
+        Err(lookup_err) if lookup_err.kind == JadeErrKind::NotFound => {},
+        Err(lookup_err) => return Err::<(), JadeErr>(lookup_err)
+    };
+    let legacy: Vec<String> = match sqlx::query_scalar!("SELECT email FROM users WHERE email_lookup IS NULL AND email LIKE '$2%'")
+        .fetch_all(pool)
+        .await
+    {
+        Ok(legacy) => legacy,
+        Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+    };
+    for stored in legacy {
+        let taken: bool = match email_matches(keys, email, &stored) {
+            Ok(taken) => taken,
+            Err(e) => return Err::<(), JadeErr>(e)
+        };
+        if taken {
+            return Err::<(), JadeErr>(JadeErr::conflict(&e.to_string()))
+        }
+    }
+    Ok(())
+}
+
+/// Attempts to count the email addresses
+/// stored as bcrypt hashes by earlier versions
+/// of Jade. If this operation fails, an error
+/// is returned.
+pub async fn count_legacy_emails(
+    pool: &Pool<Postgres>
+) -> Result<i64, JadeErr> {
+    match sqlx::query_scalar!("SELECT COUNT(*) FROM users WHERE email_lookup IS NULL")
+        .fetch_one(pool)
+        .await
+    {
+        Ok(count) => Ok(count.unwrap_or(0)),
+        Err(e) => Err::<i64, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to check whether a username is
+/// taken. Usernames are compared without regard
+/// to case. If this operation fails, an error
+/// is returned.
+pub async fn username_taken(
+    username: &String,
+    pool: &Pool<Postgres>
+) -> Result<bool, JadeErr> {
+    match sqlx::query_scalar!("SELECT EXISTS(SELECT 1 FROM users WHERE LOWER(username) = LOWER($1))", username)
+        .fetch_one(pool)
+        .await
+    {
+        Ok(taken) => Ok(taken.unwrap_or(false)),
+        Err(e) => Err::<bool, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to check the payload for creating
+/// a new user. The syntax of the username and the
+/// email address is checked and neither may belong
+/// to another user. If this operation succeeds, all
+/// problems found are returned. If this operation
+/// fails, an error is returned.
+pub async fn check_new_user(
+    payload: &CreateUserPayload,
+    keys: &EmailKeys,
+    pool: &Pool<Postgres>
+) -> Result<Vec<FieldError>, JadeErr> {
+    let mut problems: Vec<FieldError> = Vec::new();
+    match validate_username(&payload.username) {
+        Some(problem) => problems.push(problem),
+        None => {
+            let taken: bool = match username_taken(&payload.username, pool).await {
+                Ok(taken) => taken,
+                Err(e) => return Err::<Vec<FieldError>, JadeErr>(e)
+            };
+            if taken {
+                problems.push(field_error("username", "taken", "This username is already taken."));
+            }
+        }
+    };
+    match validate_email("email", &payload.email) {
+        Some(problem) => problems.push(problem),
+        None => {
+            if get_user_by_email(&payload.email, keys, pool).await.is_ok() {
+                problems.push(field_error("email", "taken", "This email address is already in use."));
+            }
+        }
+    };
+    if payload.password.is_empty() {
+        problems.push(field_error("password", "missing", "A password is required."));
+    }
+    Ok(problems)
+}
+
+/// Attempts to fetch every mood a user has
+/// ever set, oldest first. If this operation
+/// fails, an error is returned.
+pub async fn get_mood_history(
+    username: &String,
+    pool: &Pool<Postgres>
+) -> Result<Vec<JadeMood>, JadeErr> {
+    match sqlx::query_as!(JadeMood, "SELECT * FROM moods WHERE username = $1 ORDER BY created_at ASC", username)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(moods) => Ok(moods),
+        Err(e) => Err::<Vec<JadeMood>, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to fetch every API token a user
+/// has ever created, including revoked ones.
+/// Only hashes of the secrets are stored, so
+/// no secrets are returned. If this operation
+/// fails, an error is returned.
+pub async fn get_token_history(
+    username: &String,
+    pool: &Pool<Postgres>
+) -> Result<Vec<APIToken>, JadeErr> {
+    match sqlx::query_as!(APIToken, "SELECT * FROM api_tokens WHERE username = $1 ORDER BY created_at ASC", username)
+        .fetch_all(pool)
+        .await
+    {
+        Ok(tokens) => Ok(tokens),
+        Err(e) => Err::<Vec<APIToken>, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to fetch every file a user has
+/// uploaded. Files without contents are returned
+/// with empty contents. If this operation fails,
+/// an error is returned.
+pub async fn get_user_files(
+    username: &String,
+    pool: &Pool<Postgres>
+) -> Result<Vec<JadeUserFile>, JadeErr> {
+    match sqlx::query_as!(
+        JadeUserFile,
+        "SELECT file_id, username, file_name, COALESCE(data, ''::bytea) AS \"data!\" FROM user_files WHERE username = $1",
+        username
+    )
+        .fetch_all(pool)
+        .await
+    {
+        Ok(files) => Ok(files),
+        Err(e) => Err::<Vec<JadeUserFile>, JadeErr>(JadeErr::database(&e))
+    }
+}
+
+/// Attempts to fetch the file with the given ID
+/// that the given user has uploaded. If this operation
+/// succeeds, the file is returned. If this operation
+/// fails, an error is returned.
+pub async fn get_user_file(
+    username: &String,
+    file_id: &String,
+    pool: &Pool<Postgres>
+) -> Result<JadeUserFile, JadeErr> {
+    let file: Option<JadeUserFile> = match sqlx::query_as!(
+        JadeUserFile,
+        "SELECT file_id, username, file_name, COALESCE(data, ''::bytea) AS \"data!\" FROM user_files WHERE username = $1 AND file_id = $2",
+        username,
+        file_id
+    )
+        .fetch_optional(pool)
+        .await
+    {
+        Ok(file) => file,
+        Err(e) => return Err::<JadeUserFile, JadeErr>(JadeErr::database(&e))
+    };
+    match file {
+        Some(file) => Ok(file),
+        None => {
+            let e: String = format!("File \"{}\" does not exist.", file_id);
+            Err::<JadeUserFile, JadeErr>(JadeErr::not_found(&e.to_string()))
+        }
+    }
+}
+
+/// Attempts to write moods and files read from
+/// a data export into the given user's account.
+/// Moods keep their original timestamps. Everything
+/// is written in a single transaction, so nothing is
+/// imported if any write fails. Data can only be
+/// imported into accounts without moods or files.
+/// If this operation fails, an error is returned.
+pub async fn store_imported_data(
+    user: &JadeUser,
+    moods: &Vec<JadeMood>,
+    files: &Vec<JadeUserFile>,
+    pool: &Pool<Postgres>
+) -> Result<(), JadeErr> {
+    let mut tx: Transaction<'_, Postgres> = match pool.begin().await {
+        Ok(tx) => tx,
+        Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+    };
+    let has_content: bool = match sqlx::query_scalar!(
+        "SELECT EXISTS (SELECT 1 FROM moods WHERE username = $1) OR EXISTS (SELECT 1 FROM user_files WHERE username = $1) AS \"has_content!\"",
+        user.username
+    )
+        .fetch_one(&mut *tx)
+        .await
+    {
+        Ok(has_content) => has_content,
+        Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+    };
+    if has_content {
+        let e: String = format!("User \"{}\" already has moods or files. Data can only be imported into new accounts.", &user.username);
+        return Err::<(), JadeErr>(JadeErr::conflict(&e.to_string()))
+    }
+    for mood in moods {
+        match sqlx::query!(
+            "INSERT INTO moods (username, is_active, mood, created_at) VALUES ($1, $2, $3, $4)",
+            user.username,
+            mood.is_active,
+            mood.mood,
+            mood.created_at
+        )
+            .execute(&mut *tx)
+            .await
+        {
+            Ok(_feedback) => {},
+            Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+        };
+    }
+    for file in files {
+        match sqlx::query!(
+            "INSERT INTO user_files (file_id, username, file_name, data, uploaded_at) VALUES ($1, $2, $3, $4, $5)",
+            file.file_id,
+            user.username,
+            file.file_name,
+            file.data,
+            get_time()
+        )
+            .execute(&mut *tx)
+            .await
+        {
+            Ok(_feedback) => {},
+            Err(e) => return Err::<(), JadeErr>(JadeErr::database(&e))
+        };
+    }
+    match tx.commit().await {
+        Ok(_feedback) => Ok(()),
+        Err(e) => Err::<(), JadeErr>(JadeErr::database(&e))
     }
 }
\ No newline at end of file
@@ -0,0 +1,138 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "APIToken"
+/// structure for explicit
+/// typing.
+use super::units::APIToken;
+
+/// The scope for setting
+/// and deleting moods.
+pub const MOOD_WRITE: &str = "mood:write";
+
+/// The scope for reading
+/// uploaded files.
+pub const FILES_READ: &str = "files:read";
+
+/// The scope for uploading
+/// files.
+pub const FILES_WRITE: &str = "files:write";
+
+/// The scope for changing
+/// the account's password.
+pub const ACCOUNT_PASSWORD: &str = "account:password";
+
+/// The scope for changing
+/// the account's email address.
+pub const ACCOUNT_EMAIL: &str = "account:email";
+
+/// The scope for deleting
+/// the account.
+pub const ACCOUNT_DELETE: &str = "account:delete";
+
+/// The scope for enrolling,
+/// confirming, and disabling
+/// two-factor authentication.
+pub const ACCOUNT_TWO_FACTOR: &str = "account:2fa";
+
+/// The scope for exporting
+/// all of the account's data.
+pub const ACCOUNT_EXPORT: &str = "account:export";
+
+/// The scope for importing
+/// a data export into the
+/// account.
+pub const ACCOUNT_IMPORT: &str = "account:import";
+
+/// All scopes an API token
+/// can be granted.
+pub const ALL_SCOPES: [&str; 9] = [
+    MOOD_WRITE,
+    FILES_READ,
+    FILES_WRITE,
+    ACCOUNT_PASSWORD,
+    ACCOUNT_EMAIL,
+    ACCOUNT_DELETE,
+    ACCOUNT_TWO_FACTOR,
+    ACCOUNT_EXPORT,
+    ACCOUNT_IMPORT
+];
+
+/// Attempts to check that all requested
+/// scopes are known scopes. If this operation
+/// fails, an error is returned.
+pub fn validate_scopes(scopes: &Vec<String>) -> Result<(), JadeErr> {
+    for scope in scopes {
+        if ALL_SCOPES.contains(&scope.as_str()) {}
+        else {
+            let e: String = format!("The scope \"{}\" does not exist.", scope);
+            return Err::<(), JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    }
+    Ok(())
+}
+
+/// An enum containing every action
+/// a token-authenticated route can
+/// carry out on behalf of a user.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JadeAction {
+    SetMood,
+    DeleteMood,
+    ReadFile,
+    UploadFile,
+    ChangePassword,
+    ChangeEmail,
+    DeleteUser,
+    ManageTwoFactor,
+    ExportData,
+    ImportData
+}
+
+/// Implementing functions
+/// for the "JadeAction" enum.
+impl JadeAction {
+
+    /// Returns the scope a token
+    /// must have been granted to
+    /// carry out this action.
+    pub fn required_scope(&self) -> &'static str {
+        match self {
+            JadeAction::SetMood => MOOD_WRITE,
+            JadeAction::DeleteMood => MOOD_WRITE,
+            JadeAction::ReadFile => FILES_READ,
+            JadeAction::UploadFile => FILES_WRITE,
+            JadeAction::ChangePassword => ACCOUNT_PASSWORD,
+            JadeAction::ChangeEmail => ACCOUNT_EMAIL,
+            JadeAction::DeleteUser => ACCOUNT_DELETE,
+            JadeAction::ManageTwoFactor => ACCOUNT_TWO_FACTOR,
+            JadeAction::ExportData => ACCOUNT_EXPORT,
+            JadeAction::ImportData => ACCOUNT_IMPORT
+        }
+    }
+}
+
+/// Checks whether an active API token
+/// has been granted the given scope.
+pub fn token_has_scope(token: &APIToken, scope: &str) -> bool {
+    token.is_active && token.scopes.iter().any(|granted| granted == scope)
+}
+
+/// Attempts to check whether an API token
+/// has been granted the given scope. If the
+/// token lacks the scope, an error is returned.
+pub fn require_scope(token: &APIToken, scope: &str) -> Result<(), JadeErr> {
+    if token_has_scope(token, scope) {
+        Ok(())
+    }
+    else {
+        let e: String = format!("User \"{}\" does not have the \"{}\" permission.", &token.username, scope);
+        Err::<(), JadeErr>(JadeErr::forbidden(&e.to_string()))
+    }
+}
\ No newline at end of file
@@ -0,0 +1,171 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "Method"
+/// structure to tell safe
+/// requests from state-changing
+/// ones.
+use actix_web::http::Method;
+
+/// Importing the "HttpRequest"
+/// structure to read request
+/// headers.
+use actix_web::HttpRequest;
+
+/// Importing the "Cookie"
+/// structure to build the
+/// session cookie.
+use actix_web::cookie::Cookie;
+
+/// Importing the "SameSite"
+/// enum to restrict when the
+/// session cookie is sent.
+use actix_web::cookie::SameSite;
+
+/// Importing the structure
+/// containing information on a
+/// browser session.
+use super::units::JadeSession;
+
+/// Importing the "form_urlencoded"
+/// module to encode the path a user
+/// returns to after logging in.
+use url::form_urlencoded;
+
+/// Importing the "Duration"
+/// structure to set the lifetime
+/// of the session cookie.
+use actix_web::cookie::time::Duration;
+
+/// The name of the cookie
+/// the session ID is stored in.
+pub const SESSION_COOKIE: &str = "jade_session";
+
+/// The name of the header the
+/// CSRF token of a session must
+/// be sent in.
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+/// The path of the login page.
+pub const LOGIN_PATH: &str = "/auth/login";
+
+/// The number of seconds a
+/// session stays valid for.
+pub const SESSION_LIFETIME_SECS: i64 = 604800;
+
+/// The number of characters
+/// in a session ID.
+pub const SESSION_ID_LENGTH: usize = 48;
+
+/// The number of characters
+/// in a CSRF token.
+pub const CSRF_TOKEN_LENGTH: usize = 32;
+
+/// Builds the cookie that stores the
+/// given session ID. The cookie cannot be
+/// read by scripts and is only sent over
+/// HTTPS. It is sent with same-site requests
+/// and with top-level navigations from other
+/// sites, so that applications can send users
+/// to "/oauth/authorize". Cross-site requests
+/// that change data still need the CSRF token.
+pub fn session_cookie(session_id: &String) -> Cookie<'static> {
+    Cookie::build(SESSION_COOKIE, session_id.to_owned())
+        .path("/")
+        .http_only(true)
+        .secure(true)
+        .same_site(SameSite::Lax)
+        .max_age(Duration::seconds(SESSION_LIFETIME_SECS))
+        .finish()
+}
+
+/// Builds a cookie that makes the
+/// browser discard the session cookie.
+pub fn removal_cookie() -> Cookie<'static> {
+    let mut cookie: Cookie<'static> = session_cookie(&String::new());
+    cookie.make_removal();
+    cookie
+}
+
+/// Checks whether a request method
+/// only reads data.
+pub fn is_safe_method(method: &Method) -> bool {
+    method == Method::GET || method == Method::HEAD || method == Method::OPTIONS
+}
+
+/// Attempts to check that a state-changing
+/// request made with a session carries the
+/// session's CSRF token. Requests that only
+/// read data are let through. If the token
+/// is missing or wrong, an error is returned.
+pub fn check_csrf(req: &HttpRequest, session: &JadeSession) -> Result<(), JadeErr> {
+    if is_safe_method(req.method()) {
+        return Ok(());
+    }
+    let supplied: Option<&str> = req.headers()
+        .get(CSRF_HEADER)
+        .and_then(|header| header.to_str().ok());
+    match supplied {
+        Some(supplied) if supplied == session.csrf_token => Ok(()),
+        _ => {
+            let e: String = format!("The \"{}\" header is missing or invalid.", CSRF_HEADER);
+            Err::<(), JadeErr>(JadeErr::forbidden(&e.to_string()))
+        }
+    }
+}
+
+/// Returns the path a user is sent to
+/// after logging in on the login page.
+/// Only paths on this instance are allowed,
+/// so the login page cannot be used to send
+/// users to other sites. All other values
+/// are replaced with the root path.
+pub fn safe_return_to(return_to: &Option<String>) -> String {
+    match return_to {
+        Some(path) if path.starts_with('/') && !path.starts_with("//") && !path.contains('\\') => path.clone(),
+        _ => "/".to_string()
+    }
+}
+
+/// Builds the URL of the login page that
+/// sends the user back to the given path
+/// once they have logged in.
+pub fn login_url(return_to: &str) -> String {
+    let encoded: String = form_urlencoded::byte_serialize(return_to.as_bytes()).collect();
+    format!("{}?return_to={}", LOGIN_PATH, encoded)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn only_local_paths_are_returned_to() {
+        let table: [(Option<&str>, &str); 6] = [
+            (Some("/oauth/authorize?client_id=a"), "/oauth/authorize?client_id=a"),
+            (Some("//evil.example/"), "/"),
+            (Some("/\\evil.example/"), "/"),
+            (Some("https://evil.example/"), "/"),
+            (Some(""), "/"),
+            (None, "/")
+        ];
+        for (return_to, expected) in table {
+            assert_eq!(safe_return_to(&return_to.map(|path| path.to_string())), expected);
+        }
+    }
+
+    #[test]
+    fn login_urls_encode_the_return_path() {
+        assert_eq!(
+            login_url("/oauth/authorize?a=1&b=2"),
+            "/auth/login?return_to=%2Foauth%2Fauthorize%3Fa%3D1%26b%3D2"
+        );
+    }
+}
\ No newline at end of file
@@ -0,0 +1,317 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the
+/// "Pool" structure
+/// from the "sqlx" crate
+/// to make a pool for
+/// database connections.
+use sqlx::Pool;
+
+/// Importing the "Message"
+/// enum to match on messages
+/// sent over a websocket.
+use actix_ws::Message;
+
+/// Importing the "Session"
+/// structure to send messages
+/// over a websocket.
+use actix_ws::Session;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the function
+/// to delete a user's mood
+/// from the database.
+use super::rw::wipe_mood;
+
+/// Importing the "MessageStream"
+/// structure to receive messages
+/// over a websocket.
+use actix_ws::MessageStream;
+
+/// Importing the "HashSet"
+/// structure to store the
+/// watched usernames.
+use std::collections::HashSet;
+
+/// Importing the "Postgres"
+/// structure from the "sqlx"
+/// crate.
+use sqlx::postgres::Postgres;
+
+/// Importing the structure
+/// describing a change to a
+/// user's mood.
+use super::units::MoodEvent;
+
+/// Importing the "Receiver"
+/// structure to receive mood
+/// updates from the shared
+/// listener.
+use tokio::sync::broadcast::Receiver;
+
+/// Importing the "Duration"
+/// structure to space out checks
+/// of a connection's credentials.
+use std::time::Duration;
+
+/// Importing the function
+/// to wait for the next mood
+/// update.
+use super::events::next_mood_update;
+
+/// Importing the function
+/// to check that a connection's
+/// credentials are still valid.
+use super::auth::refresh_auth;
+
+/// Importing the structure
+/// to report the outcome of
+/// a websocket request.
+use super::units::SocketStatus;
+
+/// Importing the enum
+/// containing all requests
+/// a websocket client can send.
+use super::units::SocketRequest;
+
+/// Importing the function
+/// to create a new mood for
+/// a user.
+use super::rw::create_new_mood;
+
+/// Importing the function
+/// to retrieve a user by their
+/// username.
+use super::rw::get_user_by_handle;
+
+/// Importing the structure
+/// for operations on a user's
+/// moods.
+use super::units::MoodActionPayload;
+
+/// Importing the "APIToken"
+/// structure for explicit
+/// typing.
+use super::units::APIToken;
+
+/// Importing the "JadeUser"
+/// structure for explicit
+/// typing.
+use super::units::JadeUser;
+
+/// Importing the enum
+/// containing all actions that
+/// require authorization.
+use super::scopes::JadeAction;
+
+/// Importing the function
+/// to retrieve an active API
+/// token.
+use super::rw::get_api_token;
+
+/// Importing the structure
+/// containing the API token and
+/// user a request was authenticated
+/// with.
+use super::auth::AuthedUser;
+
+/// The number of seconds between
+/// checks that the credentials of an
+/// open websocket are still valid.
+pub const SOCKET_REFRESH_SECS: u64 = 60;
+
+/// A structure holding the
+/// state of a single websocket
+/// connection.
+pub struct SocketState {
+    pub auth: Option<AuthedUser>,
+    pub watched: HashSet<String>
+}
+
+/// Attempts to carry out a single request
+/// sent by a websocket client. If this operation
+/// succeeds, the name of the action carried out is
+/// returned. If this operation fails, an error is
+/// returned.
+pub async fn process_socket_request(
+    request: &SocketRequest,
+    state: &mut SocketState,
+    pool: &Pool<Postgres>
+) -> Result<String, JadeErr> {
+    if let SocketRequest::Authenticate { api_token } = request {
+        let token: APIToken = match get_api_token(api_token, pool).await {
+            Ok(token) => token,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
+        let user: JadeUser = match get_user_by_handle(&token.username, pool).await {
+            Ok(user) => user,
+            Err(e) => return Err::<String, JadeErr>(e)
+        };
+        state.auth = Some(AuthedUser { user: user, token: Some(token), session: None });
+        return Ok("authenticate".to_string());
+    }
+    let auth: AuthedUser = match &state.auth {
+        Some(auth) => match refresh_auth(auth, pool).await {
+            Ok(auth) => auth,
+            Err(e) => {
+                state.auth = None;
+                state.watched.clear();
+                return Err::<String, JadeErr>(e)
+            }
+        },
+        None => {
+            let e: String = "Please authenticate before sending any other requests.".to_string();
+            return Err::<String, JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    };
+    state.auth = Some(auth.clone());
+    match request {
+        SocketRequest::Authenticate { api_token: _ } => Ok("authenticate".to_string()),
+        SocketRequest::Subscribe { users } => {
+            for username in users {
+                let user = match get_user_by_handle(username, pool).await {
+                    Ok(user) => user,
+                    Err(e) => return Err::<String, JadeErr>(e)
+                };
+                state.watched.insert(user.username);
+            }
+            Ok("subscribe".to_string())
+        },
+        SocketRequest::Unsubscribe { users } => {
+            for username in users {
+                state.watched.remove(username);
+            }
+            Ok("unsubscribe".to_string())
+        },
+        SocketRequest::SetMood { mood } => {
+            let payload: MoodActionPayload = MoodActionPayload {
+                mood: mood.to_owned()
+            };
+            let _auth_check: () = match auth.authorize(JadeAction::SetMood) {
+                Ok(_auth_check) => _auth_check,
+                Err(e) => return Err::<String, JadeErr>(e)
+            };
+            let _new_mood = match create_new_mood(&payload, &auth.user, pool).await {
+                Ok(new_mood) => new_mood,
+                Err(e) => return Err::<String, JadeErr>(e)
+            };
+            Ok("set_mood".to_string())
+        },
+        SocketRequest::ClearMood => {
+            let _auth_check: () = match auth.authorize(JadeAction::DeleteMood) {
+                Ok(_auth_check) => _auth_check,
+                Err(e) => return Err::<String, JadeErr>(e)
+            };
+            let _status = match wipe_mood(&auth.user, pool).await {
+                Ok(status) => status,
+                Err(e) => return Err::<String, JadeErr>(e)
+            };
+            Ok("clear_mood".to_string())
+        }
+    }
+}
+
+/// Parses a message sent by a websocket
+/// client, carries it out, and returns
+/// the serialized status of the operation.
+pub async fn answer_socket_message(
+    text: &str,
+    state: &mut SocketState,
+    pool: &Pool<Postgres>
+) -> String {
+    let status: SocketStatus = match serde_json::from_str::<SocketRequest>(text) {
+        Ok(request) => match process_socket_request(&request, state, pool).await {
+            Ok(action) => SocketStatus { action: action, status: 0, details: None },
+            Err(e) => SocketStatus { action: "error".to_string(), status: 1, details: Some(e.to_string()) }
+        },
+        Err(e) => SocketStatus { action: "error".to_string(), status: 1, details: Some(e.to_string()) }
+    };
+    match serde_json::to_string(&status) {
+        Ok(reply) => reply,
+        Err(_e) => "{\"action\":\"error\",\"status\":1,\"details\":null}".to_string()
+    }
+}
+
+/// Runs a websocket connection until either side
+/// closes it. Requests sent by the client are answered
+/// in order and mood updates for watched users are
+/// forwarded from the shared mood listener as they are
+/// published. Connections opened with a valid API token
+/// start out authenticated. The credentials of the
+/// connection are checked again with every request and
+/// every few minutes, and the connection is closed once
+/// they are revoked or have expired.
+pub async fn run_mood_socket(
+    mut session: Session,
+    mut messages: MessageStream,
+    auth: Option<AuthedUser>,
+    mut updates: Receiver<String>,
+    pool: Pool<Postgres>
+) {
+    let mut state: SocketState = SocketState {
+        auth: auth,
+        watched: HashSet::new()
+    };
+    let mut refresh_ticker = tokio::time::interval(Duration::from_secs(SOCKET_REFRESH_SECS));
+    loop {
+        tokio::select! {
+            message = messages.recv() => {
+                match message {
+                    Some(Ok(Message::Text(text))) => {
+                        let reply: String = answer_socket_message(&text, &mut state, &pool).await;
+                        if session.text(reply).await.is_err() {
+                            return;
+                        }
+                    },
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    },
+                    Some(Ok(Message::Close(reason))) => {
+                        let _closed = session.close(reason).await;
+                        return;
+                    },
+                    Some(Ok(_other)) => {},
+                    Some(Err(_e)) => break,
+                    None => break
+                }
+            },
+            update = next_mood_update(&mut updates) => {
+                match update {
+                    Some(payload) => {
+                        if let Ok(event) = serde_json::from_str::<MoodEvent>(&payload) {
+                            if state.watched.contains(&event.username) &&
+                               session.text(payload.clone()).await.is_err()
+                            {
+                                return;
+                            }
+                        }
+                    },
+                    None => break
+                }
+            },
+            _tick = refresh_ticker.tick() => {
+                if let Some(auth) = &state.auth {
+                    match refresh_auth(auth, &pool).await {
+                        Ok(auth) => state.auth = Some(auth),
+                        Err(e) => {
+                            let status: SocketStatus = SocketStatus { action: "error".to_string(), status: 1, details: Some(e.to_string()) };
+                            if let Ok(reply) = serde_json::to_string(&status) {
+                                let _sent = session.text(reply).await;
+                            }
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    let _closed = session.close(None).await;
+}
\ No newline at end of file
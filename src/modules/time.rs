@@ -9,6 +9,29 @@ Licensed under the FSL v1.
 /// time.
 use chrono::offset::Local;
 
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "DateTime"
+/// structure for explicit
+/// typing.
+use chrono::DateTime;
+
+/// Importing the "TimeZone"
+/// trait to attach the local
+/// timezone to a timestamp.
+use chrono::TimeZone;
+
+/// Importing the "NaiveDateTime"
+/// structure to parse stored
+/// timestamps.
+use chrono::NaiveDateTime;
+
+/// The format all timestamps
+/// Jade stores are written in.
+pub const TIME_FORMAT: &str = "%Y-%m-%d/%H:%M:%S";
+
 /// A generic structure to
 /// hold information on the current
 /// local time.
@@ -50,6 +73,15 @@ impl TimeNow{
     }
 }
 
+/// Implementing the "Default"
+/// trait for the "TimeNow"
+/// structure.
+impl Default for TimeNow {
+    fn default() -> TimeNow {
+        TimeNow::new()
+    }
+}
+
 /// Gets the current time 
 /// in the format "YYYY-MM-DD/HH:MM:SS".
 pub fn get_time() -> String {
@@ -63,4 +95,105 @@ pub fn get_time() -> String {
         time_now.minutes,
         time_now.seconds,
     )
+}
+
+/// Gets the time the given number
+/// of seconds from now in the format
+/// "YYYY-MM-DD/HH:MM:SS".
+pub fn time_from_now(seconds: i64) -> String {
+    let later: DateTime<Local> = Local::now() + chrono::Duration::seconds(seconds);
+    format!("{}", later.format(TIME_FORMAT))
+}
+
+/// Attempts to parse a timestamp in the
+/// format "YYYY-MM-DD/HH:MM:SS" as local
+/// time. If this operation fails, an error
+/// is returned.
+pub fn parse_time(timestamp: &String) -> Result<DateTime<Local>, JadeErr> {
+    let naive: NaiveDateTime = match NaiveDateTime::parse_from_str(timestamp, TIME_FORMAT){
+        Ok(naive) => naive,
+        Err(e) => {
+            let e: String = format!("Could not parse timestamp \"{}\": {}", timestamp, e);
+            return Err::<DateTime<Local>, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    };
+    match Local.from_local_datetime(&naive).earliest(){
+        Some(parsed) => Ok(parsed),
+        None => {
+            let e: String = format!("Could not parse timestamp \"{}\".", timestamp);
+            Err::<DateTime<Local>, JadeErr>(JadeErr::validation(&e.to_string()))
+        }
+    }
+}
+
+/// Attempts to convert a stored timestamp
+/// into the format used by RSS feeds.
+/// If this operation fails, an error
+/// is returned.
+pub fn to_rfc2822(timestamp: &String) -> Result<String, JadeErr> {
+    match parse_time(timestamp){
+        Ok(parsed) => Ok(parsed.to_rfc2822()),
+        Err(e) => Err::<String, JadeErr>(e)
+    }
+}
+
+/// Attempts to convert a stored timestamp
+/// into the format used by Atom feeds and
+/// JSON feeds. If this operation fails, an
+/// error is returned.
+pub fn to_rfc3339(timestamp: &String) -> Result<String, JadeErr> {
+    match parse_time(timestamp){
+        Ok(parsed) => Ok(parsed.to_rfc3339()),
+        Err(e) => Err::<String, JadeErr>(e)
+    }
+}
+
+/// Attempts to describe how long ago a stored
+/// timestamp was, for example "5 minutes ago".
+/// If this operation fails, an error is returned.
+pub fn time_ago(timestamp: &String) -> Result<String, JadeErr> {
+    let parsed: DateTime<Local> = match parse_time(timestamp){
+        Ok(parsed) => parsed,
+        Err(e) => return Err::<String, JadeErr>(e)
+    };
+    let seconds: i64 = Local::now().signed_duration_since(parsed).num_seconds();
+    let (amount, unit): (i64, &str) = if seconds < 60 {
+        return Ok("just now".to_string());
+    }
+    else if seconds < 3600 {
+        (seconds / 60, "minute")
+    }
+    else if seconds < 86400 {
+        (seconds / 3600, "hour")
+    }
+    else {
+        (seconds / 86400, "day")
+    };
+    if amount == 1 {
+        Ok(format!("1 {} ago", unit))
+    }
+    else {
+        Ok(format!("{} {}s ago", amount, unit))
+    }
+}
+
+/// Attempts to check whether a stored
+/// timestamp lies in the past. If this
+/// operation fails, an error is returned.
+pub fn is_past(timestamp: &String) -> Result<bool, JadeErr> {
+    match parse_time(timestamp){
+        Ok(parsed) => Ok(parsed <= Local::now()),
+        Err(e) => Err::<bool, JadeErr>(e)
+    }
+}
+
+/// Attempts to check whether a stored
+/// timestamp lies more than the given
+/// number of seconds in the past. If this
+/// operation fails, an error is returned.
+pub fn is_older_than(timestamp: &String, seconds: i64) -> Result<bool, JadeErr> {
+    match parse_time(timestamp){
+        Ok(parsed) => Ok(parsed + chrono::Duration::seconds(seconds) <= Local::now()),
+        Err(e) => Err::<bool, JadeErr>(e)
+    }
 }
\ No newline at end of file
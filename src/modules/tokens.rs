@@ -0,0 +1,149 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "Rng"
+/// trait to sample random
+/// characters.
+use rand::Rng;
+
+/// Importing the "Digest"
+/// trait to hash token
+/// secrets.
+use sha2::Digest;
+
+/// Importing the "Sha256"
+/// structure to hash token
+/// secrets.
+use sha2::Sha256;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "Alphanumeric"
+/// distribution to generate
+/// random tokens.
+use rand::distributions::Alphanumeric;
+
+/// The number of characters
+/// in the public prefix of
+/// an API token.
+pub const TOKEN_PREFIX_LENGTH: usize = 12;
+
+/// The number of characters
+/// in the secret part of
+/// an API token.
+pub const TOKEN_SECRET_LENGTH: usize = 40;
+
+/// The marker that tokens created
+/// before tokens had a prefix start
+/// with. These tokens were bcrypt hashes.
+pub const LEGACY_TOKEN_MARKER: &str = "$2";
+
+/// The prefix stored for tokens
+/// created before tokens had a
+/// prefix.
+pub const LEGACY_TOKEN_PREFIX: &str = "legacy-";
+
+/// The number of seconds after
+/// which another use of an API token
+/// from the same address is recorded.
+pub const TOKEN_USE_RECORD_SECS: i64 = 60;
+
+/// The number of characters
+/// in a password reset token.
+pub const RESET_TOKEN_LENGTH: usize = 48;
+
+/// The number of seconds a
+/// password reset token can
+/// be used for.
+pub const RESET_TOKEN_LIFETIME_SECS: i64 = 3600;
+
+/// The number of characters
+/// in an email verification
+/// token.
+pub const EMAIL_TOKEN_LENGTH: usize = 48;
+
+/// The number of seconds an
+/// email verification token can
+/// be used for.
+pub const EMAIL_TOKEN_LIFETIME_SECS: i64 = 86400;
+
+/// The number of seconds a user
+/// has to wait before another
+/// verification email is sent.
+pub const VERIFICATION_RESEND_COOLDOWN_SECS: i64 = 300;
+
+/// The number of characters
+/// in the tokens confirming or
+/// reverting a change of a user's
+/// email address.
+pub const EMAIL_CHANGE_TOKEN_LENGTH: usize = 48;
+
+/// The number of seconds the new
+/// email address can be confirmed
+/// for.
+pub const EMAIL_CHANGE_LIFETIME_SECS: i64 = 86400;
+
+/// The number of seconds a change
+/// of a user's email address can be
+/// reverted from the old address for.
+pub const EMAIL_REVERT_LIFETIME_SECS: i64 = 604800;
+
+/// The number of characters
+/// in the token that undoes the
+/// deletion of an account.
+pub const DELETION_UNDO_TOKEN_LENGTH: usize = 48;
+
+/// The number of characters
+/// in the ID of an uploaded
+/// file.
+pub const FILE_ID_LENGTH: usize = 32;
+
+/// Generates a random string of
+/// the given length containing only
+/// letters and digits.
+pub fn random_string(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
+
+/// Returns the hex-encoded SHA-256
+/// hash of the secret part of an
+/// API token. Only this hash is stored.
+pub fn hash_token_secret(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+/// Generates the prefix and the secret
+/// of a new API token. Clients receive
+/// the token in the form "prefix.secret".
+pub fn generate_api_token() -> (String, String) {
+    (random_string(TOKEN_PREFIX_LENGTH), random_string(TOKEN_SECRET_LENGTH))
+}
+
+/// Attempts to split an API token in the form
+/// "prefix.secret" into its prefix and its secret.
+/// Tokens created before tokens had a prefix are
+/// stored under the hash of the whole token, so that
+/// hash is returned as their prefix. If the token is
+/// malformed, an error is returned.
+pub fn split_api_token(api_token: &str) -> Result<(String, String), JadeErr> {
+    if api_token.starts_with(LEGACY_TOKEN_MARKER) {
+        return Ok((format!("{}{}", LEGACY_TOKEN_PREFIX, hash_token_secret(api_token)), api_token.to_string()));
+    }
+    match api_token.split_once('.') {
+        Some((prefix, secret)) if !prefix.is_empty() && !secret.is_empty() => {
+            Ok((prefix.to_string(), secret.to_string()))
+        },
+        _ => {
+            let e: String = "The supplied API token is malformed.".to_string();
+            Err::<(String, String), JadeErr>(JadeErr::unauthorized(&e.to_string()))
+        }
+    }
+}
\ No newline at end of file
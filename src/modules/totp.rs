@@ -0,0 +1,222 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "TOTP"
+/// structure to generate and
+/// check one-time codes.
+use totp_rs::TOTP;
+
+/// Importing the "Secret"
+/// enum to generate and decode
+/// TOTP secrets.
+use totp_rs::Secret;
+
+/// Importing this crate's
+/// error structure.
+use super::err::JadeErr;
+
+/// Importing the "Algorithm"
+/// enum to pick the hash
+/// authenticator apps expect.
+use totp_rs::Algorithm;
+
+/// Importing the function
+/// to generate random strings.
+use super::tokens::random_string;
+
+/// Importing the "SystemTime"
+/// structure to get the current
+/// Unix time.
+use std::time::SystemTime;
+
+/// Importing the Unix epoch
+/// to get the current Unix
+/// time.
+use std::time::UNIX_EPOCH;
+
+/// The issuer shown next
+/// to Jade accounts in
+/// authenticator apps.
+pub const TOTP_ISSUER: &str = "Jade";
+
+/// The number of digits
+/// in a TOTP code.
+pub const TOTP_DIGITS: usize = 6;
+
+/// The number of seconds
+/// a TOTP code is valid for.
+pub const TOTP_STEP_SECS: u64 = 30;
+
+/// The number of steps before
+/// and after the current one whose
+/// codes are accepted to allow for
+/// clock drift.
+pub const TOTP_SKEW_STEPS: u64 = 1;
+
+/// The number of recovery
+/// codes issued when two-factor
+/// authentication is enabled.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// The number of characters
+/// in a recovery code.
+pub const RECOVERY_CODE_LENGTH: usize = 12;
+
+/// Generates a new base32-encoded
+/// TOTP secret.
+pub fn generate_totp_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+/// Attempts to build the TOTP generator for
+/// the given user from their base32-encoded
+/// secret. The generator only accepts codes for
+/// the exact time it is given, clock drift is
+/// handled by "find_totp_step". If this operation
+/// fails, an error is returned.
+pub fn build_totp(secret: &String, username: &String) -> Result<TOTP, JadeErr> {
+    let bytes: Vec<u8> = match Secret::Encoded(secret.to_owned()).to_bytes() {
+        Ok(bytes) => bytes,
+        Err(e) => return Err::<TOTP, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    match TOTP::new(
+        Algorithm::SHA1,
+        TOTP_DIGITS,
+        0,
+        TOTP_STEP_SECS,
+        bytes,
+        Some(TOTP_ISSUER.to_string()),
+        username.to_owned()
+    ) {
+        Ok(totp) => Ok(totp),
+        Err(e) => Err::<TOTP, JadeErr>(JadeErr::new(&e.to_string()))
+    }
+}
+
+/// Attempts to build the "otpauth://" URI
+/// authenticator apps read from a QR code.
+/// If this operation fails, an error is
+/// returned.
+pub fn provisioning_uri(secret: &String, username: &String) -> Result<String, JadeErr> {
+    match build_totp(secret, username) {
+        Ok(totp) => Ok(totp.get_url()),
+        Err(e) => Err::<String, JadeErr>(e)
+    }
+}
+
+/// Attempts to find the time step a TOTP code
+/// belongs to at the given Unix time. Codes from
+/// "TOTP_SKEW_STEPS" steps before and after the
+/// current one are accepted. If the code matches,
+/// its step is returned, so that callers can refuse
+/// codes from steps that were already used. If this
+/// operation fails, an error is returned.
+pub fn find_totp_step(
+    secret: &String,
+    username: &String,
+    code: &String,
+    now: u64
+) -> Result<Option<u64>, JadeErr> {
+    let totp: TOTP = match build_totp(secret, username) {
+        Ok(totp) => totp,
+        Err(e) => return Err::<Option<u64>, JadeErr>(e)
+    };
+    let current: u64 = now / TOTP_STEP_SECS;
+    let first: u64 = current.saturating_sub(TOTP_SKEW_STEPS);
+    for step in first..=current + TOTP_SKEW_STEPS {
+        if totp.check(code.trim(), step * TOTP_STEP_SECS) {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+/// Attempts to find the time step a TOTP code
+/// belongs to at the current time. If this
+/// operation fails, an error is returned.
+pub fn current_totp_step(
+    secret: &String,
+    username: &String,
+    code: &String
+) -> Result<Option<u64>, JadeErr> {
+    let now: u64 = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(now) => now.as_secs(),
+        Err(e) => return Err::<Option<u64>, JadeErr>(JadeErr::new(&e.to_string()))
+    };
+    find_totp_step(secret, username, code, now)
+}
+
+/// Generates a fresh set of
+/// recovery codes.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_index| random_string(RECOVERY_CODE_LENGTH).to_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// The secret from the test vectors
+    /// of RFC 6238, encoded in base32.
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    fn code_at(time: u64) -> String {
+        build_totp(&SECRET.to_string(), &"alice".to_string()).unwrap().generate(time)
+    }
+
+    #[test]
+    fn codes_match_rfc_6238() {
+        assert_eq!(code_at(59), "287082");
+        assert_eq!(code_at(1111111109), "081804");
+        assert_eq!(code_at(1234567890), "005924");
+    }
+
+    #[test]
+    fn codes_are_found_within_the_skew() {
+        let now: u64 = 1234567890;
+        let current: u64 = now / TOTP_STEP_SECS;
+        for step in [current - 1, current, current + 1] {
+            let code: String = code_at(step * TOTP_STEP_SECS);
+            assert_eq!(
+                find_totp_step(&SECRET.to_string(), &"alice".to_string(), &code, now).unwrap(),
+                Some(step)
+            );
+        }
+    }
+
+    #[test]
+    fn codes_outside_the_skew_are_rejected() {
+        let now: u64 = 1234567890;
+        let current: u64 = now / TOTP_STEP_SECS;
+        for step in [current - 2, current + 2] {
+            let code: String = code_at(step * TOTP_STEP_SECS);
+            assert_eq!(
+                find_totp_step(&SECRET.to_string(), &"alice".to_string(), &code, now).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn codes_are_trimmed_and_wrong_codes_rejected() {
+        let now: u64 = 1234567890;
+        let code: String = format!(" {} ", code_at(now));
+        assert!(find_totp_step(&SECRET.to_string(), &"alice".to_string(), &code, now).unwrap().is_some());
+        assert!(find_totp_step(&SECRET.to_string(), &"alice".to_string(), &"000000".to_string(), now).unwrap().is_none());
+    }
+
+    #[test]
+    fn recovery_codes_are_unique() {
+        let codes: Vec<String> = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            assert_eq!(code.len(), RECOVERY_CODE_LENGTH);
+            assert_eq!(codes.iter().filter(|other| *other == code).count(), 1);
+        }
+    }
+}
\ No newline at end of file
@@ -43,15 +43,61 @@ use actix_multipart::form::MultipartForm;
 /// multipart file uploads.
 use actix_multipart::form::tempfile::TempFile;
 
+/// Importing the store
+/// rate-limiting counters
+/// are kept in.
+use super::ratelimit::LimitStore;
+
+/// Importing the structure
+/// containing the keys email
+/// addresses are protected with.
+use super::crypto::EmailKeys;
+
+/// Importing the "Sender"
+/// structure to hand out
+/// mood updates.
+use tokio::sync::broadcast::Sender;
+
+/// Importing the "IpAddr"
+/// enum for the addresses of
+/// trusted proxies.
+use std::net::IpAddr;
+
 /// A data structure containing information
-/// on a Jade User.
+/// on a Jade User. The email address is stored
+/// encrypted and can be looked up by its keyed
+/// hash. Users who asked for their account to
+/// be deleted are purged once "purge_at" has
+/// passed. The password hash and the email
+/// verification token are never serialized.
+/// The TOTP secret is only set while two-factor
+/// authentication is being set up or is enabled.
+/// A user is active once they have verified their
+/// email address.
 #[derive(Deserialize, Serialize, FromRow, Clone)]
 pub struct JadeUser {
     pub username: String,
     pub email: String,
+    #[serde(skip_serializing)]
     pub pwd: String,
+    #[serde(skip_serializing)]
     pub email_token: String,
-    pub is_active: bool
+    pub is_active: bool,
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    #[serde(skip_serializing)]
+    pub email_token_expires_at: Option<String>,
+    #[serde(skip_serializing)]
+    pub verification_resend_after: Option<String>,
+    #[serde(skip_serializing)]
+    pub email_lookup: Option<String>,
+    #[serde(skip_serializing)]
+    pub purge_at: Option<String>,
+    #[serde(skip_serializing)]
+    pub deletion_undo_hash: Option<String>,
+    #[serde(skip_serializing)]
+    pub totp_last_step: Option<i64>
 }
 
 /// A data structure a file
@@ -75,17 +121,47 @@ pub struct JadeMood {
 }
 
 /// A structure containing information
-/// on a created API token.
+/// on a created API token. The prefix
+/// identifies the token and only a hash
+/// of the token's secret is stored. The
+/// optional timestamps record when the
+/// token expires, when it was last used,
+/// and when it was revoked. The scopes
+/// name the operations the token may be
+/// used for. Tokens issued to a third-party
+/// application through OAuth record the ID
+/// of the application.
 #[derive(Deserialize, Serialize, FromRow, Clone)]
 pub struct APIToken {
     pub username: String,
-    pub token: String,
+    pub token_prefix: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
     pub created_at: String,
     pub is_active: bool,
-    pub can_change_pwd: bool,
-    pub can_set_mood: bool,
-    pub can_delete_user: bool,
-    pub can_change_email: bool
+    pub scopes: Vec<String>,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub last_used_ip: Option<String>,
+    pub revoked_at: Option<String>,
+    pub client_id: Option<String>
+}
+
+/// A structure containing
+/// information on a browser
+/// session. Only a hash of the
+/// session ID stored in the session
+/// cookie is kept. The CSRF token
+/// must accompany every state-changing
+/// request made with the session.
+#[derive(Deserialize, Serialize, FromRow, Clone)]
+pub struct JadeSession {
+    #[serde(skip_serializing)]
+    pub session_hash: String,
+    pub username: String,
+    pub csrf_token: String,
+    pub created_at: String,
+    pub expires_at: String
 }
 
 /// A structure containing
@@ -99,59 +175,471 @@ pub struct CreateUserPayload {
     pub password: String
 }
 
-/// A structure containing
-/// information to submit
-/// a payload for operations
-/// only requiring an API token.
-#[derive(Deserialize)]
-pub struct TokenOnlyPayload {
-    pub api_token: String,
-}
-
 /// A structure containing
 /// information to submit
 /// a payload for changing
 /// account information.
 #[derive(Deserialize)]
 pub struct ChangeEntityPayload {
-    pub new_entity: String,
-    pub api_token: String,
+    pub new_entity: String
 }
 
 /// A structure containing
 /// information to submit
 /// a payload for creating
-/// an API token.
+/// an API token with the given
+/// scopes. The optional expiry
+/// date is given in the format
+/// "YYYY-MM-DD/HH:MM:SS".
 #[derive(Deserialize)]
 pub struct CreateTokenPayload {
     pub username: String,
     pub password: String,
-    pub can_change_pwd: bool,
-    pub can_set_mood: bool,
-    pub can_delete_user: bool,
-    pub can_change_email: bool
+    pub scopes: Vec<String>,
+    pub expires_at: Option<String>,
+    pub totp_code: Option<String>
+}
+
+/// A structure containing
+/// a newly-created API token
+/// in the form "prefix.secret"
+/// and its details.
+#[derive(Serialize)]
+pub struct NewTokenResponse {
+    pub api_token: String,
+    #[serde(flatten)]
+    pub details: APIToken
 }
 
 /// A structure containing
 /// information to submit
 /// a payload for deleting
-/// an API token.
+/// an API token. The token
+/// is picked by its prefix.
 #[derive(Deserialize)]
 pub struct DeleteTokenPayload {
     pub username: String,
     pub password: String,
-    pub api_token: String,
+    pub token_prefix: String,
+}
+
+/// A structure containing
+/// information on a third-party
+/// application registered for OAuth.
+/// Only a hash of the client secret
+/// is stored.
+#[derive(Deserialize, Serialize, FromRow, Clone)]
+pub struct OAuthClient {
+    pub client_id: String,
+    #[serde(skip_serializing)]
+    pub client_secret_hash: Option<String>,
+    pub client_name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub website: Option<String>,
+    pub created_at: String,
+    pub is_confidential: bool
+}
+
+/// A structure containing
+/// information on an OAuth
+/// authorization code that has
+/// not been redeemed yet.
+#[derive(Deserialize, Serialize, FromRow, Clone)]
+pub struct OAuthCode {
+    pub code_hash: String,
+    pub client_id: String,
+    pub username: String,
+    pub redirect_uri: String,
+    pub scopes: Vec<String>,
+    pub code_challenge: String,
+    pub expires_at: String
+}
+
+/// A structure containing
+/// information on an OAuth refresh
+/// token and the API token it was
+/// issued alongside.
+#[derive(Deserialize, Serialize, FromRow, Clone)]
+pub struct OAuthRefreshToken {
+    pub token_hash: String,
+    pub client_id: String,
+    pub username: String,
+    pub token_prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub is_active: bool
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for registering
+/// an OAuth application.
+#[derive(Deserialize)]
+pub struct RegisterClientPayload {
+    pub client_name: String,
+    pub redirect_uris: Vec<String>,
+    pub scopes: Vec<String>,
+    pub website: Option<String>,
+    pub client_type: Option<String>
+}
+
+/// A structure containing
+/// a newly-registered OAuth
+/// application and its client
+/// secret. Public applications
+/// have no client secret.
+#[derive(Serialize)]
+pub struct NewClientResponse {
+    pub client_secret: Option<String>,
+    #[serde(flatten)]
+    pub details: OAuthClient
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for registering
+/// an application the way
+/// Mastodon clients do.
+#[derive(Deserialize)]
+pub struct MastodonAppPayload {
+    pub client_name: String,
+    pub redirect_uris: String,
+    pub scopes: Option<String>,
+    pub website: Option<String>
+}
+
+/// A structure containing
+/// a newly-registered application
+/// in the shape Mastodon clients
+/// expect.
+#[derive(Serialize)]
+pub struct MastodonAppResponse {
+    pub id: String,
+    pub name: String,
+    pub website: Option<String>,
+    pub redirect_uri: String,
+    pub client_id: String,
+    pub client_secret: String
+}
+
+/// A structure containing
+/// the query parameters of an
+/// OAuth authorization request.
+#[derive(Deserialize, Serialize)]
+pub struct AuthorizeQuery {
+    pub response_type: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: String,
+    pub code_challenge_method: String
+}
+
+/// A structure containing
+/// the fields of the form on
+/// the OAuth consent screen.
+#[derive(Deserialize)]
+pub struct ConsentForm {
+    #[serde(flatten)]
+    pub request: AuthorizeQuery,
+    pub csrf_token: String,
+    pub decision: String
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for exchanging
+/// an authorization code or
+/// a refresh token for an
+/// API token.
+#[derive(Deserialize)]
+pub struct OAuthTokenPayload {
+    pub grant_type: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub code_verifier: Option<String>,
+    pub refresh_token: Option<String>
+}
+
+/// A structure containing
+/// the API token and refresh
+/// token issued to an OAuth
+/// application.
+#[derive(Serialize)]
+pub struct OAuthTokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    pub refresh_token: String,
+    pub scope: String
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for changing
+/// a user's password.
+#[derive(Deserialize)]
+pub struct ChangePasswordPayload {
+    pub current_password: String,
+    pub new_password: String
+}
+
+/// A structure containing
+/// the profile of a user as
+/// it is written to a data
+/// export. The email address is
+/// only included if it can be
+/// read.
+#[derive(Deserialize, Serialize)]
+pub struct ExportProfile {
+    pub username: String,
+    pub email: Option<String>,
+    pub is_active: bool,
+    pub totp_enabled: bool,
+    pub exported_at: String
+}
+
+/// A structure containing
+/// information on an uploaded
+/// file in a data export and
+/// the path of its contents
+/// in the archive.
+#[derive(Deserialize, Serialize)]
+pub struct ExportedFile {
+    pub file_id: String,
+    pub file_name: String,
+    pub path: Option<String>
+}
+
+/// A structure to assist with
+/// uploading a data export to
+/// import via "actix-multipart".
+#[derive(MultipartForm, Debug)]
+pub struct ImportUploadForm {
+    #[multipart(limit = "100MB")]
+    pub archive: TempFile
+}
+
+/// A structure containing
+/// information on what was
+/// imported from a data export.
+#[derive(Deserialize, Serialize)]
+pub struct ImportSummary {
+    pub imported_from: String,
+    pub moods_imported: usize,
+    pub files_imported: usize
+}
+
+/// A structure containing
+/// the data read from a data
+/// export, ready to be stored
+/// for the importing user.
+pub struct ImportedData {
+    pub profile: ExportProfile,
+    pub moods: Vec<JadeMood>,
+    pub files: Vec<JadeUserFile>
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for deleting
+/// a user's account.
+#[derive(Deserialize)]
+pub struct DeleteAccountPayload {
+    pub password: String,
+    pub email: Option<String>
+}
+
+/// A structure containing
+/// the time a user's account
+/// will be purged at and a
+/// warning if the link to undo
+/// the deletion could not be sent.
+#[derive(Deserialize, Serialize)]
+pub struct DeletionScheduled {
+    pub purge_at: String,
+    pub warning: Option<String>
+}
+
+/// A structure containing
+/// a problem with a single
+/// field of a payload. The code
+/// is meant for programs and the
+/// message for people.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub code: String,
+    pub message: String
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for changing
+/// a user's email address.
+/// The current address must be
+/// supplied so that it can be
+/// told about the change.
+#[derive(Deserialize)]
+pub struct ChangeEmailPayload {
+    pub current_email: String,
+    pub new_email: String
+}
+
+/// A structure containing
+/// information on a requested
+/// change of a user's email
+/// address. Both addresses are
+/// stored encrypted together with
+/// their keyed hashes. The new address
+/// only replaces the old one once
+/// it has been confirmed, and the
+/// change can be reverted from the
+/// old address until the revert
+/// link expires. Only hashes of
+/// the tokens in the links are
+/// stored.
+#[derive(Deserialize, Serialize, FromRow, Clone)]
+pub struct EmailChange {
+    pub username: String,
+    pub old_email: String,
+    pub new_email: String,
+    pub confirm_token_hash: String,
+    pub revert_token_hash: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub confirmed_at: Option<String>,
+    pub revert_expires_at: String,
+    pub old_email_lookup: Option<String>,
+    pub new_email_lookup: Option<String>
+}
+
+/// A structure containing
+/// information on a password
+/// reset link. Only a hash of
+/// the token in the link is
+/// stored.
+#[derive(Deserialize, Serialize, FromRow, Clone)]
+pub struct PasswordReset {
+    pub token_hash: String,
+    pub username: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub used_at: Option<String>
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for requesting
+/// a new verification email.
+#[derive(Deserialize)]
+pub struct ResendVerificationPayload {
+    pub username: String,
+    pub email: String
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for requesting
+/// a password reset link.
+#[derive(Deserialize)]
+pub struct ForgotPasswordPayload {
+    pub username: String,
+    pub email: String
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for setting a
+/// new password with a reset
+/// token.
+#[derive(Deserialize)]
+pub struct ResetPasswordPayload {
+    pub token: String,
+    pub new_password: String,
+    pub totp_code: Option<String>
+}
+
+/// A structure containing
+/// information to submit
+/// a payload for logging
+/// in.
+#[derive(Deserialize)]
+pub struct LoginPayload {
+    pub username: String,
+    pub password: String,
+    pub totp_code: Option<String>
+}
+
+/// A structure containing
+/// the query parameters of the
+/// login page.
+#[derive(Deserialize)]
+pub struct LoginPageQuery {
+    pub return_to: Option<String>
+}
+
+/// A structure containing
+/// the fields of the form on
+/// the login page.
+#[derive(Deserialize)]
+pub struct LoginForm {
+    pub username: String,
+    pub password: String,
+    pub totp_code: Option<String>,
+    pub return_to: Option<String>
+}
+
+/// A structure containing
+/// the secret of a TOTP
+/// enrollment and the URI
+/// authenticator apps can
+/// scan as a QR code.
+#[derive(Serialize)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String
+}
+
+/// A structure containing
+/// information to submit
+/// a TOTP code or a recovery
+/// code.
+#[derive(Deserialize)]
+pub struct TotpCodePayload {
+    pub code: String
+}
+
+/// A structure containing
+/// the recovery codes issued
+/// when two-factor authentication
+/// is enabled.
+#[derive(Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>
+}
+
+/// A structure containing
+/// information returned
+/// after logging in.
+#[derive(Serialize)]
+pub struct LoginResponse {
+    pub username: String,
+    pub csrf_token: String,
+    pub expires_at: String
 }
 
 /// A structure containing
 /// information to submit
 /// a payload for creating
-/// or deleting a new Jade
-/// mood.
+/// a new Jade mood.
 #[derive(Deserialize)]
 pub struct MoodActionPayload {
-    pub api_token: String,
-    pub mood: String,
+    pub mood: String
 }
 
 /// A structure containing
@@ -207,8 +695,7 @@ pub struct EmailVerificationStatus {
 /// file.
 #[derive(Deserialize, Debug)]
 pub struct MetaData {
-    pub name: String,
-    pub api_token: String,
+    pub name: String
 }
 
 /// A structure to assist with
@@ -220,12 +707,151 @@ pub struct FileUploadForm {
     pub metadata: Json<MetaData>
 }
 
+/// A structure describing
+/// a change to a user's mood.
+/// The "mood" field is empty
+/// if the user's mood was deleted.
+#[derive(Deserialize, Serialize, Clone)]
+pub struct MoodEvent {
+    pub username: String,
+    pub event: String,
+    pub mood: Option<JadeMood>
+}
+
+/// A structure containing
+/// the query parameters for
+/// streaming mood updates.
+/// The "users" field holds a
+/// comma-separated list of
+/// usernames.
+#[derive(Deserialize)]
+pub struct MoodStreamQuery {
+    pub users: String
+}
+
+/// An enum containing all
+/// requests a client can send
+/// over a websocket. The variant
+/// is picked by the "action" key.
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum SocketRequest {
+    Authenticate { api_token: String },
+    Subscribe { users: Vec<String> },
+    Unsubscribe { users: Vec<String> },
+    SetMood { mood: String },
+    ClearMood
+}
+
+/// A structure containing
+/// information on whether
+/// a request sent over a
+/// websocket was successful.
+#[derive(Serialize)]
+pub struct SocketStatus {
+    pub action: String,
+    pub status: usize,
+    pub details: Option<String>
+}
+
+/// A structure containing
+/// a feed in the JSON Feed
+/// format.
+#[derive(Serialize)]
+pub struct JsonFeed {
+    pub version: String,
+    pub title: String,
+    pub home_page_url: String,
+    pub feed_url: String,
+    pub items: Vec<JsonFeedItem>
+}
+
+/// A structure containing
+/// a single entry of a feed
+/// in the JSON Feed format.
+#[derive(Serialize)]
+pub struct JsonFeedItem {
+    pub id: String,
+    pub url: String,
+    pub title: String,
+    pub content_text: String,
+    pub date_published: String
+}
+
+/// A structure containing
+/// the limits placed on the
+/// endpoints that accept a
+/// password, the name of the
+/// store the counters are kept
+/// in, either "memory" or
+/// "postgres", and the reverse
+/// proxies allowed to name the
+/// client's address.
+#[derive(Clone)]
+pub struct AuthLimitConfig {
+    pub ip_limit: i32,
+    pub window_secs: i64,
+    pub max_failures: i32,
+    pub lockout_secs: i64,
+    pub store: String,
+    pub trusted_proxies: Vec<IpAddr>
+}
+
+/// A structure containing
+/// the number of requests a
+/// single client address or API
+/// token may make to each group
+/// of routes per window.
+#[derive(Clone)]
+pub struct ApiLimitConfig {
+    pub default_limit: i32,
+    pub moods_limit: i32,
+    pub files_limit: i32,
+    pub window_secs: i64
+}
+
+/// A structure containing
+/// a single rate-limiting counter.
+/// Counters are reset once their
+/// window has passed. Each counter
+/// keeps the length of its own
+/// window, since counters for
+/// different limits are kept in
+/// the same store. Locked
+/// counters reject requests until
+/// the given UNIX timestamp.
+#[derive(Clone, FromRow)]
+pub struct LimitEntry {
+    pub key: String,
+    pub count: i32,
+    pub window_start: i64,
+    pub window_secs: i64,
+    pub locked_until: Option<i64>
+}
+
+/// A structure containing
+/// the outcome of counting a
+/// request against a limit.
+pub struct LimitDecision {
+    pub allowed: bool,
+    pub limit: i32,
+    pub remaining: i32,
+    pub reset_secs: i64
+}
+
 /// A structure containing
 /// a pool of database connections
 /// to make app data persist.
 pub struct AppData {
     pub pool: Pool<Postgres>,
-    pub smtp_server: String
+    pub smtp_server: String,
+    pub api_domain: String,
+    pub auth_limits: AuthLimitConfig,
+    pub api_limits: ApiLimitConfig,
+    pub limiter: LimitStore,
+    pub email_keys: EmailKeys,
+    pub deletion_grace_secs: i64,
+    pub mood_events: Sender<String>
 }
 
 /// Implementing generic
@@ -237,8 +863,36 @@ impl AppData{
     /// to create a new instance
     /// of the "AppData"
     /// structure.
-    pub fn new(pg_pool: &Pool<Postgres>, smtp_server: &String) -> AppData{
-        AppData { pool: pg_pool.to_owned(), smtp_server: smtp_server.to_owned() }
+    pub fn new(
+        pg_pool: &Pool<Postgres>,
+        smtp_server: &String,
+        api_domain: &String,
+        auth_limits: &AuthLimitConfig,
+        api_limits: &ApiLimitConfig,
+        limiter: &LimitStore,
+        email_keys: &EmailKeys,
+        deletion_grace_secs: &i64,
+        mood_events: &Sender<String>
+    ) -> AppData{
+        AppData {
+            pool: pg_pool.to_owned(),
+            smtp_server: smtp_server.to_owned(),
+            api_domain: api_domain.to_owned(),
+            auth_limits: auth_limits.to_owned(),
+            api_limits: api_limits.to_owned(),
+            limiter: limiter.to_owned(),
+            email_keys: email_keys.to_owned(),
+            deletion_grace_secs: deletion_grace_secs.to_owned(),
+            mood_events: mood_events.to_owned()
+        }
+    }
+
+    /// Implementing a method
+    /// to get the public URL
+    /// this instance of Jade
+    /// is reachable under.
+    pub fn instance_url(&self) -> String {
+        format!("https://{}", self.api_domain)
     }
 
 }
@@ -250,7 +904,12 @@ pub struct ConfigData{
     pub db_url: String,
     pub actix_host: String,
     pub actix_port: String,
-    pub smtp_server: String
+    pub smtp_server: String,
+    pub api_domain: String,
+    pub auth_limits: AuthLimitConfig,
+    pub api_limits: ApiLimitConfig,
+    pub email_key: String,
+    pub deletion_grace_secs: i64
 }
 
 /// Implementing generic
@@ -266,13 +925,23 @@ impl ConfigData{
         db_url: &String,
         actix_host: &String,
         actix_port: &String,
-        smtp_server: &String
+        smtp_server: &String,
+        api_domain: &String,
+        auth_limits: &AuthLimitConfig,
+        api_limits: &ApiLimitConfig,
+        email_key: &String,
+        deletion_grace_secs: &i64
     ) -> ConfigData {
         ConfigData {
             db_url: db_url.to_owned(),
             actix_host: actix_host.to_owned(),
             actix_port: actix_port.to_owned(),
-            smtp_server: smtp_server.to_owned()
+            smtp_server: smtp_server.to_owned(),
+            api_domain: api_domain.to_owned(),
+            auth_limits: auth_limits.to_owned(),
+            api_limits: api_limits.to_owned(),
+            email_key: email_key.to_owned(),
+            deletion_grace_secs: deletion_grace_secs.to_owned()
         }
     }
     
@@ -0,0 +1,195 @@
+/*
+Jade by Alyx Shang.
+Licensed under the FSL v1.
+*/
+
+/// Importing the "Address"
+/// structure to check email
+/// addresses.
+use lettre::Address;
+
+/// Importing the structure
+/// containing a problem with
+/// a single field of a payload.
+use super::units::FieldError;
+
+/// The smallest number of
+/// characters in a username.
+pub const USERNAME_MIN_LENGTH: usize = 3;
+
+/// The largest number of
+/// characters in a username.
+pub const USERNAME_MAX_LENGTH: usize = 30;
+
+/// The largest number of
+/// characters in an email
+/// address.
+pub const EMAIL_MAX_LENGTH: usize = 254;
+
+/// Usernames that cannot be registered
+/// because they clash with routes or
+/// could be mistaken for the instance.
+/// Usernames are compared without regard
+/// to case.
+pub const RESERVED_USERNAMES: [&str; 28] = [
+    ".well-known",
+    "about",
+    "admin",
+    "administrator",
+    "api",
+    "auth",
+    "email",
+    "files",
+    "help",
+    "inbox",
+    "jade",
+    "login",
+    "logout",
+    "mod",
+    "moderator",
+    "mood",
+    "moods",
+    "nodeinfo",
+    "oauth",
+    "outbox",
+    "root",
+    "settings",
+    "signup",
+    "support",
+    "system",
+    "token",
+    "tokens",
+    "users"
+];
+
+/// Builds the problem with
+/// the given field.
+pub fn field_error(field: &str, code: &str, message: &str) -> FieldError {
+    FieldError {
+        field: field.to_string(),
+        code: code.to_string(),
+        message: message.to_string()
+    }
+}
+
+/// Checks whether a username is
+/// reserved.
+pub fn is_reserved_username(username: &String) -> bool {
+    let lowered: String = username.to_lowercase();
+    RESERVED_USERNAMES.iter().any(|reserved| *reserved == lowered)
+}
+
+/// Checks the syntax of a username. Usernames
+/// are between 3 and 30 characters long, consist
+/// of ASCII letters, digits and underscores, and
+/// must not be reserved. If the username is
+/// not valid, the problem is returned.
+pub fn validate_username(username: &String) -> Option<FieldError> {
+    let length: usize = username.chars().count();
+    if length < USERNAME_MIN_LENGTH {
+        let message: String = format!("Usernames must be at least {} characters long.", USERNAME_MIN_LENGTH);
+        Some(field_error("username", "too_short", &message))
+    }
+    else if length > USERNAME_MAX_LENGTH {
+        let message: String = format!("Usernames must be at most {} characters long.", USERNAME_MAX_LENGTH);
+        Some(field_error("username", "too_long", &message))
+    }
+    else if is_reserved_username(username) {
+        Some(field_error("username", "reserved", "This username is reserved."))
+    }
+    else if username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        None
+    }
+    else {
+        Some(field_error("username", "invalid_characters", "Usernames may only contain letters, digits, and underscores."))
+    }
+}
+
+/// Checks that an email address is valid
+/// according to RFC 5321 and RFC 5322. The
+/// problem is reported for the field with the
+/// given name. If the address is not valid,
+/// the problem is returned.
+pub fn validate_email(field: &str, email: &String) -> Option<FieldError> {
+    let trimmed: &str = email.trim();
+    if trimmed.is_empty() {
+        Some(field_error(field, "missing", "An email address is required."))
+    }
+    else if trimmed.len() > EMAIL_MAX_LENGTH {
+        let message: String = format!("Email addresses must be at most {} characters long.", EMAIL_MAX_LENGTH);
+        Some(field_error(field, "too_long", &message))
+    }
+    else if trimmed.parse::<Address>().is_ok() {
+        None
+    }
+    else {
+        Some(field_error(field, "invalid", "This is not a valid email address."))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn username_problem(username: &str) -> Option<String> {
+        validate_username(&username.to_string()).map(|problem| problem.code)
+    }
+
+    fn email_problem(email: &str) -> Option<String> {
+        validate_email("email", &email.to_string()).map(|problem| problem.code)
+    }
+
+    #[test]
+    fn usernames_are_checked() {
+        let table: [(&str, Option<&str>); 9] = [
+            ("alice", None),
+            ("Alice_2", None),
+            ("abc", None),
+            ("ab", Some("too_short")),
+            ("a23456789012345678901234567890", None),
+            ("a234567890123456789012345678901", Some("too_long")),
+            ("alice.b", Some("invalid_characters")),
+            ("älice", Some("invalid_characters")),
+            ("al ice", Some("invalid_characters"))
+        ];
+        for (username, expected) in table {
+            assert_eq!(username_problem(username), expected.map(|code| code.to_string()), "{}", username);
+        }
+    }
+
+    #[test]
+    fn reserved_usernames_are_rejected_in_any_case() {
+        for username in ["admin", "ADMIN", "Users", "oauth"] {
+            assert_eq!(username_problem(username), Some("reserved".to_string()), "{}", username);
+        }
+        assert!(is_reserved_username(&".well-known".to_string()));
+        assert!(!is_reserved_username(&"admins".to_string()));
+    }
+
+    #[test]
+    fn email_addresses_are_checked() {
+        let long_local: String = format!("{}@example.com", "a".repeat(EMAIL_MAX_LENGTH));
+        let table: [(&str, Option<&str>); 7] = [
+            ("alice@example.com", None),
+            ("  alice@example.com  ", None),
+            ("alice+moods@example.co.uk", None),
+            ("", Some("missing")),
+            ("   ", Some("missing")),
+            ("alice", Some("invalid")),
+            ("alice@", Some("invalid"))
+        ];
+        for (email, expected) in table {
+            assert_eq!(email_problem(email), expected.map(|code| code.to_string()), "{:?}", email);
+        }
+        assert_eq!(email_problem(&long_local), Some("too_long".to_string()));
+    }
+
+    #[test]
+    fn problems_name_their_field() {
+        let problem: FieldError = validate_email("new_email", &"nope".to_string()).unwrap();
+        assert_eq!(problem.field, "new_email");
+        assert_eq!(validate_username(&"ab".to_string()).unwrap().field, "username");
+    }
+}
\ No newline at end of file